@@ -278,9 +278,9 @@ void CCallback::recruitHero(const CGObjectInstance *townOrTavern, const CGHeroIn
 	}
 }
 
-void CCallback::save( const std::string &fname )
+void CCallback::save( const std::string &fname, const std::string &replaceOnChain )
 {
-	cl->save(fname);
+	cl->save(fname, replaceOnChain);
 }
 
 
@@ -26,6 +26,7 @@
 #include "ClientCommandManager.h"
 #include "windows/CMessage.h"
 #include "renderSDL/SDL_Extensions.h"
+#include "rusty_bridge/lib.h"
 
 #include "../lib/filesystem/Filesystem.h"
 #include "../lib/filesystem/FileStream.h"
@@ -1072,6 +1073,8 @@ static void quitApplication()
 			CSH->endGameplay();
 	}
 
+	shutdown_connection();
+
 	GH.listInt.clear();
 	GH.objsToBlit.clear();
 
@@ -339,7 +339,7 @@ void CClient::serialize(BinaryDeserializer & h, const int version)
 	logNetwork->trace("Loaded client part of save %d ms", CSH->th->getDiff());
 }
 
-void CClient::save(const std::string & fname)
+void CClient::save(const std::string & fname, const std::string & replaceOnChain)
 {
 	if(gs->curB)
 	{
@@ -347,7 +347,7 @@ void CClient::save(const std::string & fname)
 		return;
 	}
 
-	SaveGame save_game(fname);
+	SaveGame save_game(fname, replaceOnChain);
 	sendRequest(&save_game, PlayerColor::NEUTRAL);
 
 	const std::vector<std::string> contentNames = {"heroClasses", "artifacts", "creatures", "factions", "objects", "heroes", "spells", "skills"};
@@ -424,7 +424,14 @@ void CClient::save(const std::string & fname)
 
 		rplayers.push_back(rust_player_state);
 	}
-	save_game_state(day, current_player, rplayers);
+	try
+	{
+		save_game_state(day, current_player, rplayers);
+	}
+	catch(const rust::Error & e)
+	{
+		logGlobal->warn("Can't save game state on chain: %s", e.what());
+	}
 
 	for(auto contentName : contentNames)
 	{
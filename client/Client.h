@@ -156,7 +156,7 @@ public:
 	void serialize(BinarySerializer & h, const int version);
 	void serialize(BinaryDeserializer & h, const int version);
 
-	void save(const std::string & fname);
+	void save(const std::string & fname, const std::string & replaceOnChain = "");
 	void endGame();
 
 	void initMapHandler();
@@ -19,6 +19,7 @@
 #include "../gui/CGuiHandler.h"
 #include "../widgets/Buttons.h"
 #include "../windows/InfoWindows.h"
+#include "../windows/GUIClasses.h"
 
 #include "../../CCallback.h"
 
@@ -28,6 +29,8 @@
 #include "../../lib/mapping/CMapInfo.h"
 #include "../../lib/mapping/CCampaignHandler.h"
 #include "../../lib/rmg/CMapGenOptions.h"
+#include "../../lib/CConfigHandler.h"
+#include "../../lib/VCMIDirs.h"
 
 #include "rusty_bridge/lib.h"
 
@@ -79,8 +82,9 @@ CLobbyScreen::CLobbyScreen(ESelectionScreen screenType)
 	{
 		tabOpt = std::make_shared<OptionsTab>();
 		buttonStart = std::make_shared<CButton>(Point(411, 535), "SCNRLOD.DEF", CGI->generaltexth->zelp[103], std::bind(&CLobbyScreen::startScenario, this, false), SDLK_l);
+		buttonChain = std::make_shared<CButton>(Point(411, 105), "GSPBUTT.DEF", CButton::tooltip("Saved games on chain"), std::bind(&CLobbyScreen::showSavesOnChain, this), SDLK_g);
+		buttonChain->addTextOverlay("Chain", FONT_SMALL);
 		initLobby();
-		load_all_from_chain();
 		break;
 	}
 	case ESelectionScreen::campaignList:
@@ -98,6 +102,97 @@ CLobbyScreen::CLobbyScreen(ESelectionScreen screenType)
 	}, SDLK_ESCAPE);
 }
 
+static RConflictPolicy chainLoadConflictPolicy()
+{
+	const std::string policy = settings["general"]["chainLoadConflicts"].String();
+	if(policy == "skip")
+		return RConflictPolicy::Skip;
+	if(policy == "overwrite")
+		return RConflictPolicy::Overwrite;
+	return RConflictPolicy::Rename;
+}
+
+static RSaveQuery chainSavesQuery()
+{
+	RSaveQuery query;
+	const std::string sortBy = settings["general"]["chainSavesSort"].String();
+	if(sortBy == "name")
+		query.sort_by = RSaveSort::Name;
+	else if(sortBy == "day")
+		query.sort_by = RSaveSort::Day;
+	else if(sortBy == "size")
+		query.sort_by = RSaveSort::Size;
+	else
+		query.sort_by = RSaveSort::Date;
+	// Newest, longest and biggest games first, names alphabetically
+	query.descending = query.sort_by != RSaveSort::Name;
+	return query;
+}
+
+void CLobbyScreen::showSavesOnChain()
+{
+	try
+	{
+		auto saves = list_saves_onchain(chainSavesQuery());
+		if(saves.empty())
+		{
+			CInfoWindow::showInfoDialog("There are no saved games on chain", CInfoWindow::TCompsInfo(), PlayerColor(1));
+			return;
+		}
+		std::vector<std::string> names;
+		std::vector<std::string> cids;
+		for(const auto & save : saves)
+		{
+			std::string name(save.name);
+			if(save.has_metadata)
+				name += " - " + std::string(save.metadata.map_name) + ", day " + std::to_string(save.metadata.day);
+			if(save.size)
+				name += " (" + std::to_string(save.size / 1024) + " KB)";
+			if(save.encrypted)
+				name += " [encrypted]";
+			names.push_back(name);
+			cids.push_back(std::string(save.cid));
+		}
+		GH.pushIntT<CObjectListWindow>(names, nullptr, "Saved games on chain", "Choose the game to load from chain", [this, cids](int index)
+		{
+			fetchSaveFromChain(cids.at(index));
+		});
+	}
+	catch(const rust::Error & e)
+	{
+		logGlobal->error("Can't list saved games on chain: %s", e.what());
+		CInfoWindow::showInfoDialog("Saved games on chain aren't listed: " + std::string(error_message(e.what())), CInfoWindow::TCompsInfo(), PlayerColor(1));
+	}
+}
+
+void CLobbyScreen::fetchSaveFromChain(const std::string & cid)
+{
+	const std::string targetDir = VCMIDirs::get().userSavePath().string();
+	try
+	{
+		auto loaded = fetch_save_from_chain(cid, targetDir, chainLoadConflictPolicy());
+		const std::string filename(loaded.filename);
+		logGlobal->info("Saved game %s is loaded from chain as %s", filename, std::string(loaded.saved_as));
+		switch(loaded.status)
+		{
+		case RLoadStatus::Renamed:
+			CInfoWindow::showInfoDialog(filename + ": a local save has the same name, loaded as " + std::string(loaded.saved_as), CInfoWindow::TCompsInfo(), PlayerColor(1));
+			break;
+		case RLoadStatus::Skipped:
+			CInfoWindow::showInfoDialog(filename + ": a local save has the same name, skipped", CInfoWindow::TCompsInfo(), PlayerColor(1));
+			break;
+		default:
+			break;
+		}
+		tabSel->toggleMode();
+	}
+	catch(const rust::Error & e)
+	{
+		logGlobal->error("Can't load saved game %s from chain: %s", cid, e.what());
+		CInfoWindow::showInfoDialog("Saved game isn't loaded from chain: " + std::string(error_message(e.what())), CInfoWindow::TCompsInfo(), PlayerColor(1));
+	}
+}
+
 CLobbyScreen::~CLobbyScreen()
 {
 	// TODO: For now we always destroy whole lobby when leaving bonus selection screen
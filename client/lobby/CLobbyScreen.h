@@ -17,6 +17,7 @@ class CLobbyScreen : public CSelectionBase
 {
 public:
 	std::shared_ptr<CButton> buttonChat;
+	std::shared_ptr<CButton> buttonChain;
 
 	CLobbyScreen(ESelectionScreen type);
 	~CLobbyScreen();
@@ -25,6 +26,8 @@ public:
 	void startScenario(bool allowOnlyAI = false);
 	void toggleMode(bool host);
 	void toggleChat();
+	void showSavesOnChain();
+	void fetchSaveFromChain(const std::string & cid);
 
 	void updateAfterStateChange();
 
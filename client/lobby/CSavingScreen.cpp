@@ -26,6 +26,8 @@
 #include "../../lib/mapping/CMapInfo.h"
 #include "../../lib/mapping/CMap.h"
 
+#include "rusty_bridge/lib.h"
+
 CSavingScreen::CSavingScreen()
 	: CSelectionBase(ESelectionScreen::saveGame)
 {
@@ -64,21 +66,56 @@ void CSavingScreen::changeSelection(std::shared_ptr<CMapInfo> to)
 	card->changeSelection();
 }
 
+/// IPFS CID of the save on chain named `name`, empty if there is none or the chain can't be reached
+static std::string savedOnChain(const std::string & name)
+{
+	try
+	{
+		RSaveQuery query;
+		query.text = name;
+		query.sort_by = RSaveSort::Name;
+		query.descending = false;
+		for(const auto & save : list_saves_onchain(query))
+		{
+			if(std::string(save.name) == name)
+				return std::string(save.cid);
+		}
+	}
+	catch(const rust::Error & e)
+	{
+		logGlobal->warn("Can't list saved games on chain: %s", e.what());
+	}
+	return "";
+}
+
 void CSavingScreen::saveGame()
 {
 	if(!(tabSel && tabSel->inputName && tabSel->inputName->getText().size()))
 		return;
 
-	std::string path = "Saves/" + tabSel->inputName->getText();
+	const std::string name = tabSel->inputName->getText();
+	std::string path = "Saves/" + name;
 
-	auto overWrite = [this, path]() -> void
+	auto save = [this, path](const std::string & replaceOnChain) -> void
 	{
 		Settings lastSave = settings.write["general"]["lastSave"];
 		lastSave->String() = path;
-		LOCPLINT->cb->save(path);
+		LOCPLINT->cb->save(path, replaceOnChain);
 		close();
 	};
 
+	// Saves on chain are kept unless the player chooses to replace the one with the same name
+	auto overWrite = [save, name]() -> void
+	{
+		const std::string cid = savedOnChain(name);
+		if(cid.empty())
+		{
+			save("");
+			return;
+		}
+		LOCPLINT->showYesNoDialog(name + " is saved on chain too. Replace it there?", [save, cid](){ save(cid); }, [save](){ save(""); });
+	};
+
 	if(CResourceHandler::get("local")->existsResource(ResourceID(path, EResType::CLIENT_SAVEGAME)))
 	{
 		std::string hlp = CGI->generaltexth->allTexts[493]; //%s exists. Overwrite?
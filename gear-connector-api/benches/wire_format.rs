@@ -0,0 +1,111 @@
+//! Compares JSON and SCALE encoding of the frames which carry a whole save archive,
+//! uploaded with `UploadChunk`s and downloaded with `DownloadedChunk`s.
+//!
+//! Run with `cargo bench --bench wire_format`.
+
+use bytes::BytesMut;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gear_connector_api::{
+    codec::{WireCodec, WireFormat},
+    transfer::{checksum, CHUNK_LEN},
+    CommandEnvelope, ReplyEnvelope, VcmiCommand, VcmiReply,
+};
+use parity_scale_codec::{Decode, Encode};
+use serde::{de::DeserializeOwned, Serialize};
+use std::pin::Pin;
+use tokio_serde::{Deserializer, Serializer};
+
+/// Size of the benchmarked save, a compressed save of a large map late in the game.
+const SAVE_LEN: usize = 12 * 1024 * 1024 + 345;
+
+/// Zipped saves are close to random data, so fill the archive with xorshift output.
+fn archive(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn upload_frames(save: &[u8]) -> Vec<CommandEnvelope> {
+    let checksum = checksum(save);
+    save.chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(i, chunk)| CommandEnvelope {
+            request_id: i as u64,
+            command: VcmiCommand::UploadChunk {
+                checksum,
+                offset: (i * CHUNK_LEN) as u64,
+                data: chunk.to_vec(),
+            },
+        })
+        .collect()
+}
+
+fn download_frames(save: &[u8]) -> Vec<ReplyEnvelope> {
+    save.chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(i, chunk)| ReplyEnvelope {
+            request_id: i as u64,
+            reply: VcmiReply::DownloadedChunk {
+                offset: (i * CHUNK_LEN) as u64,
+                data: chunk.to_vec(),
+            },
+        })
+        .collect()
+}
+
+/// Encodes and decodes every frame of the save in both formats.
+fn bench_transfer<T>(c: &mut Criterion, name: &str, frames: &[T])
+where
+    T: Serialize + DeserializeOwned + Encode + Decode,
+{
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SAVE_LEN as u64));
+
+    for format in [WireFormat::Json, WireFormat::Scale] {
+        let mut codec = WireCodec::<(), T>::new(format);
+        let encoded: Vec<BytesMut> = frames
+            .iter()
+            .map(|frame| BytesMut::from(&Pin::new(&mut codec).serialize(frame).unwrap()[..]))
+            .collect();
+
+        group.bench_with_input(BenchmarkId::new("encode", format), frames, |b, frames| {
+            let mut codec = WireCodec::<(), T>::new(format);
+            b.iter(|| {
+                for frame in frames {
+                    black_box(Pin::new(&mut codec).serialize(black_box(frame)).unwrap());
+                }
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("decode", format),
+            &encoded,
+            |b, encoded| {
+                let mut codec = WireCodec::<T, ()>::new(format);
+                b.iter(|| {
+                    for frame in encoded {
+                        black_box(Pin::new(&mut codec).deserialize(black_box(frame)).unwrap());
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_upload(c: &mut Criterion) {
+    bench_transfer(c, "Upload save", &upload_frames(&archive(SAVE_LEN)));
+}
+
+fn bench_download(c: &mut Criterion) {
+    bench_transfer(c, "Download save", &download_frames(&archive(SAVE_LEN)));
+}
+
+criterion_group!(benches, bench_upload, bench_download);
+criterion_main!(benches);
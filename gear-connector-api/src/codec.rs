@@ -0,0 +1,93 @@
+use bytes::{Bytes, BytesMut};
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fmt, io, marker::PhantomData, pin::Pin, str::FromStr};
+use tokio_serde::{Deserializer, Serializer};
+
+/// Encoding of `CommandEnvelope`/`ReplyEnvelope` frames, chosen during the handshake.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub enum WireFormat {
+    /// Human readable, kept for debugging. Byte vectors become JSON number arrays.
+    Json,
+    /// Compact SCALE encoding, byte vectors are sent as is.
+    Scale,
+}
+
+impl WireFormat {
+    /// Supported formats in order of preference.
+    pub const ALL: &'static [WireFormat] = &[WireFormat::Scale, WireFormat::Json];
+}
+
+impl Default for WireFormat {
+    /// Peers which don't announce wire formats speak JSON.
+    fn default() -> Self {
+        WireFormat::Json
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireFormat::Json => write!(f, "json"),
+            WireFormat::Scale => write!(f, "scale"),
+        }
+    }
+}
+
+impl FromStr for WireFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(WireFormat::Json),
+            "scale" => Ok(WireFormat::Scale),
+            _ => Err(format!("unknown wire format {s}, expected json or scale")),
+        }
+    }
+}
+
+/// `tokio_serde` codec which encodes frames with the negotiated `WireFormat`.
+pub struct WireCodec<Item, SinkItem> {
+    format: WireFormat,
+    ghost: PhantomData<(Item, SinkItem)>,
+}
+
+impl<Item, SinkItem> WireCodec<Item, SinkItem> {
+    pub fn new(format: WireFormat) -> Self {
+        Self {
+            format,
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<Item, SinkItem> Unpin for WireCodec<Item, SinkItem> {}
+
+impl<Item, SinkItem> Serializer<SinkItem> for WireCodec<Item, SinkItem>
+where
+    SinkItem: Serialize + Encode,
+{
+    type Error = io::Error;
+
+    fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+        match self.format {
+            WireFormat::Json => Ok(serde_json::to_vec(item)?.into()),
+            WireFormat::Scale => Ok(item.encode().into()),
+        }
+    }
+}
+
+impl<Item, SinkItem> Deserializer<Item> for WireCodec<Item, SinkItem>
+where
+    Item: DeserializeOwned + Decode,
+{
+    type Error = io::Error;
+
+    fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+        match self.format {
+            WireFormat::Json => Ok(serde_json::from_slice(src)?),
+            WireFormat::Scale => Item::decode_all(&mut &src[..])
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+}
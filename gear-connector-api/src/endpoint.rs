@@ -0,0 +1,123 @@
+//! Where rust_vcmi finds gear-connector.
+//!
+//! gear-connector listens on a random local port and publishes the bound address.
+//! rust_vcmi resolves it from, in order:
+//! 1. `GEAR_CONNECTOR_ADDRESS` environment variable, set by gear-connector for the
+//!    vcmiclient it spawns, or by hand;
+//! 2. the port file `gear-connector.port` in the VCMI user data directory, written
+//!    by a running gear-connector;
+//! 3. `DEFAULT_ADDRESS`.
+
+use std::{
+    env, fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+pub const ADDRESS_ENV: &str = "GEAR_CONNECTOR_ADDRESS";
+pub const PORT_FILE_NAME: &str = "gear-connector.port";
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:6666";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressSource {
+    Env,
+    PortFile(PathBuf),
+    Default,
+}
+
+impl fmt::Display for AddressSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressSource::Env => write!(f, "${ADDRESS_ENV}"),
+            AddressSource::PortFile(path) => write!(f, "{}", path.display()),
+            AddressSource::Default => write!(f, "default address"),
+        }
+    }
+}
+
+/// VCMI user data directory, resolved the same way as `VCMIDirs::userDataPath`.
+pub fn vcmi_user_data_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("USERPROFILE").map(|profile| {
+            Path::new(&profile)
+                .join("Documents")
+                .join("My Games")
+                .join("vcmi")
+        })
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| {
+            Path::new(&home)
+                .join("Library")
+                .join("Application Support")
+                .join("vcmi")
+        })
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(|data_home| Path::new(&data_home).join("vcmi"))
+            .or_else(|| {
+                env::var_os("HOME")
+                    .map(|home| Path::new(&home).join(".local").join("share").join("vcmi"))
+            })
+    }
+}
+
+pub fn port_file_path() -> Option<PathBuf> {
+    vcmi_user_data_dir().map(|dir| dir.join(PORT_FILE_NAME))
+}
+
+/// Writes `address` to the port file, returns the path of the file.
+pub fn publish_address(address: SocketAddr) -> io::Result<PathBuf> {
+    let path = port_file_path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "VCMI user data directory is unknown",
+        )
+    })?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, address.to_string())?;
+    Ok(path)
+}
+
+/// Removes the port file if it still holds `address`.
+pub fn unpublish_address(address: SocketAddr) {
+    if let Some(path) = port_file_path() {
+        if read_port_file(&path).ok().flatten() == Some(address) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Resolves the gear-connector address in the order documented in this module.
+/// Malformed values are errors rather than skipped, so a typo doesn't silently
+/// connect to another connector.
+pub fn resolve_address() -> io::Result<(SocketAddr, AddressSource)> {
+    if let Ok(address) = env::var(ADDRESS_ENV) {
+        return Ok((parse_address(&address, ADDRESS_ENV)?, AddressSource::Env));
+    }
+    if let Some(path) = port_file_path() {
+        if let Some(address) = read_port_file(&path)? {
+            return Ok((address, AddressSource::PortFile(path)));
+        }
+    }
+    let address = DEFAULT_ADDRESS.parse().expect("DEFAULT_ADDRESS is valid");
+    Ok((address, AddressSource::Default))
+}
+
+fn read_port_file(path: &Path) -> io::Result<Option<SocketAddr>> {
+    match fs::read_to_string(path) {
+        Ok(content) => parse_address(content.trim(), &path.display().to_string()).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_address(address: &str, source: &str) -> io::Result<SocketAddr> {
+    address.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{source}: invalid address {address:?}: {e}"),
+        )
+    })
+}
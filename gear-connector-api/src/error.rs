@@ -0,0 +1,109 @@
+use parity_scale_codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::VcmiReply;
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
+pub enum ErrorCode {
+    Ipfs,
+    Chain,
+    Lobby,
+    Codec,
+    NotConnected,
+    Internal,
+}
+
+/// Failure of one of the gear-connector subsystems, reported to VCMI as `VcmiReply::Error`.
+#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
+pub enum ConnectorError {
+    Ipfs(String),
+    Chain(String),
+    Lobby(String),
+    Codec(String),
+    NotConnected(String),
+    /// A connector thread died or answered with something unexpected.
+    Internal(String),
+}
+
+impl ConnectorError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match code {
+            ErrorCode::Ipfs => Self::Ipfs(message),
+            ErrorCode::Chain => Self::Chain(message),
+            ErrorCode::Lobby => Self::Lobby(message),
+            ErrorCode::Codec => Self::Codec(message),
+            ErrorCode::NotConnected => Self::NotConnected(message),
+            ErrorCode::Internal => Self::Internal(message),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Ipfs(_) => ErrorCode::Ipfs,
+            Self::Chain(_) => ErrorCode::Chain,
+            Self::Lobby(_) => ErrorCode::Lobby,
+            Self::Codec(_) => ErrorCode::Codec,
+            Self::NotConnected(_) => ErrorCode::NotConnected,
+            Self::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Ipfs(message)
+            | Self::Chain(message)
+            | Self::Lobby(message)
+            | Self::Codec(message)
+            | Self::NotConnected(message)
+            | Self::Internal(message) => message,
+        }
+    }
+
+    /// Whether repeating the same request later has a chance to succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self.code() {
+            ErrorCode::Ipfs | ErrorCode::Chain | ErrorCode::Lobby | ErrorCode::NotConnected => true,
+            ErrorCode::Codec | ErrorCode::Internal => false,
+        }
+    }
+}
+
+impl fmt::Display for ConnectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subsystem = match self.code() {
+            ErrorCode::Ipfs => "IPFS",
+            ErrorCode::Chain => "Gear chain",
+            ErrorCode::Lobby => "lobby",
+            ErrorCode::Codec => "codec",
+            ErrorCode::NotConnected => "not connected",
+            ErrorCode::Internal => "gear-connector",
+        };
+        write!(f, "{subsystem} error: {}", self.message())
+    }
+}
+
+impl std::error::Error for ConnectorError {}
+
+impl From<ConnectorError> for VcmiReply {
+    fn from(value: ConnectorError) -> Self {
+        VcmiReply::Error {
+            code: value.code(),
+            retryable: value.is_retryable(),
+            message: value.message().to_string(),
+        }
+    }
+}
+
+impl VcmiReply {
+    /// Turns `VcmiReply::Error` into `Err`, passes every other reply through.
+    pub fn into_result(self) -> Result<Self, ConnectorError> {
+        match self {
+            VcmiReply::Error { code, message, .. } => Err(ConnectorError::new(code, message)),
+            reply => Ok(reply),
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+use crate::codec::WireFormat;
+use crate::utils::length_delimited_codec;
+
+/// Version of the rust_vcmi <-> gear-connector protocol.
+/// Bump it on every incompatible change of `VcmiCommand`/`VcmiReply`.
+pub const PROTOCOL_VERSION: u32 = 10;
+
+/// How long each side of the handshake waits for the frame of the other.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub enum Capability {
+    BattleSimulation,
+    ArchiveSave,
+    GameStateSave,
+    /// Capability announced by a newer peer which this build doesn't know about.
+    #[serde(other)]
+    Unknown,
+}
+
+/// First frame sent by rust_vcmi after the TCP connection is established.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub build_id: String,
+    pub capabilities: Vec<Capability>,
+    /// Wire formats the peer can speak, most preferred first.
+    #[serde(default)]
+    pub wire_formats: Vec<WireFormat>,
+}
+
+/// Answer of gear-connector to `Hello`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Welcome {
+    Accepted {
+        protocol_version: u32,
+        build_id: String,
+        /// Capabilities supported by both sides.
+        capabilities: Vec<Capability>,
+        /// Format of every frame after the handshake.
+        #[serde(default)]
+        wire_format: WireFormat,
+    },
+    Rejected {
+        protocol_version: u32,
+        build_id: String,
+        reason: String,
+    },
+}
+
+/// Outcome of a successful handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub capabilities: Vec<Capability>,
+    pub wire_format: WireFormat,
+}
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    Io(std::io::Error),
+    Codec(serde_json::Error),
+    Timeout,
+    Closed,
+    Incompatible {
+        local: u32,
+        remote: u32,
+        remote_build: String,
+    },
+    Rejected(String),
+}
+
+impl Hello {
+    pub fn new(build_id: impl Into<String>, capabilities: &[Capability]) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            build_id: build_id.into(),
+            capabilities: capabilities.to_vec(),
+            wire_formats: WireFormat::ALL.to_vec(),
+        }
+    }
+
+    pub fn with_wire_formats(mut self, wire_formats: &[WireFormat]) -> Self {
+        self.wire_formats = wire_formats.to_vec();
+        self
+    }
+}
+
+impl Welcome {
+    /// Checks `hello` against the local build and builds the answer for it.
+    /// The first of the peer wire formats which is also in `wire_formats` wins.
+    pub fn answer(
+        hello: &Hello,
+        build_id: &str,
+        capabilities: &[Capability],
+        wire_formats: &[WireFormat],
+    ) -> Self {
+        if hello.protocol_version != PROTOCOL_VERSION {
+            return Self::Rejected {
+                protocol_version: PROTOCOL_VERSION,
+                build_id: build_id.to_string(),
+                reason: format!(
+                    "protocol version mismatch: gear-connector {} speaks v{}, rust_vcmi {} speaks v{}",
+                    build_id, PROTOCOL_VERSION, hello.build_id, hello.protocol_version
+                ),
+            };
+        }
+
+        let mut common: Vec<Capability> = hello
+            .capabilities
+            .iter()
+            .filter(|capability| capabilities.contains(capability))
+            .copied()
+            .collect();
+        common.sort();
+        common.dedup();
+
+        // Peers built before the wire formats were negotiated announce none and speak JSON
+        let offered = match hello.wire_formats.as_slice() {
+            [] => &[WireFormat::Json][..],
+            offered => offered,
+        };
+        let wire_format = match offered.iter().find(|format| wire_formats.contains(format)) {
+            Some(wire_format) => *wire_format,
+            None => {
+                let names = |formats: &[WireFormat]| {
+                    formats
+                        .iter()
+                        .map(WireFormat::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                return Self::Rejected {
+                    protocol_version: PROTOCOL_VERSION,
+                    build_id: build_id.to_string(),
+                    reason: format!(
+                        "no common wire format: gear-connector {} speaks {}, rust_vcmi {} speaks {}",
+                        build_id,
+                        names(wire_formats),
+                        hello.build_id,
+                        names(offered)
+                    ),
+                };
+            }
+        };
+
+        Self::Accepted {
+            protocol_version: PROTOCOL_VERSION,
+            build_id: build_id.to_string(),
+            capabilities: common,
+            wire_format,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::BattleSimulation => "battle simulation",
+            Capability::ArchiveSave => "archive save",
+            Capability::GameStateSave => "game-state save",
+            Capability::Unknown => "unknown",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Io(e) => write!(f, "handshake io error: {e}"),
+            HandshakeError::Codec(e) => write!(
+                f,
+                "can't parse handshake frame ({e}), peer is probably built from an older gear_connector_api"
+            ),
+            HandshakeError::Timeout => write!(f, "peer didn't finish handshake in time"),
+            HandshakeError::Closed => write!(f, "connection closed during handshake"),
+            HandshakeError::Incompatible {
+                local,
+                remote,
+                remote_build,
+            } => write!(
+                f,
+                "incompatible peer {remote_build}: it speaks protocol v{remote}, we speak v{local}"
+            ),
+            HandshakeError::Rejected(reason) => write!(f, "rejected by gear-connector: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+impl From<std::io::Error> for HandshakeError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for HandshakeError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Codec(value)
+    }
+}
+
+impl From<HandshakeError> for std::io::Error {
+    fn from(value: HandshakeError) -> Self {
+        match value {
+            HandshakeError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::ConnectionRefused, e.to_string()),
+        }
+    }
+}
+
+/// Sends `hello` and waits for `Welcome`. Must be called before the stream is split.
+pub async fn client_handshake(
+    stream: &mut TcpStream,
+    hello: Hello,
+) -> Result<Negotiated, HandshakeError> {
+    let mut framed = Framed::new(stream, length_delimited_codec());
+    framed
+        .send(Bytes::from(serde_json::to_vec(&hello)?))
+        .await?;
+
+    // A connector built before the handshake never answers
+    let frame = tokio::time::timeout(HANDSHAKE_TIMEOUT, framed.next())
+        .await
+        .map_err(|_| HandshakeError::Timeout)?
+        .ok_or(HandshakeError::Closed)??;
+    match serde_json::from_slice::<Welcome>(&frame)? {
+        Welcome::Accepted {
+            protocol_version,
+            build_id,
+            capabilities,
+            wire_format,
+        } => {
+            if protocol_version != PROTOCOL_VERSION {
+                return Err(HandshakeError::Incompatible {
+                    local: PROTOCOL_VERSION,
+                    remote: protocol_version,
+                    remote_build: build_id,
+                });
+            }
+            Ok(Negotiated {
+                capabilities,
+                wire_format,
+            })
+        }
+        Welcome::Rejected { reason, .. } => Err(HandshakeError::Rejected(reason)),
+    }
+}
+
+/// Waits for `Hello`, answers with `Welcome` and returns the peer greeting together
+/// with the negotiated capabilities and wire format. Incompatible peers get `Welcome::Rejected`.
+pub async fn server_handshake(
+    stream: &mut TcpStream,
+    build_id: &str,
+    capabilities: &[Capability],
+    wire_formats: &[WireFormat],
+) -> Result<(Hello, Negotiated), HandshakeError> {
+    let mut framed = Framed::new(stream, length_delimited_codec());
+    let frame = tokio::time::timeout(HANDSHAKE_TIMEOUT, framed.next())
+        .await
+        .map_err(|_| HandshakeError::Timeout)?
+        .ok_or(HandshakeError::Closed)??;
+    let hello: Hello = serde_json::from_slice(&frame)?;
+
+    let welcome = Welcome::answer(&hello, build_id, capabilities, wire_formats);
+    framed
+        .send(Bytes::from(serde_json::to_vec(&welcome)?))
+        .await?;
+
+    match welcome {
+        Welcome::Accepted {
+            capabilities,
+            wire_format,
+            ..
+        } => Ok((
+            hello,
+            Negotiated {
+                capabilities,
+                wire_format,
+            },
+        )),
+        Welcome::Rejected { .. } if hello.protocol_version != PROTOCOL_VERSION => {
+            Err(HandshakeError::Incompatible {
+                local: PROTOCOL_VERSION,
+                remote: hello.protocol_version,
+                remote_build: hello.build_id,
+            })
+        }
+        Welcome::Rejected { reason, .. } => Err(HandshakeError::Rejected(reason)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: &[Capability] = &[Capability::BattleSimulation, Capability::ArchiveSave];
+
+    fn hello(capabilities: &[Capability], wire_formats: &[WireFormat]) -> Hello {
+        Hello::new("rust_vcmi test", capabilities).with_wire_formats(wire_formats)
+    }
+
+    #[test]
+    fn other_protocol_version_is_rejected() {
+        let mut hello = hello(LOCAL, WireFormat::ALL);
+        hello.protocol_version = PROTOCOL_VERSION + 1;
+        match Welcome::answer(&hello, "connector test", LOCAL, WireFormat::ALL) {
+            Welcome::Rejected {
+                protocol_version,
+                reason,
+                ..
+            } => {
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert!(reason.contains("mismatch"), "{reason}");
+            }
+            welcome => panic!("Unexpected {welcome:?}"),
+        }
+    }
+
+    #[test]
+    fn capabilities_are_intersected() {
+        let hello = hello(
+            &[
+                Capability::GameStateSave,
+                Capability::ArchiveSave,
+                Capability::Unknown,
+                Capability::BattleSimulation,
+                Capability::ArchiveSave,
+            ],
+            WireFormat::ALL,
+        );
+        match Welcome::answer(&hello, "connector test", LOCAL, WireFormat::ALL) {
+            Welcome::Accepted { capabilities, .. } => assert_eq!(
+                capabilities,
+                vec![Capability::BattleSimulation, Capability::ArchiveSave]
+            ),
+            welcome => panic!("Unexpected {welcome:?}"),
+        }
+    }
+
+    #[test]
+    fn first_common_wire_format_of_the_peer_wins() {
+        let wire_format = |peer: &[WireFormat], local: &[WireFormat]| match Welcome::answer(
+            &hello(LOCAL, peer),
+            "connector test",
+            LOCAL,
+            local,
+        ) {
+            Welcome::Accepted { wire_format, .. } => wire_format,
+            welcome => panic!("Unexpected {welcome:?}"),
+        };
+        assert_eq!(
+            wire_format(WireFormat::ALL, WireFormat::ALL),
+            WireFormat::Scale
+        );
+        assert_eq!(
+            wire_format(&[WireFormat::Json, WireFormat::Scale], WireFormat::ALL),
+            WireFormat::Json
+        );
+        assert_eq!(
+            wire_format(WireFormat::ALL, &[WireFormat::Json]),
+            WireFormat::Json
+        );
+        // Peers built before the wire formats were negotiated announce none
+        assert_eq!(wire_format(&[], WireFormat::ALL), WireFormat::Json);
+    }
+
+    #[test]
+    fn no_common_wire_format_is_rejected() {
+        let hello = hello(LOCAL, &[WireFormat::Scale]);
+        match Welcome::answer(&hello, "connector test", LOCAL, &[WireFormat::Json]) {
+            Welcome::Rejected { reason, .. } => {
+                assert!(reason.contains("no common wire format"), "{reason}");
+                assert!(reason.contains("speaks json, rust_vcmi"), "{reason}");
+            }
+            welcome => panic!("Unexpected {welcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn client_gives_up_on_a_silent_connector() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            // Reads Hello and never answers, as connectors without the handshake do
+            tokio::time::sleep(HANDSHAKE_TIMEOUT * 2).await;
+            drop(stream);
+        });
+
+        tokio::time::pause();
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let result = client_handshake(&mut stream, hello(LOCAL, WireFormat::ALL)).await;
+        assert!(matches!(result, Err(HandshakeError::Timeout)), "{result:?}");
+        server.abort();
+    }
+}
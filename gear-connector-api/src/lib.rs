@@ -5,9 +5,20 @@ use serde::{Deserialize, Serialize};
 use std::error::Error;
 use tokio_util::codec::{Decoder, Encoder};
 
+pub use error::{ConnectorError, ErrorCode};
+pub use transfer::{ArchiveInfo, Checksum, SaveDescription, SaveMetadata, SavePlayer};
+
+pub mod codec;
+pub mod endpoint;
+pub mod error;
+pub mod handshake;
+pub mod manifest;
+pub mod transfer;
 pub mod utils;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 #[repr(u8)]
 pub enum PrimarySkill {
     None = u8::MAX,
@@ -18,7 +29,9 @@ pub enum PrimarySkill {
     Experience = 4,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 #[repr(u8)]
 pub enum SecondarySkill {
     Wrong = u8::MAX - 1,
@@ -54,7 +67,9 @@ pub enum SecondarySkill {
     SkillSize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 #[repr(i32)]
 pub enum FortLevel {
     None = 0,
@@ -63,7 +78,9 @@ pub enum FortLevel {
     Castle = 3,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 #[repr(u8)]
 pub enum HallLevel {
     None = u8::MAX,
@@ -73,20 +90,37 @@ pub enum HallLevel {
     Capitol = 3,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 pub struct SecondarySkillInfo {
     pub skill: SecondarySkill,
     pub value: u8,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Serialize,
+    Deserialize,
+    Hash,
+    PartialEq,
+    PartialOrd,
+    Eq,
+    Ord,
+    Encode,
+    Decode,
+)]
 pub struct Stack {
     pub name: String,
     pub level: i32,
     pub count: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 pub struct Hero {
     pub name: String,
     pub level: u32,
@@ -97,7 +131,9 @@ pub struct Hero {
     pub stacks: [Option<Stack>; 7],
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 pub struct Town {
     pub name: String,
     pub fort_level: FortLevel,
@@ -133,7 +169,9 @@ pub enum Resource {
     Invalid,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 pub enum Terrain {
     NativeTerrain,
     AnyTerrain,
@@ -152,7 +190,9 @@ pub enum Terrain {
     OriginalRegularTerrainCount,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
 pub struct PlayerState {
     pub color: String,
     pub team_id: u32,
@@ -163,7 +203,8 @@ pub struct PlayerState {
     pub days_without_castle: Option<u8>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
 pub enum VcmiCommand {
     Connect,
     ShowLoadGameDialog,
@@ -172,16 +213,78 @@ pub enum VcmiCommand {
         current_player: String,
         player_states: Vec<PlayerState>,
     },
-    SaveArchive {
+    /// Starts or resumes the upload of a zipped save, answered with `UploadOffset`.
+    UploadBegin {
         filename: String,
-        compressed_archive: Vec<u8>,
+        len: u64,
+        checksum: Checksum,
+    },
+    /// Answered with `UploadOffset`, which is where the next chunk has to start.
+    UploadChunk {
+        checksum: Checksum,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    /// Verifies the uploaded archive and saves it on chain, answered with `Saved`.
+    /// `metadata` is kept by the connector next to the CID of the archive.
+    UploadCommit {
+        checksum: Checksum,
+        metadata: SaveMetadata,
     },
     SimulateBattle(BattleInfo),
     Load(String),
-    LoadAll,
+    /// Lists the saves on chain, answered with `Saves`.
+    ListSaves,
+    /// Downloads the save with IPFS CID `cid` and stages it, answered with `Fetched`.
+    FetchSave {
+        cid: String,
+    },
+    /// Removes the save with IPFS CID `cid` from chain, answered with `Deleted`.
+    /// With `unpin` its archive is also unpinned from the local IPFS node.
+    DeleteSave {
+        cid: String,
+        unpin: bool,
+    },
+    /// Renames the save with IPFS CID `cid` on chain, answered with `Saved`.
+    RenameSave {
+        cid: String,
+        name: String,
+    },
+    /// Like `UploadCommit`, but the uploaded archive takes the place of the save
+    /// with IPFS CID `cid` instead of adding another save, answered with `Saved`.
+    ReplaceSave {
+        cid: String,
+        checksum: Checksum,
+        metadata: SaveMetadata,
+    },
+    /// Reads up to `len` bytes of an archive staged by `FetchSave`.
+    DownloadChunk {
+        checksum: Checksum,
+        offset: u64,
+        len: u32,
+    },
+    /// Health check, answered with `Pong` by the connection itself.
+    Ping,
 }
 
-#[derive(Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+/// Identifier chosen by the sender of a `VcmiCommand` and echoed back in its reply.
+pub type RequestId = u64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct CommandEnvelope {
+    pub request_id: RequestId,
+    pub command: VcmiCommand,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct ReplyEnvelope {
+    pub request_id: RequestId,
+    pub reply: VcmiReply,
+}
+
+#[derive(
+    Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug, Encode, Decode,
+)]
 pub struct BattleInfo {
     pub stacks: Vec<Stack>,
     pub sides: [BattleSide; 2],
@@ -189,29 +292,43 @@ pub struct BattleInfo {
     pub active_stack: i32,
     pub terrain_type: Terrain,
 }
-#[derive(Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+#[derive(
+    Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug, Encode, Decode,
+)]
 pub struct BattleSide {
     pub color: String,
     pub hero: Hero,
 }
 
-#[derive(Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
-pub struct VcmiSavedGame {
-    pub filename: String,
-    pub data: Vec<u8>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
 pub enum VcmiReply {
     ConnectDialogShowed,
     CanceledDialog,
     Connected,
 
     Saved,
-    Loaded { archive_data: Vec<u8> },
-    AllLoaded { archives: Vec<VcmiSavedGame> },
+    Deleted,
+    Loaded {
+        archive_data: Vec<u8>,
+    },
+    Saves(Vec<SaveDescription>),
+    Fetched(ArchiveInfo),
+    UploadOffset {
+        offset: u64,
+    },
+    DownloadedChunk {
+        offset: u64,
+        data: Vec<u8>,
+    },
     BattleInfo(BattleInfo),
     LoadGameDialogShowed,
+    Pong,
+    Error {
+        code: ErrorCode,
+        message: String,
+        retryable: bool,
+    },
 }
 
 pub struct VcmiCommandCodec;
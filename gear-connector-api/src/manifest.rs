@@ -0,0 +1,61 @@
+//! Manifest stored in every save archive, describes the files of the save.
+
+use serde::{Deserialize, Serialize};
+
+use crate::transfer::{checksum, checksum_hex};
+use crate::ConnectorError;
+
+/// Name of the manifest inside the archive.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub len: u64,
+    /// Hex-encoded SHA-256 of the file.
+    pub sha256: String,
+}
+
+impl ManifestEntry {
+    pub fn new(name: impl Into<String>, data: &[u8]) -> Self {
+        Self {
+            name: name.into(),
+            len: data.len() as u64,
+            sha256: checksum_hex(&checksum(data)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Manifest {
+    pub vcmi_version: String,
+    /// When the game was saved, in seconds since the Unix epoch.
+    pub saved_at: u64,
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("Manifest is serializable")
+    }
+
+    pub fn from_json(data: &[u8]) -> Result<Self, ConnectorError> {
+        serde_json::from_slice(data)
+            .map_err(|e| ConnectorError::Codec(format!("invalid {MANIFEST_NAME}: {e}")))
+    }
+
+    /// Checks that `data` is the file `name` listed in the manifest.
+    pub fn verify(&self, name: &str, data: &[u8]) -> Result<(), ConnectorError> {
+        let entry = self
+            .files
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| ConnectorError::Codec(format!("{name} isn't in {MANIFEST_NAME}")))?;
+        if *entry != ManifestEntry::new(name, data) {
+            return Err(ConnectorError::Codec(format!(
+                "{name} doesn't match {MANIFEST_NAME}"
+            )));
+        }
+        Ok(())
+    }
+}
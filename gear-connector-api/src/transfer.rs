@@ -0,0 +1,95 @@
+//! Chunked transfer of save archives between rust_vcmi and gear-connector.
+//!
+//! Upload: `UploadBegin` answers with the offset the connector already has for the
+//! archive (non-zero when an interrupted upload is resumed), then `UploadChunk`s are
+//! sent from that offset and `UploadCommit` verifies the checksum of the whole archive.
+//!
+//! Download: `ListSaves` answers with a `SaveDescription` of every save on chain
+//! without downloading any. `FetchSave` stages one of them on the connector and
+//! answers with its `ArchiveInfo`, the archive is then read with `DownloadChunk`.
+
+use parity_scale_codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// SHA-256 of a whole archive, identifies the transfer.
+pub type Checksum = [u8; 32];
+
+/// Payload size of one `UploadChunk`/`DownloadedChunk` frame.
+pub const CHUNK_LEN: usize = 1024 * 1024;
+
+/// Biggest archive accepted by `UploadBegin`.
+pub const MAX_ARCHIVE_LEN: u64 = 512 * 1024 * 1024;
+
+pub fn checksum(data: &[u8]) -> Checksum {
+    Sha256::digest(data).into()
+}
+
+pub fn checksum_hex(checksum: &Checksum) -> String {
+    checksum.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Save stored on chain, as listed by `ListSaves`.
+/// Fields the connector doesn't know yet, e.g. of saves made elsewhere, are `None`.
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
+pub struct SaveDescription {
+    pub name: String,
+    /// IPFS CID of the archive, passed to `FetchSave`.
+    pub cid: String,
+    /// Size of the archive in bytes.
+    pub size: Option<u64>,
+    /// When the archive was saved on chain, in seconds since the Unix epoch.
+    pub saved_at: Option<u64>,
+    pub metadata: Option<SaveMetadata>,
+    /// Whether the archive is encrypted with a key of the account which saved it.
+    /// Older saves, indexed before archives were encrypted, are plain.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// SHA-256 of the archive as it was uploaded, kept on chain next to the CID,
+    /// checks the download.
+    #[serde(default)]
+    pub checksum: Option<Checksum>,
+}
+
+/// Describes a save without opening it, collected by VCMI at save time.
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
+pub struct SaveMetadata {
+    pub map_name: String,
+    /// Map size in tiles.
+    pub map_width: i32,
+    pub map_height: i32,
+    pub two_levels: bool,
+    /// In-game day, starting from 1.
+    pub day: u32,
+    pub players: Vec<SavePlayer>,
+    pub vcmi_version: String,
+    pub mods: Vec<String>,
+    /// Total size of the save files before compression.
+    pub uncompressed_size: u64,
+    /// Whether the archive is encrypted, set by gear-connector on upload and kept on chain.
+    /// `None` for saves made before the flag, their archive tells it by its magic.
+    #[serde(default)]
+    pub encrypted: Option<bool>,
+}
+
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
+pub struct SavePlayer {
+    pub color: String,
+    pub is_human: bool,
+}
+
+/// Archive staged on gear-connector and ready to be read with `DownloadChunk`.
+#[derive(
+    Debug, Clone, Serialize, Deserialize, Hash, PartialEq, PartialOrd, Eq, Ord, Encode, Decode,
+)]
+pub struct ArchiveInfo {
+    pub filename: String,
+    pub len: u64,
+    pub checksum: Checksum,
+}
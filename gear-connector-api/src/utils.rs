@@ -1,50 +1,59 @@
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
-use tokio_serde::{formats::Json, Framed};
+use tokio_serde::Framed;
 
 use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
 
-use crate::{VcmiCommand, VcmiReply};
+use crate::codec::{WireCodec, WireFormat};
+use crate::{CommandEnvelope, ReplyEnvelope};
 
 type WrappedStream = FramedRead<OwnedReadHalf, LengthDelimitedCodec>;
 type WrappedSink = FramedWrite<OwnedWriteHalf, LengthDelimitedCodec>;
 
 // We use the unit type in place of the message types since we're
 // only dealing with one half of the IO
-type CommandReadStream = Framed<WrappedStream, VcmiCommand, (), Json<VcmiCommand, ()>>;
-type CommandWriteStream = Framed<WrappedSink, (), VcmiCommand, Json<(), VcmiCommand>>;
+type CommandReadStream = Framed<WrappedStream, CommandEnvelope, (), WireCodec<CommandEnvelope, ()>>;
+type CommandWriteStream = Framed<WrappedSink, (), CommandEnvelope, WireCodec<(), CommandEnvelope>>;
 
-type ReplyReadStream = Framed<WrappedStream, VcmiReply, (), Json<VcmiReply, ()>>;
-type ReplyWriteStream = Framed<WrappedSink, (), VcmiReply, Json<(), VcmiReply>>;
+type ReplyReadStream = Framed<WrappedStream, ReplyEnvelope, (), WireCodec<ReplyEnvelope, ()>>;
+type ReplyWriteStream = Framed<WrappedSink, (), ReplyEnvelope, WireCodec<(), ReplyEnvelope>>;
+
+/// Biggest frame accepted from the peer. Saves of XL maps are several megabytes
+/// and grow about four times when sent as JSON.
+pub const MAX_FRAME_LENGTH: usize = 128 * 1024 * 1024;
+
+pub fn length_delimited_codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .length_field_length(8)
+        .length_field_type::<u64>()
+        .max_frame_length(MAX_FRAME_LENGTH)
+        .new_codec()
+}
 
 pub fn wrap_to_command_read_reply_write(
     stream: TcpStream,
+    format: WireFormat,
 ) -> (CommandReadStream, ReplyWriteStream) {
     let (read, write) = stream.into_split();
-    let codec = LengthDelimitedCodec::builder()
-        .length_field_length(8)
-        .length_field_type::<u64>()
-        .new_codec();
+    let codec = length_delimited_codec();
     let stream = WrappedStream::new(read, codec.clone());
     let sink = WrappedSink::new(write, codec);
     (
-        CommandReadStream::new(stream, Json::default()),
-        ReplyWriteStream::new(sink, Json::default()),
+        CommandReadStream::new(stream, WireCodec::new(format)),
+        ReplyWriteStream::new(sink, WireCodec::new(format)),
     )
 }
 
 pub fn split_to_reply_read_command_write(
     stream: TcpStream,
+    format: WireFormat,
 ) -> (ReplyReadStream, CommandWriteStream) {
     let (read, write) = stream.into_split();
-    let codec = LengthDelimitedCodec::builder()
-        .length_field_length(8)
-        .length_field_type::<u64>()
-        .new_codec();
+    let codec = length_delimited_codec();
     let stream = WrappedStream::new(read, codec.clone());
     let sink = WrappedSink::new(write, codec);
     (
-        ReplyReadStream::new(stream, Json::default()),
-        CommandWriteStream::new(sink, Json::default()),
+        ReplyReadStream::new(stream, WireCodec::new(format)),
+        CommandWriteStream::new(sink, WireCodec::new(format)),
     )
 }
@@ -0,0 +1,144 @@
+use crate::utils::{env_number, load_json_or_default, store_json_atomically};
+use gear_connector_api::endpoint::vcmi_user_data_dir;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// Directory of the cache of downloaded archives.
+pub const CACHE_DIR_ENV: &str = "GEAR_CONNECTOR_CACHE_DIR";
+/// Size limit of the cache in MiB, `0` turns the cache off.
+pub const CACHE_SIZE_ENV: &str = "GEAR_CONNECTOR_CACHE_MB";
+
+/// Name of the cache in the VCMI user data directory.
+const CACHE_DIR_NAME: &str = "gear-connector-cache";
+const INDEX_FILE_NAME: &str = "index.json";
+const DEFAULT_CACHE_MB: u64 = 256;
+
+#[derive(Serialize, Deserialize)]
+struct CachedArchive {
+    cid: String,
+    len: u64,
+}
+
+/// Archives downloaded before, one file per archive named by its CID, so loading
+/// a save again doesn't download it. The least recently used archives are dropped
+/// when the cache grows over its limit.
+pub struct ArchiveCache {
+    dir: PathBuf,
+    limit: u64,
+    /// Least recently used first.
+    archives: Vec<CachedArchive>,
+}
+
+impl ArchiveCache {
+    /// Uses `CACHE_DIR_ENV` and `CACHE_SIZE_ENV`, `gear-connector-cache` in the VCMI
+    /// user data directory by default.
+    pub fn from_env() -> Self {
+        let dir = std::env::var_os(CACHE_DIR_ENV)
+            .map(PathBuf::from)
+            .or_else(|| vcmi_user_data_dir().map(|dir| dir.join(CACHE_DIR_NAME)))
+            .unwrap_or_else(|| PathBuf::from(CACHE_DIR_NAME));
+        let limit = env_number(CACHE_SIZE_ENV).unwrap_or(DEFAULT_CACHE_MB) * 1024 * 1024;
+        Self::new(dir, limit)
+    }
+
+    /// Opens the cache in `dir`, starting an empty one if its index is missing or unreadable.
+    pub fn new(dir: PathBuf, limit: u64) -> Self {
+        let path = dir.join(INDEX_FILE_NAME);
+        let mut archives: Vec<CachedArchive> = load_json_or_default(&path);
+        // The files could be removed while the connector didn't run
+        archives.retain(|archive| is_cid(&archive.cid) && dir.join(&archive.cid).is_file());
+        let mut cache = Self {
+            dir,
+            limit,
+            archives,
+        };
+        cache.evict();
+        cache
+    }
+
+    /// Reads the archive if it is cached, making it the most recently used.
+    pub fn get(&mut self, cid: &str) -> Option<Vec<u8>> {
+        let index = self
+            .archives
+            .iter()
+            .position(|archive| archive.cid == cid)?;
+        let archive = self.archives.remove(index);
+        let path = self.dir.join(cid);
+        let data = match fs::read(&path) {
+            Ok(data) => {
+                self.archives.push(archive);
+                Some(data)
+            }
+            Err(e) => {
+                tracing::warn!("Can't read {}: {}", path.display(), e);
+                None
+            }
+        };
+        self.store();
+        data
+    }
+
+    /// Caches the archive, dropping the least recently used ones over the limit.
+    pub fn put(&mut self, cid: &str, data: &[u8]) {
+        let len = data.len() as u64;
+        // The CID becomes a file name, so it must not lead out of the cache
+        if len > self.limit || !is_cid(cid) {
+            return;
+        }
+        self.archives.retain(|archive| archive.cid != cid);
+        let path = self.dir.join(cid);
+        let tmp_path = path.with_extension("tmp");
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&tmp_path, data))
+            .and_then(|()| fs::rename(&tmp_path, &path));
+        if let Err(e) = result {
+            tracing::warn!("Can't write {}: {}", path.display(), e);
+            return;
+        }
+        self.archives.push(CachedArchive {
+            cid: cid.to_string(),
+            len,
+        });
+        self.evict();
+        self.store();
+    }
+
+    pub fn remove(&mut self, cid: &str) {
+        if let Some(index) = self.archives.iter().position(|archive| archive.cid == cid) {
+            let archive = self.archives.remove(index);
+            self.remove_file(&archive);
+            self.store();
+        }
+    }
+
+    fn evict(&mut self) {
+        let mut len: u64 = self.archives.iter().map(|archive| archive.len).sum();
+        while len > self.limit && !self.archives.is_empty() {
+            let archive = self.archives.remove(0);
+            tracing::debug!("Evict archive {} from the cache", archive.cid);
+            len -= archive.len;
+            self.remove_file(&archive);
+        }
+    }
+
+    fn remove_file(&self, archive: &CachedArchive) {
+        let path = self.dir.join(&archive.cid);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                tracing::warn!("Can't remove {}: {}", path.display(), e)
+            }
+            _ => {}
+        }
+    }
+
+    fn store(&self) {
+        let path = self.dir.join(INDEX_FILE_NAME);
+        if let Err(e) = store_json_atomically(&path, &self.archives) {
+            tracing::error!("Can't write {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn is_cid(cid: &str) -> bool {
+    !cid.is_empty() && cid.bytes().all(|b| b.is_ascii_alphanumeric())
+}
@@ -0,0 +1,132 @@
+use crate::keystore::Secret;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use gsdk::ext::sp_core::{sr25519, Pair};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// `off` to upload save archives unencrypted.
+pub const ENCRYPT_SAVES_ENV: &str = "GEAR_CONNECTOR_ENCRYPT_SAVES";
+
+/// Starts every encrypted archive. Plain archives are zips starting with `PK`,
+/// so saves uploaded before the encryption still load.
+const MAGIC: &[u8; 8] = b"GCSAVE\x01\x00";
+const NONCE_LEN: usize = 24;
+/// Keeps the archive key apart from anything else derived from the account.
+const KEY_CONTEXT: &[u8] = b"gear-connector save archive key";
+
+/// Whether archives are encrypted before upload, unless `ENCRYPT_SAVES_ENV` says `off`.
+pub fn encrypt_saves_from_env() -> bool {
+    match std::env::var(ENCRYPT_SAVES_ENV) {
+        Ok(value) if value.eq_ignore_ascii_case("off") => false,
+        Ok(value) if !value.eq_ignore_ascii_case("on") => {
+            tracing::warn!("Ignore {}={:?}: not on or off", ENCRYPT_SAVES_ENV, value);
+            true
+        }
+        _ => true,
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts save archives by XChaCha20-Poly1305 with a key of the player's account,
+/// so only the account which saved a game can read its archive from IPFS.
+pub struct ArchiveCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl ArchiveCipher {
+    /// Derives the key by HKDF-SHA256 from the secret key of the account keypair,
+    /// so the account gets the same key on every computer, whether its secret URI
+    /// is a mnemonic or the seed of it.
+    pub fn for_account(suri: &Secret) -> Result<Self, String> {
+        let pair = sr25519::Pair::from_string(suri.expose(), None)
+            .map_err(|e| format!("Invalid secret phrase: {e:?}"))?;
+        let secret = Zeroizing::new(pair.to_raw_vec());
+        let mut key = Zeroizing::new([0u8; 32]);
+        Hkdf::<Sha256>::new(None, &secret)
+            .expand(KEY_CONTEXT, &mut key[..])
+            .expect("32 bytes are within the HKDF-SHA256 output limit");
+        Ok(Self {
+            cipher: XChaCha20Poly1305::new(key.as_ref().into()),
+        })
+    }
+
+    /// Returns the magic, a random nonce and the archive encrypted with the magic authenticated.
+    pub fn encrypt(&self, archive: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: archive,
+            aad: MAGIC,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .expect("Archives are far below the XChaCha20-Poly1305 message limit");
+
+        let mut encrypted = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        encrypted.extend_from_slice(MAGIC);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted
+    }
+
+    /// Opens an archive made by `encrypt`, failing if it was encrypted by another
+    /// account or changed since.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let body = data
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or_else(|| "the archive is not encrypted".to_string())?;
+        if body.len() < NONCE_LEN {
+            return Err("the encrypted archive is truncated".to_string());
+        }
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: MAGIC,
+        };
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| "the archive is encrypted by another account or corrupted".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(suri: &str) -> ArchiveCipher {
+        ArchiveCipher::for_account(&Secret::new(suri.to_string())).unwrap()
+    }
+
+    #[test]
+    fn same_account_same_key() {
+        let archive = b"PK zipped save";
+        let dev_phrase = "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+        let encrypted = cipher(dev_phrase).encrypt(archive);
+        let seed = "0xfac7959dbfe72f052e5a0c3c8d6530f202b02fd8f9f5ca3580ec8deb7797479e";
+        assert_eq!(cipher(seed).decrypt(&encrypted).unwrap(), archive);
+
+        let encrypted = cipher("//Alice").encrypt(archive);
+        let seed = "0xe5be9a5092b81bca64be81d212e7f2f9eba183bb7a90954f7b76361f6edb5c0a";
+        assert_eq!(cipher(seed).decrypt(&encrypted).unwrap(), archive);
+    }
+
+    #[test]
+    fn other_account_other_key() {
+        let encrypted = cipher("//Alice").encrypt(b"PK zipped save");
+        assert!(cipher("//Bob").decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn invalid_secret_phrase() {
+        let suri = Secret::new("not a secret phrase".to_string());
+        assert!(ArchiveCipher::for_account(&suri).is_err());
+    }
+}
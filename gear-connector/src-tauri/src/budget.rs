@@ -0,0 +1,147 @@
+use crate::utils::env_number;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most the connector spends without asking, in the smallest units of the chain token.
+pub const SPENDING_CAP_ENV: &str = "GEAR_CONNECTOR_SPENDING_CAP";
+/// `session` to count spending since the connector started, `game` since the game started.
+pub const SPENDING_SCOPE_ENV: &str = "GEAR_CONNECTOR_SPENDING_SCOPE";
+/// Price of a gas unit in the smallest units of the chain token.
+pub const VALUE_PER_GAS_ENV: &str = "GEAR_CONNECTOR_VALUE_PER_GAS";
+
+/// Placeholder price of a gas unit, the value per gas of the runtime isn't read.
+const DEFAULT_VALUE_PER_GAS: u128 = 1;
+
+/// Gas cost of a transaction, estimated before it is sent. The transaction fee
+/// comes on top of it and shows up only in the spending ledger.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeeEstimate {
+    pub action: String,
+    pub gas: u64,
+    /// Value of `gas` at the configured price, the most the message can burn.
+    pub gas_value: u128,
+}
+
+/// Turns gas into token cost, leaving the transaction fee out.
+#[derive(Debug, Clone, Copy)]
+pub struct FeeEstimator {
+    value_per_gas: u128,
+}
+
+impl FeeEstimator {
+    pub fn from_env() -> Self {
+        Self {
+            value_per_gas: env_number(VALUE_PER_GAS_ENV).unwrap_or(DEFAULT_VALUE_PER_GAS),
+        }
+    }
+
+    pub fn estimate(&self, action: impl Into<String>, gas: u64) -> FeeEstimate {
+        FeeEstimate {
+            action: action.into(),
+            gas,
+            gas_value: self.value_per_gas.saturating_mul(gas as u128),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BudgetScope {
+    Session,
+    Game,
+}
+
+/// A transaction in the spending ledger of the log window.
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerEntry {
+    pub action: String,
+    /// Gas cost estimated before the transaction was sent.
+    pub estimated: u128,
+    /// How much the free balance went down, transaction fee and gas together.
+    pub spent: u128,
+    /// Spending of the scope so far, this transaction included.
+    pub total: u128,
+    pub cap: Option<u128>,
+    /// Unix time in seconds.
+    pub at: Option<u64>,
+}
+
+/// Spending of the session or the game, checked against the cap before every transaction.
+pub struct Budget {
+    cap: Option<u128>,
+    scope: BudgetScope,
+    spent: u128,
+    /// The player allowed to go over the cap until the scope ends.
+    waived: bool,
+}
+
+impl Budget {
+    pub fn new(cap: Option<u128>, scope: BudgetScope) -> Self {
+        Self {
+            cap,
+            scope,
+            spent: 0,
+            waived: false,
+        }
+    }
+
+    /// No cap unless `SPENDING_CAP_ENV` is set, counted per session unless
+    /// `SPENDING_SCOPE_ENV` says `game`.
+    pub fn from_env() -> Self {
+        let scope = match std::env::var(SPENDING_SCOPE_ENV) {
+            Ok(scope) if scope.eq_ignore_ascii_case("game") => BudgetScope::Game,
+            Ok(scope) if !scope.eq_ignore_ascii_case("session") => {
+                tracing::warn!(
+                    "Ignore {}={:?}: not session or game",
+                    SPENDING_SCOPE_ENV,
+                    scope
+                );
+                BudgetScope::Session
+            }
+            _ => BudgetScope::Session,
+        };
+        Self::new(env_number(SPENDING_CAP_ENV), scope)
+    }
+
+    pub fn cap(&self) -> Option<u128> {
+        self.cap
+    }
+
+    pub fn spent(&self) -> u128 {
+        self.spent
+    }
+
+    /// Whether the player has to allow `fee`, as its gas takes the spending over the cap.
+    pub fn needs_confirmation(&self, fee: &FeeEstimate) -> bool {
+        !self.waived
+            && self
+                .cap
+                .map_or(false, |cap| self.spent.saturating_add(fee.gas_value) > cap)
+    }
+
+    pub fn waive(&mut self) {
+        self.waived = true;
+    }
+
+    pub fn record(&mut self, action: String, estimated: u128, spent: u128) -> LedgerEntry {
+        self.spent = self.spent.saturating_add(spent);
+        LedgerEntry {
+            action,
+            estimated,
+            spent,
+            total: self.spent,
+            cap: self.cap,
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|now| now.as_secs()),
+        }
+    }
+
+    /// Starts counting anew if the budget is per game.
+    pub fn start_game(&mut self) {
+        if self.scope == BudgetScope::Game {
+            self.spent = 0;
+            self.waived = false;
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::keystore::Secret;
+use crate::retry::{classify, ErrorClass, RetryPolicy};
+use async_trait::async_trait;
+use gclient::{EventListener, GearApi, WSAddress};
+use gear_connector_api::ConnectorError;
+use gmeta::Encode;
+use gstd::ActorId;
+use homm3_archive_io::ArchiveDescription;
+
+/// What the connector needs from a Gear node. `GearClient` drives one backend
+/// from its own thread, so the futures don't have to be `Send`.
+#[async_trait(?Send)]
+pub trait ChainBackend {
+    /// Connects as the account with secret URI `suri`, the dev account Alice without it,
+    /// and returns the address of the account.
+    async fn connect(
+        &mut self,
+        address: WSAddress,
+        suri: Option<Secret>,
+    ) -> Result<String, ConnectorError>;
+
+    fn disconnect(&mut self);
+
+    /// Actor ID of the connected account.
+    fn actor_id(&self) -> Option<ActorId>;
+
+    async fn program_exists(&mut self, program_id: [u8; 32]) -> bool;
+
+    /// Fails if the node stopped producing blocks, does nothing while not connected.
+    async fn check_health(&mut self) -> Result<(), ConnectorError>;
+
+    async fn free_balance(&mut self) -> Result<u128, ConnectorError>;
+
+    /// Gas the program needs to handle `payload`.
+    async fn calculate_gas(
+        &mut self,
+        program_id: [u8; 32],
+        payload: &[u8],
+    ) -> Result<u64, ConnectorError>;
+
+    /// Sends `payload` to the program and waits for the reply to this very message,
+    /// which is an error if the program panicked. With `resend` the message is sent
+    /// again also when its reply is lost, which only idempotent actions allow.
+    async fn send_message(
+        &mut self,
+        program_id: [u8; 32],
+        payload: Vec<u8>,
+        gas_limit: u64,
+        resend: bool,
+    ) -> Result<Result<Vec<u8>, String>, ConnectorError>;
+
+    /// Saves of the connected account in the archive program, `limit` of them from `offset`.
+    async fn saves_by_owner(
+        &mut self,
+        program_id: [u8; 32],
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<ArchiveDescription>, ConnectorError>;
+}
+
+struct GearConnection {
+    client: GearApi,
+    listener: EventListener,
+}
+
+impl GearConnection {
+    async fn open(address: WSAddress, suri: Option<&Secret>) -> Result<Self, ConnectorError> {
+        let client = match suri {
+            Some(suri) => {
+                tracing::debug!("Init GEAR API, address: {:?}", address);
+                GearApi::init_with(address, suri.expose()).await
+            }
+            None => {
+                tracing::debug!(
+                    "Init GEAR API as default Alice user, address: {:?}",
+                    address
+                );
+                GearApi::init(address).await
+            }
+        }
+        .map_err(|e| ConnectorError::NotConnected(format!("{e}")))?;
+        let listener = client.subscribe().await.map_err(|e| {
+            ConnectorError::NotConnected(format!("can't subscribe to Gear events: {e}"))
+        })?;
+        Ok(Self { client, listener })
+    }
+}
+
+/// Backend talking to a Gear node through gclient.
+pub struct GearApiBackend {
+    connection: Option<GearConnection>,
+    /// Node and account of the connection, to open it again when it breaks.
+    endpoint: Option<(WSAddress, Option<Secret>)>,
+    retry_policy: RetryPolicy,
+}
+
+impl GearApiBackend {
+    pub fn new(retry_policy: RetryPolicy) -> Self {
+        Self {
+            connection: None,
+            endpoint: None,
+            retry_policy,
+        }
+    }
+
+    fn connection(&mut self) -> Result<&mut GearConnection, ConnectorError> {
+        self.connection
+            .as_mut()
+            .ok_or_else(|| ConnectorError::NotConnected("not connected to Gear node".to_string()))
+    }
+
+    /// Replaces a broken connection and its event subscription with new ones. The old
+    /// connection stays if the node is still unreachable, so the next attempt fails again.
+    async fn reconnect(&mut self) {
+        let Some((address, suri)) = self.endpoint.clone() else {
+            return;
+        };
+        match GearConnection::open(address, suri.as_ref()).await {
+            Ok(connection) => {
+                tracing::info!("Reconnected to Gear node");
+                self.connection = Some(connection);
+            }
+            Err(e) => tracing::warn!("Can't reconnect to Gear node: {}", e),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ChainBackend for GearApiBackend {
+    async fn connect(
+        &mut self,
+        address: WSAddress,
+        suri: Option<Secret>,
+    ) -> Result<String, ConnectorError> {
+        let connection = GearConnection::open(address.clone(), suri.as_ref()).await?;
+        let account_id = connection.client.account_id().to_string();
+        self.connection = Some(connection);
+        self.endpoint = Some((address, suri));
+        Ok(account_id)
+    }
+
+    fn disconnect(&mut self) {
+        self.connection = None;
+        self.endpoint = None;
+    }
+
+    fn actor_id(&self) -> Option<ActorId> {
+        let connection = self.connection.as_ref()?;
+        ActorId::from_slice(&connection.client.account_id().encode()).ok()
+    }
+
+    async fn program_exists(&mut self, program_id: [u8; 32]) -> bool {
+        let connection = match self.connection() {
+            Ok(connection) => connection,
+            Err(_) => return false,
+        };
+        match connection.client.read_metahash(program_id.into()).await {
+            Ok(hash) => {
+                tracing::info!("Program hash: {:?}", hash);
+                true
+            }
+            Err(err) => {
+                tracing::error!("Read Metahash Error: {}", err);
+                false
+            }
+        }
+    }
+
+    async fn check_health(&mut self) -> Result<(), ConnectorError> {
+        match self.connection.as_mut() {
+            Some(connection) => connection
+                .listener
+                .blocks_running()
+                .await
+                .map_err(|e| ConnectorError::NotConnected(format!("{e}"))),
+            None => Ok(()),
+        }
+    }
+
+    async fn free_balance(&mut self) -> Result<u128, ConnectorError> {
+        let client = &self.connection()?.client;
+        client
+            .free_balance(client.account_id())
+            .await
+            .map_err(|e| ConnectorError::Chain(format!("Can't read free balance: {e}")))
+    }
+
+    async fn calculate_gas(
+        &mut self,
+        program_id: [u8; 32],
+        payload: &[u8],
+    ) -> Result<u64, ConnectorError> {
+        let policy = self.retry_policy;
+        let mut attempt = 1;
+        loop {
+            let client = &self.connection()?.client;
+            let error = match client
+                .calculate_handle_gas(None, program_id.into(), payload.to_vec(), 0, true)
+                .await
+            {
+                Ok(gas_info) => return Ok(gas_info.min_limit),
+                Err(e) => e,
+            };
+            let class = classify(&error);
+            if class == ErrorClass::Fatal || attempt >= policy.max_attempts {
+                return Err(ConnectorError::Chain(format!(
+                    "can't calculate gas: {error}"
+                )));
+            }
+            tracing::warn!("Can't calculate gas, attempt {}: {}", attempt, error);
+            tokio::time::sleep(policy.delay(attempt)).await;
+            if class == ErrorClass::Reconnect {
+                self.reconnect().await;
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn send_message(
+        &mut self,
+        program_id: [u8; 32],
+        payload: Vec<u8>,
+        gas_limit: u64,
+        resend: bool,
+    ) -> Result<Result<Vec<u8>, String>, ConnectorError> {
+        let policy = self.retry_policy;
+        let mut attempt = 1;
+        loop {
+            let GearConnection { client, listener } = self.connection()?;
+            let (sent, error) =
+                match send_once(client, listener, program_id, &payload, gas_limit).await {
+                    Ok(reply) => return Ok(reply),
+                    Err(failure) => failure,
+                };
+            let class = classify(&error);
+            if class == ErrorClass::Fatal || (sent && !resend) || attempt >= policy.max_attempts {
+                tracing::error!("Can't send message ({:?}): {}", class, error);
+                return Err(ConnectorError::Chain(format!(
+                    "can't send message after {attempt} attempt(s): {error}"
+                )));
+            }
+            let delay = policy.delay(attempt);
+            tracing::warn!(
+                "Attempt {}/{} to send message failed: {}, retry in {:?}",
+                attempt,
+                policy.max_attempts,
+                error,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            if class == ErrorClass::Reconnect {
+                self.reconnect().await;
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn saves_by_owner(
+        &mut self,
+        program_id: [u8; 32],
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<ArchiveDescription>, ConnectorError> {
+        let actor_id = self.actor_id().ok_or_else(|| {
+            ConnectorError::NotConnected("not connected to Gear node".to_string())
+        })?;
+        let client = &self.connection()?.client;
+        client
+            .read_state_using_wasm::<_, Vec<ArchiveDescription>>(
+                program_id.into(),
+                "saves_by_owner",
+                homm3_archive_state::WASM_BINARY.to_vec(),
+                Some((actor_id, offset, limit)),
+            )
+            .await
+            .map_err(|e| ConnectorError::Chain(format!("Can't read saves of the account: {e}")))
+    }
+}
+
+/// One attempt of `send_message`. A failure tells whether the message reached the chain.
+async fn send_once(
+    client: &GearApi,
+    listener: &mut EventListener,
+    program_id: [u8; 32],
+    payload: &[u8],
+    gas_limit: u64,
+) -> Result<Result<Vec<u8>, String>, (bool, gclient::Error)> {
+    let (message_id, _) = client
+        .send_message_bytes(program_id.into(), payload, gas_limit, 0)
+        .await
+        .map_err(|e| (false, e))?;
+    tracing::info!("Sent message {:?} to Gear", message_id);
+
+    let (_, reply) = listener
+        .reply_bytes_on(message_id)
+        .await
+        .map_err(|e| (true, e))?;
+    Ok(reply)
+}
@@ -0,0 +1,261 @@
+use crate::chain::ChainBackend;
+use crate::keystore::Secret;
+use async_trait::async_trait;
+use gclient::WSAddress;
+use gear_connector_api::ConnectorError;
+use gmeta::{Decode, Encode};
+use gstd::ActorId;
+use homm3_archive_io::{Action as ArchiveAction, Archive, ArchiveDescription};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+pub const FAKE_ARCHIVE_PROGRAM: [u8; 32] = [1; 32];
+pub const FAKE_GAME_STATE_PROGRAM: [u8; 32] = [2; 32];
+pub const FAKE_BATTLE_PROGRAM: [u8; 32] = [3; 32];
+/// Free balance of every account when it first connects.
+pub const FAKE_INITIAL_BALANCE: u128 = 1_000_000_000_000_000;
+
+const ARCHIVE_GAS: u64 = 1_000_000_000;
+const GAME_STATE_GAS: u64 = 5_000_000_000;
+const BATTLE_GAS: u64 = 10_000_000_000;
+/// Secret URI of the dev account, used when `connect` gets none.
+const ALICE: &str = "//Alice";
+
+/// Game state as the fake game state program keeps it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeGameState {
+    pub saver_id: ActorId,
+    pub day: u32,
+    pub current_player: String,
+    pub players: usize,
+}
+
+#[derive(Default)]
+struct FakeState {
+    balances: HashMap<ActorId, u128>,
+    /// Runs the rules of the archive program with its default limits.
+    archive: Archive,
+    game_states: Vec<FakeGameState>,
+    battles: u32,
+}
+
+/// In-memory chain running stand-ins of the archive, game state and battle programs,
+/// for tests without a Gear node. Clones share the chain, so a test can keep one
+/// to look at what the connector did. Every message burns its whole gas limit.
+#[derive(Clone, Default)]
+pub struct FakeChain {
+    state: Arc<Mutex<FakeState>>,
+    account: Option<ActorId>,
+}
+
+impl FakeChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Actor ID the account with secret URI `suri` gets, Alice without it.
+    pub fn account_of(suri: Option<&str>) -> ActorId {
+        let mut hasher = DefaultHasher::new();
+        suri.unwrap_or(ALICE).hash(&mut hasher);
+        let mut id = [0u8; 32];
+        for chunk in id.chunks_mut(8) {
+            chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+            hasher.write_u8(0);
+        }
+        ActorId::new(id)
+    }
+
+    pub fn saves_of(&self, owner: ActorId) -> Vec<ArchiveDescription> {
+        let state = self.state.lock().expect("Panic in another thread");
+        state.archive.saves.get(&owner).cloned().unwrap_or_default()
+    }
+
+    pub fn game_states(&self) -> Vec<FakeGameState> {
+        let state = self.state.lock().expect("Panic in another thread");
+        state.game_states.clone()
+    }
+
+    pub fn battles(&self) -> u32 {
+        self.state.lock().expect("Panic in another thread").battles
+    }
+
+    pub fn balance_of(&self, account: ActorId) -> u128 {
+        let state = self.state.lock().expect("Panic in another thread");
+        state
+            .balances
+            .get(&account)
+            .copied()
+            .unwrap_or(FAKE_INITIAL_BALANCE)
+    }
+
+    fn account(&self) -> Result<ActorId, ConnectorError> {
+        self.account
+            .ok_or_else(|| ConnectorError::NotConnected("not connected to Gear node".to_string()))
+    }
+}
+
+#[async_trait(?Send)]
+impl ChainBackend for FakeChain {
+    async fn connect(
+        &mut self,
+        address: WSAddress,
+        suri: Option<Secret>,
+    ) -> Result<String, ConnectorError> {
+        tracing::debug!("Connect to fake chain instead of {:?}", address);
+        let account = Self::account_of(suri.as_ref().map(Secret::expose));
+        self.state
+            .lock()
+            .expect("Panic in another thread")
+            .balances
+            .entry(account)
+            .or_insert(FAKE_INITIAL_BALANCE);
+        self.account = Some(account);
+        Ok(format!("0x{}", hex::encode(account.encode())))
+    }
+
+    fn disconnect(&mut self) {
+        self.account = None;
+    }
+
+    fn actor_id(&self) -> Option<ActorId> {
+        self.account
+    }
+
+    async fn program_exists(&mut self, program_id: [u8; 32]) -> bool {
+        gas_of(program_id).is_some()
+    }
+
+    async fn check_health(&mut self) -> Result<(), ConnectorError> {
+        Ok(())
+    }
+
+    async fn free_balance(&mut self) -> Result<u128, ConnectorError> {
+        Ok(self.balance_of(self.account()?))
+    }
+
+    async fn calculate_gas(
+        &mut self,
+        program_id: [u8; 32],
+        _payload: &[u8],
+    ) -> Result<u64, ConnectorError> {
+        self.account()?;
+        gas_of(program_id).ok_or_else(|| program_not_found(program_id))
+    }
+
+    async fn send_message(
+        &mut self,
+        program_id: [u8; 32],
+        payload: Vec<u8>,
+        gas_limit: u64,
+        _resend: bool,
+    ) -> Result<Result<Vec<u8>, String>, ConnectorError> {
+        let account = self.account()?;
+        let gas = gas_of(program_id).ok_or_else(|| program_not_found(program_id))?;
+        let mut state = self.state.lock().expect("Panic in another thread");
+        let balance = state
+            .balances
+            .entry(account)
+            .or_insert(FAKE_INITIAL_BALANCE);
+        if *balance < gas_limit as u128 {
+            return Err(ConnectorError::Chain(
+                "can't send message: not enough balance".to_string(),
+            ));
+        }
+        *balance -= gas_limit as u128;
+        if gas_limit < gas {
+            return Ok(Err("Ran out of gas".to_string()));
+        }
+
+        let input = &mut payload.as_slice();
+        let decode_error = |e: parity_scale_codec::Error| format!("Unable to decode Action: {e}");
+        let reply = match program_id {
+            FAKE_ARCHIVE_PROGRAM => ArchiveAction::decode(input)
+                .map_err(decode_error)
+                .map(|action| state.archive.handle(account, action).encode()),
+            FAKE_GAME_STATE_PROGRAM => homm3_gamestate_io::Action::decode(input)
+                .map_err(decode_error)
+                .map(|action| state.handle_game_state(action)),
+            _ => homm3_battle_io::Action::decode(input)
+                .map_err(decode_error)
+                .and_then(|action| state.handle_battle(action))
+                .map(|event| event.encode()),
+        };
+        Ok(reply)
+    }
+
+    async fn saves_by_owner(
+        &mut self,
+        program_id: [u8; 32],
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<ArchiveDescription>, ConnectorError> {
+        if program_id != FAKE_ARCHIVE_PROGRAM {
+            return Err(program_not_found(program_id));
+        }
+        let saves = self.saves_of(self.account()?);
+        Ok(saves
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+}
+
+impl FakeState {
+    /// Keeps the game state. The reply carries nothing the connector reads, so it is empty.
+    fn handle_game_state(&mut self, action: homm3_gamestate_io::Action) -> Vec<u8> {
+        #[allow(unreachable_patterns)]
+        match action {
+            homm3_gamestate_io::Action::SaveGameState {
+                saver_id,
+                day,
+                current_player,
+                player_states,
+            } => self.game_states.push(FakeGameState {
+                saver_id,
+                day,
+                current_player,
+                players: player_states.len(),
+            }),
+            action => tracing::warn!("Fake game state program ignores {:?}", action),
+        }
+        Vec::new()
+    }
+
+    /// Plays one round in which every stack loses a quarter of its creatures.
+    fn handle_battle(
+        &mut self,
+        action: homm3_battle_io::Action,
+    ) -> Result<homm3_battle_io::Event, String> {
+        #[allow(unreachable_patterns)]
+        let mut battle_info = match action {
+            homm3_battle_io::Action::Simulate(battle_info) => battle_info,
+            action => return Err(format!("Fake battle program can't handle {action:?}")),
+        };
+        self.battles += 1;
+        for stack in battle_info.stacks.iter_mut() {
+            stack.count -= stack.count / 4;
+        }
+        battle_info.round += 1;
+        Ok(homm3_battle_io::Event::BattleResult(battle_info))
+    }
+}
+
+fn gas_of(program_id: [u8; 32]) -> Option<u64> {
+    match program_id {
+        FAKE_ARCHIVE_PROGRAM => Some(ARCHIVE_GAS),
+        FAKE_GAME_STATE_PROGRAM => Some(GAME_STATE_GAS),
+        FAKE_BATTLE_PROGRAM => Some(BATTLE_GAS),
+        _ => None,
+    }
+}
+
+fn program_not_found(program_id: [u8; 32]) -> ConnectorError {
+    ConnectorError::Chain(format!(
+        "program 0x{} is not on the fake chain",
+        hex::encode(program_id)
+    ))
+}
@@ -1,10 +1,13 @@
+use crate::budget::{FeeEstimate, FeeEstimator};
+use crate::chain::{ChainBackend, GearApiBackend};
+use crate::keystore::Secret;
+use crate::retry::RetryPolicy;
 use crate::utils::convert_state;
 use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
-use gclient::{EventListener, GearApi, WSAddress};
-use gear_connector_api::PlayerState;
-use gmeta::Encode;
-use gstd::ActorId;
-use homm3_archive_io::{Action as ArchiveAction, ArchiveDescription, GameArchive};
+use gclient::WSAddress;
+use gear_connector_api::{ConnectorError, PlayerState};
+use gmeta::{Decode, Encode};
+use homm3_archive_io::{Action as ArchiveAction, ArchiveDescription, ArchiveError, Event};
 use homm3_battle_io::BattleInfo;
 use homm3_gamestate_io::PlayerState as IoPlayerState;
 use std::{
@@ -16,6 +19,8 @@ use std::{
 };
 
 pub const RECV_TIMEOUT: Duration = std::time::Duration::from_millis(1);
+/// How many saves are read from the archive program at once.
+const SAVES_PAGE_LEN: u32 = 32;
 
 #[derive(Debug)]
 pub enum GearCommand {
@@ -24,11 +29,24 @@ pub enum GearCommand {
         program_id: String,
         meta_program_id: String,
         battle_program_id: String,
-        account_id: String,
-        password: String,
+        /// Secret URI of the account, the dev account Alice if it is `None`.
+        suri: Option<Secret>,
     },
     GetFreeBalance,
     SaveArchive(ArchiveDescription),
+    /// Removes the archives of the account with IPFS hash `hash`.
+    DeleteArchive {
+        hash: String,
+    },
+    RenameArchive {
+        hash: String,
+        filename: String,
+    },
+    /// Puts `archive` in place of the archives of the account with IPFS hash `hash`.
+    ReplaceArchive {
+        hash: String,
+        archive: ArchiveDescription,
+    },
     SaveGameState {
         day: u32,
         current_player: String,
@@ -37,48 +55,99 @@ pub enum GearCommand {
     SendAction(ArchiveAction),
     SimulateBattle(BattleInfo),
     GetSavedGames,
+    /// Answer to `GearReply::Fee`: whether the message may be sent.
+    ApproveFee(bool),
 }
 
 #[derive(Debug)]
 pub enum GearReply {
-    Connected { username: String },
+    Connected {
+        username: String,
+    },
     NotConnected(String),
-    ProgramNotFound { program_id: String },
+    ProgramNotFound {
+        program_id: String,
+    },
     Simulated(homm3_battle_io::Event),
-    Saved(homm3_archive_io::Event),
+    Saved(Event),
     FreeBalance(u128),
-    SavedGames(Vec<GameArchive>),
+    SavedGames(Vec<ArchiveDescription>),
+    StateSaved,
+    /// Sent before a message, which waits for `GearCommand::ApproveFee`.
+    Fee(FeeEstimate),
+    /// Sent after a message: how much the free balance went down.
+    Spent {
+        action: String,
+        estimated: u128,
+        spent: u128,
+    },
+    Error(ConnectorError),
 }
 
-pub struct GearConnection {
-    client: GearApi,
-    listener: EventListener,
-    program_id: [u8; 32],
-    meta_program_id: [u8; 32],
-    battle_program_id: [u8; 32],
+/// Programs the game uses on the connected node.
+#[derive(Debug, Clone, Copy)]
+struct Programs {
+    archive: [u8; 32],
+    game_state: [u8; 32],
+    battle: [u8; 32],
 }
 
-pub struct GearClient {
+/// Account the client is connected with.
+#[derive(Clone)]
+struct Account {
+    suri: Option<Secret>,
+    username: String,
+}
+
+impl Account {
+    fn is(&self, suri: Option<&Secret>) -> bool {
+        self.suri.as_ref().map(Secret::expose) == suri.map(Secret::expose)
+    }
+}
+
+/// Sends the messages of Logic to the chain. It is made on the thread which runs it.
+pub struct GearClient<B: ChainBackend = GearApiBackend> {
     need_stop: Arc<AtomicBool>,
     gear_reply_sender: Sender<GearReply>,
     gear_command_receiver: Receiver<GearCommand>,
-    gear_connection: Arc<RwLock<Option<GearConnection>>>,
+    backend: RwLock<B>,
+    /// Set once connected.
+    programs: RwLock<Option<Programs>>,
+    /// Set once connected.
+    account: RwLock<Option<Account>>,
+    fee_estimator: FeeEstimator,
 }
 
-unsafe impl Send for GearConnection {}
-unsafe impl Send for GearClient {}
-
 impl GearClient {
     pub fn new(
         need_stop: Arc<AtomicBool>,
         gear_command_receiver: Receiver<GearCommand>,
         gear_reply_sender: Sender<GearReply>,
+    ) -> Self {
+        Self::with_backend(
+            need_stop,
+            gear_command_receiver,
+            gear_reply_sender,
+            GearApiBackend::new(RetryPolicy::from_env()),
+        )
+    }
+}
+
+impl<B: ChainBackend> GearClient<B> {
+    pub fn with_backend(
+        need_stop: Arc<AtomicBool>,
+        gear_command_receiver: Receiver<GearCommand>,
+        gear_reply_sender: Sender<GearReply>,
+        backend: B,
     ) -> Self {
         Self {
             need_stop,
             gear_reply_sender,
             gear_command_receiver,
-            gear_connection: Arc::new(RwLock::new(None)),
+            backend: RwLock::new(backend),
+            programs: RwLock::new(None),
+            account: RwLock::new(None),
+            fee_estimator: FeeEstimator::from_env(),
         }
     }
 
@@ -97,12 +166,10 @@ impl GearClient {
                     }
                 }
 
-                if let Some(connection) = self.gear_connection.write().unwrap().as_mut() {
-                    if let Err(e) = connection.listener.blocks_running().await {
-                        self.gear_reply_sender
-                            .send(GearReply::NotConnected(format!("{e}")))
-                            .expect("Cant' send");
-                    }
+                if let Err(e) = self.backend().check_health().await {
+                    self.gear_reply_sender
+                        .send(GearReply::NotConnected(e.to_string()))
+                        .expect("Cant' send");
                 }
 
                 std::thread::sleep(std::time::Duration::from_millis(1));
@@ -110,133 +177,125 @@ impl GearClient {
         });
     }
 
+    fn backend(&self) -> std::sync::RwLockWriteGuard<'_, B> {
+        self.backend.write().expect("Error in another thread")
+    }
+
+    fn programs(&self) -> Result<Programs, ConnectorError> {
+        self.programs
+            .read()
+            .expect("Error in another thread")
+            .ok_or_else(|| ConnectorError::NotConnected("not connected to Gear node".to_string()))
+    }
+
     async fn simulate_battle(&self, battle_info: BattleInfo) {
-        let mut guard = self
-            .gear_connection
-            .write()
-            .expect("Error in another thread");
-        if let Some(GearConnection {
-            client,
-            program_id: _,
-            listener,
-            meta_program_id: _,
-            battle_program_id,
-        }) = guard.as_mut()
-        {
-            let program_id = (*battle_program_id).into();
-
-            let action = homm3_battle_io::Action::Simulate(battle_info);
-            let gas_limit = client
-                .calculate_handle_gas(None, program_id, action.encode(), 0, true)
-                .await
-                .expect("Can't calculate gas for Action::Save")
-                .min_limit;
-            tracing::info!("Gas limit {} for Action {:?}", gas_limit, action);
-
-            send_message(client, listener,*battle_program_id, action).await;
-
-            let mut battle_infos: Vec<BattleInfo> = client
-                .read_state(program_id)
-                .await
-                .expect("Can't read state");
-            tracing::debug!("battle infos: {:?}", battle_infos);
-            if let Some(last) = battle_infos.pop() {
-                let reply = GearReply::Simulated(homm3_battle_io::Event::BattleResult(last));
-                self.gear_reply_sender
-                    .send(reply)
-                    .expect("Panic in another thread");
+        let action = homm3_battle_io::Action::Simulate(battle_info);
+        let reply = match self.programs() {
+            Ok(programs) => {
+                match self
+                    .transact(programs.battle, "SimulateBattle", action, true)
+                    .await
+                {
+                    Ok(event) => GearReply::Simulated(event),
+                    Err(e) => GearReply::Error(e),
+                }
             }
-        }
+            Err(e) => GearReply::Error(e),
+        };
+        self.gear_reply_sender
+            .send(reply)
+            .expect("Panic in another thread");
     }
 
     async fn get_saved_games(&self) {
-        let guard = self
-            .gear_connection
-            .read()
-            .expect("Error in another thread");
-        if let Some(GearConnection {
-            client,
-            program_id,
-            listener: _,
-            meta_program_id: _,
-            battle_program_id: _,
-        }) = guard.as_ref()
-        {
-            let program_id = (*program_id).into();
-            let actor_id = client.account_id().encode();
-            let actor_id = ActorId::from_slice(&actor_id).unwrap();
-            let saved_games: Vec<GameArchive> = client
-                .read_state(program_id)
-                .await
-                .expect("Can't read state");
-            let saved_games: Vec<GameArchive> = saved_games
-                .into_iter()
-                .filter(|state| state.saver_id.eq(&actor_id))
-                .collect();
-            tracing::debug!(
-                "For ActorId: {:?} len: {}, saved_games: {:?}",
-                actor_id,
-                saved_games.len(),
-                saved_games
-            );
-            self.gear_reply_sender
-                .send(GearReply::SavedGames(saved_games))
-                .expect("Panic in another thread");
-        } else {
-            unreachable!("Not connected to blockchain");
+        let reply = match self.programs() {
+            Ok(programs) => match self.read_saved_games(programs.archive).await {
+                Ok(saved_games) => GearReply::SavedGames(saved_games),
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    GearReply::Error(e)
+                }
+            },
+            Err(e) => GearReply::Error(e),
+        };
+        self.gear_reply_sender
+            .send(reply)
+            .expect("Panic in another thread");
+    }
+
+    /// Only the saves of the account are read, page by page.
+    async fn read_saved_games(
+        &self,
+        program_id: [u8; 32],
+    ) -> Result<Vec<ArchiveDescription>, ConnectorError> {
+        let mut backend = self.backend();
+        let mut saved_games = Vec::new();
+        loop {
+            let page = backend
+                .saves_by_owner(program_id, saved_games.len() as u32, SAVES_PAGE_LEN)
+                .await?;
+            let last_page = (page.len() as u32) < SAVES_PAGE_LEN;
+            saved_games.extend(page);
+            if last_page {
+                tracing::debug!(
+                    "For ActorId: {:?} len: {}, saved_games: {:?}",
+                    backend.actor_id(),
+                    saved_games.len(),
+                    saved_games
+                );
+                return Ok(saved_games);
+            }
         }
     }
 
     async fn get_free_balance(&self) {
-        let guard = self
-            .gear_connection
-            .read()
-            .expect("Error in another thread");
-        if let Some(GearConnection {
-            client,
-            program_id: _,
-            listener: _,
-            meta_program_id: _,
-            battle_program_id: _,
-        }) = guard.as_ref()
-        {
-            let free_balance = client.free_balance(client.account_id()).await.unwrap();
-            self.gear_reply_sender
-                .send(GearReply::FreeBalance(free_balance))
-                .expect("Panic in another thread");
-        } else {
-            unreachable!("Not connected to blockchain");
-        }
+        let reply = match self.backend().free_balance().await {
+            Ok(free_balance) => GearReply::FreeBalance(free_balance),
+            Err(e) => GearReply::Error(e),
+        };
+        self.gear_reply_sender
+            .send(reply)
+            .expect("Panic in another thread");
     }
 
     async fn save_game_archive(&self, archive: ArchiveDescription) {
-        let mut guard = self
-            .gear_connection
-            .write()
-            .expect("Error in another thread");
         tracing::debug!("Save to Chain: {:?}", archive);
-        if let Some(GearConnection {
-            client,
-            program_id,
-            meta_program_id: _,
-            listener,
-            battle_program_id: _,
-        }) = guard.as_mut()
-        {
-            let pid = *program_id;
-            let account_id = ActorId::from_slice(&client.account_id().encode()).unwrap();
-
-            let action = ArchiveAction::SaveArchive(GameArchive {
-                saver_id: account_id,
-                archive,
-            });
-            send_message(client, listener, pid, action).await;
-            self.gear_reply_sender
-                .send(GearReply::Saved(homm3_archive_io::Event::SavedArchive))
-                .unwrap();
-        } else {
-            tracing::warn!("Can't connect to Gear Blockchain Node")
-        }
+        let reply = match self.programs() {
+            Ok(programs) => {
+                let action = ArchiveAction::SaveArchive(archive);
+                archive_reply(
+                    self.transact(programs.archive, "SaveArchive", action, true)
+                        .await,
+                )
+            }
+            Err(e) => {
+                tracing::warn!("Can't connect to Gear Blockchain Node");
+                GearReply::Error(e)
+            }
+        };
+        self.gear_reply_sender
+            .send(reply)
+            .expect("Panic in another thread");
+    }
+
+    /// Sends an action changing the archives of the account.
+    async fn change_archives(&self, action: ArchiveAction) {
+        tracing::debug!("Change archives on Chain: {:?}", action);
+        let reply = match self.programs() {
+            Ok(programs) => {
+                let name = match &action {
+                    ArchiveAction::Delete { .. } => "DeleteArchive",
+                    ArchiveAction::Rename { .. } => "RenameArchive",
+                    ArchiveAction::Replace { .. } => "ReplaceArchive",
+                    _ => "ArchiveAction",
+                };
+                archive_reply(self.transact(programs.archive, name, action, true).await)
+            }
+            Err(e) => GearReply::Error(e),
+        };
+        self.gear_reply_sender
+            .send(reply)
+            .expect("Panic in another thread");
     }
 
     async fn save_game_state(
@@ -245,38 +304,92 @@ impl GearClient {
         current_player: String,
         player_states: Vec<PlayerState>,
     ) {
-        let mut guard = self
-            .gear_connection
-            .write()
-            .expect("Error in another thread");
         tracing::debug!(
             "Save to Chain: day: {:?}, curreny_player: {:?}",
             day,
             current_player
         );
-        if let Some(GearConnection {
-            client,
-            program_id: _,
-            meta_program_id,
-            battle_program_id: _,
-            listener,
-        }) = guard.as_mut()
-        {
-            let pid = *meta_program_id;
-            let player_states: Vec<IoPlayerState> = player_states
-                .into_iter()
-                .map(|state| convert_state(state))
-                .collect();
-            let actor_id = client.account_id().encode();
-            let actor_id = ActorId::from_slice(&actor_id).unwrap();
-            let action = homm3_gamestate_io::Action::SaveGameState {
-                saver_id: actor_id,
-                day,
-                current_player,
-                player_states,
-            };
-            send_message(client, listener, pid, action).await;
+        let saver_id = self.backend().actor_id();
+        let reply = match (self.programs(), saver_id) {
+            (Ok(programs), Some(saver_id)) => {
+                let player_states: Vec<IoPlayerState> = player_states
+                    .into_iter()
+                    .map(|state| convert_state(state))
+                    .collect();
+                let action = homm3_gamestate_io::Action::SaveGameState {
+                    saver_id,
+                    day,
+                    current_player,
+                    player_states,
+                };
+                // The reply carries nothing the connector uses, so it isn't decoded
+                match self
+                    .transact::<()>(programs.game_state, "SaveGameState", action, false)
+                    .await
+                {
+                    Ok(_) => GearReply::StateSaved,
+                    Err(e) => GearReply::Error(e),
+                }
+            }
+            (Err(e), _) => GearReply::Error(e),
+            (_, None) => GearReply::Error(ConnectorError::NotConnected(
+                "not connected to Gear node".to_string(),
+            )),
+        };
+        self.gear_reply_sender
+            .send(reply)
+            .expect("Panic in another thread");
+    }
+
+    async fn connect_to_node(
+        &self,
+        address: WSAddress,
+        program_ids: [String; 3],
+        suri: Option<Secret>,
+    ) -> GearReply {
+        let mut backend = self.backend();
+        let username = match backend.connect(address, suri.clone()).await {
+            Ok(username) => username,
+            Err(e) => {
+                tracing::error!("Gear connect Error: {}", e);
+                return GearReply::NotConnected(e.to_string());
+            }
+        };
+
+        let mut programs = [[0u8; 32]; 3];
+        for (program, program_id) in programs.iter_mut().zip(program_ids) {
+            match parse_program_id(&program_id) {
+                Some(id) if backend.program_exists(id).await => *program = id,
+                _ => {
+                    backend.disconnect();
+                    return GearReply::ProgramNotFound { program_id };
+                }
+            }
         }
+        let [archive, game_state, battle] = programs;
+        self.programs
+            .write()
+            .expect("Error in another thread")
+            .replace(Programs {
+                archive,
+                game_state,
+                battle,
+            });
+
+        match backend.free_balance().await {
+            Ok(free_balance) => {
+                tracing::info!("Available Balance for {}: {}", username, free_balance)
+            }
+            Err(e) => tracing::warn!("Can't read the balance of {}: {}", username, e),
+        }
+        self.account
+            .write()
+            .expect("Error in another thread")
+            .replace(Account {
+                suri,
+                username: username.clone(),
+            });
+        GearReply::Connected { username }
     }
 
     async fn process_command(&self, command: GearCommand) {
@@ -286,83 +399,63 @@ impl GearClient {
                 program_id,
                 meta_program_id,
                 battle_program_id,
-                account_id,
-                password,
+                suri,
             } => {
                 tracing::info!(
-                    "Process GUI command ConnectToNode address: {:?}, Program ID: {}, mnemonic_phrase: {}, password: {}",
+                    "Process GUI command ConnectToNode address: {:?}, Program ID: {}",
                     address,
-                    program_id,
-                    account_id,
-                    password
+                    program_id
                 );
-                let mut guard = self
-                    .gear_connection
-                    .write()
-                    .expect("Error in another thread");
-                if guard.is_none() {
-                    let client = if account_id.is_empty() {
-                        tracing::debug!(
-                            "Init GEAR API as default Alice user, address: {:?}",
-                            address
-                        );
-                        GearApi::init(address).await
-                    } else {
-                        let suri = account_id;
-                        tracing::debug!("Init GEAR API as {}, address: {:?}", &suri, address);
-                        GearApi::init_with(address, suri).await
-                    };
-                    match client {
-                        Ok(client) => {
-                            let (ok, program_id) = self.read_metahash(program_id, &client).await;
-                            if ok {
-                                let (ok, meta_program_id) =
-                                    self.read_metahash(meta_program_id, &client).await;
-                                if ok {
-                                    let (ok, battle_program_id) =
-                                        self.read_metahash(battle_program_id, &client).await;
-                                    if ok {
-                                        let gear_connection = GearConnection {
-                                            client: client.clone(),
-                                            listener: client.subscribe().await.unwrap(),
-                                            program_id,
-                                            meta_program_id,
-                                            battle_program_id,
-                                        };
-                                        let account_id =
-                                            gear_connection.client.account_id().clone();
-                                        let free_balance =
-                                            client.free_balance(client.account_id()).await.unwrap();
-                                        tracing::info!(
-                                            "Available Balance for {:?}: {}",
-                                            account_id,
-                                            free_balance
-                                        );
-                                        guard.replace(gear_connection);
-
-                                        self.gear_reply_sender
-                                            .send(GearReply::Connected {
-                                                username: account_id.to_string(),
-                                            })
-                                            .expect("Panic in another thread");
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Gear connect Error: {}", e);
-                            self.gear_reply_sender
-                                .send(GearReply::NotConnected(format!("{e}")))
-                                .expect("Panic in another thread");
-                        }
+                let account = self
+                    .account
+                    .read()
+                    .expect("Error in another thread")
+                    .clone();
+                let reply = match account {
+                    None => {
+                        let program_ids = [program_id, meta_program_id, battle_program_id];
+                        self.connect_to_node(address, program_ids, suri).await
                     }
-                }
+                    Some(account) if account.is(suri.as_ref()) => GearReply::Connected {
+                        username: account.username,
+                    },
+                    // Logic keys the saves by the account, it can't change under it
+                    Some(account) => GearReply::Error(ConnectorError::NotConnected(format!(
+                        "already connected as {}, restart gear-connector to use another account",
+                        account.username
+                    ))),
+                };
+                self.gear_reply_sender
+                    .send(reply)
+                    .expect("Panic in another thread");
             }
-            GearCommand::SendAction(_action) => unreachable!("Shouldn't process action"),
+            GearCommand::SendAction(action) => self
+                .gear_reply_sender
+                .send(GearReply::Error(ConnectorError::Internal(format!(
+                    "{action:?} can't be sent directly"
+                ))))
+                .expect("Panic in another thread"),
+            GearCommand::ApproveFee(_) => self
+                .gear_reply_sender
+                .send(GearReply::Error(ConnectorError::Internal(
+                    "no fee waits for approval".to_string(),
+                )))
+                .expect("Panic in another thread"),
             GearCommand::SimulateBattle(battle_info) => self.simulate_battle(battle_info).await,
             GearCommand::GetFreeBalance => self.get_free_balance().await,
             GearCommand::GetSavedGames => self.get_saved_games().await,
             GearCommand::SaveArchive(archive) => self.save_game_archive(archive).await,
+            GearCommand::DeleteArchive { hash } => {
+                self.change_archives(ArchiveAction::Delete { hash }).await
+            }
+            GearCommand::RenameArchive { hash, filename } => {
+                self.change_archives(ArchiveAction::Rename { hash, filename })
+                    .await
+            }
+            GearCommand::ReplaceArchive { hash, archive } => {
+                self.change_archives(ArchiveAction::Replace { hash, archive })
+                    .await
+            }
             GearCommand::SaveGameState {
                 day,
                 current_player,
@@ -374,62 +467,90 @@ impl GearClient {
         }
     }
 
-    async fn read_metahash(&self, program_id: String, client: &GearApi) -> (bool, [u8; 32]) {
-        let pid = hex::decode(&program_id[2..]).expect("Can't decode Program ID");
-        let mut program_id = [0u8; 32];
-        program_id.copy_from_slice(&pid);
+    /// Sends `payload` once its fee is approved, waits for the reply
+    /// and reports what it cost.
+    async fn transact<R: Decode>(
+        &self,
+        program_id: [u8; 32],
+        action: &str,
+        payload: impl Encode + gstd::fmt::Debug,
+        resend: bool,
+    ) -> Result<R, ConnectorError> {
+        let mut backend = self.backend();
+        let encoded = payload.encode();
+        let gas_limit = backend
+            .calculate_gas(program_id, &encoded)
+            .await
+            .map_err(|e| ConnectorError::Chain(format!("{action}: {e}")))?;
+        let fee = self.fee_estimator.estimate(action, gas_limit);
+        let estimated = fee.gas_value;
+        self.approve_fee(fee)?;
 
-        match client.read_metahash(program_id.into()).await {
-            Ok(hash) => {
-                tracing::info!("Program hash: {:?}", hash);
-                (true, program_id)
-            }
-            Err(err) => {
-                tracing::error!("Read Metahash Error: {}", err);
-                self.gear_reply_sender
-                    .send(GearReply::ProgramNotFound {
-                        program_id: hex::encode(&program_id),
-                    })
-                    .expect("Error in another thread");
-                (false, [0u8; 32])
+        tracing::info!("Send Action to Gear: {:?}", payload);
+        let balance = backend.free_balance().await;
+        let result = backend
+            .send_message(program_id, encoded, gas_limit, resend)
+            .await;
+        // Gas left unused is returned by the time of the reply, so the difference is the cost
+        match (balance, backend.free_balance().await) {
+            (Ok(before), Ok(after)) => self
+                .gear_reply_sender
+                .send(GearReply::Spent {
+                    action: action.to_string(),
+                    estimated,
+                    spent: before.saturating_sub(after),
+                })
+                .expect("Panic in another thread"),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::warn!("Can't tell how much {} cost: {}", action, e)
             }
         }
+
+        let reply = result?.map_err(|e| {
+            ConnectorError::Chain(format!("program failed to handle {payload:?}: {e}"))
+        })?;
+        R::decode(&mut reply.as_slice())
+            .map_err(|e| ConnectorError::Codec(format!("invalid reply to {payload:?}: {e}")))
     }
-}
 
-async fn send_message(
-    client: &GearApi,
-    listener: &mut EventListener,
-    program_id: [u8; 32],
-    payload: impl Encode + gstd::fmt::Debug,
-) {
-    let program_id = program_id.into();
-
-    let gas_limit = client
-        .calculate_handle_gas(None, program_id, payload.encode(), 0, true)
-        .await
-        .expect("Can't calculate gas for Action::Save")
-        .min_limit;
-    tracing::info!("Gas limit {} for Action {:?}", gas_limit, payload);
-
-    for _ in 0..10 {
-        if let Err(e) = client
-            .send_message(program_id, &payload, gas_limit, 0)
-            .await
-        {
-            if let gclient::Error::GearSDK(err) = e {
-                if let gsdk::Error::Tx(error) = err {
-                    if let gsdk::result::TxError::Retracted(_) = error {
-                        listener.blocks_running().await.expect("Block running ");
-                        continue;
-                    }
-                }
-            } else {
-                panic!("Can't send {:?} error: {}", &payload, e);
-            }
-        } else {
-            tracing::info!("Sent Action to Gear: {:?}", payload);
-            break;
+    /// Asks Logic whether `fee` may be spent.
+    fn approve_fee(&self, fee: FeeEstimate) -> Result<(), ConnectorError> {
+        tracing::info!(
+            "Fee of {}: {} gas, about {} without the transaction fee",
+            fee.action,
+            fee.gas,
+            fee.gas_value
+        );
+        let action = fee.action.clone();
+        self.gear_reply_sender
+            .send(GearReply::Fee(fee))
+            .expect("Panic in another thread");
+        match self.gear_command_receiver.recv() {
+            Ok(GearCommand::ApproveFee(true)) => Ok(()),
+            Ok(GearCommand::ApproveFee(false)) => Err(ConnectorError::Chain(format!(
+                "{action} isn't sent, its fee is declined"
+            ))),
+            Ok(command) => Err(ConnectorError::Internal(format!(
+                "{command:?} while the fee of {action} waits for approval"
+            ))),
+            Err(e) => Err(ConnectorError::Internal(format!("Logic is down: {e}"))),
         }
     }
 }
+
+/// Program ID in hex, with or without the `0x` prefix.
+fn parse_program_id(program_id: &str) -> Option<[u8; 32]> {
+    let hex_id = program_id.trim();
+    let hex_id = hex_id.strip_prefix("0x").unwrap_or(hex_id);
+    hex::decode(hex_id).ok()?.try_into().ok()
+}
+
+fn archive_reply(reply: Result<Result<Event, ArchiveError>, ConnectorError>) -> GearReply {
+    match reply {
+        Ok(Ok(event)) => GearReply::Saved(event),
+        Ok(Err(e)) => GearReply::Error(ConnectorError::Chain(format!(
+            "archive program rejected the action: {e:?}"
+        ))),
+        Err(e) => GearReply::Error(e),
+    }
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+use std::cell::Cell;
+use tauri::{LogicalSize, PhysicalSize, Size, Window};
+use tauri_plugin_positioner::{Position, WindowExt};
+
+/// Windows of the connector as `Logic` sees them: the lobby window, called main,
+/// and the log window, which stays at the side of the screen during a game.
+pub trait Gui {
+    fn emit_main<S: Serialize + Clone>(&self, event: &str, payload: S);
+    fn emit_log<S: Serialize + Clone>(&self, event: &str, payload: S);
+    /// Brings the lobby window up in the middle of the screen.
+    fn show_main(&self);
+    fn hide_main(&self);
+    /// Puts the log window at the right edge of the screen, in full height.
+    fn dock_log(&self);
+    /// Shrinks the log window to the corner of the screen while the game runs.
+    fn shrink_log(&self);
+    /// Makes the log window large enough to ask the player something.
+    fn raise_log(&self);
+    /// Gives the log window back the size it had before `raise_log`.
+    fn restore_log(&self);
+}
+
+pub struct TauriGui {
+    main_window: Window,
+    log_window: Window,
+    /// Size of the log window before `raise_log`.
+    log_size: Cell<Option<PhysicalSize<u32>>>,
+}
+
+impl TauriGui {
+    pub fn new(main_window: Window, log_window: Window) -> Self {
+        Self {
+            main_window,
+            log_window,
+            log_size: Cell::new(None),
+        }
+    }
+}
+
+impl Gui for TauriGui {
+    fn emit_main<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        self.main_window.emit(event, payload).unwrap();
+    }
+
+    fn emit_log<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        self.log_window.emit(event, payload).unwrap();
+    }
+
+    fn show_main(&self) {
+        self.main_window.center().unwrap();
+        self.main_window.show().unwrap();
+        self.main_window.set_focus().unwrap();
+    }
+
+    fn hide_main(&self) {
+        self.main_window.hide().unwrap();
+    }
+
+    fn dock_log(&self) {
+        let mon = self.log_window.current_monitor();
+        let monitor_size = *mon.unwrap().unwrap().size();
+        self.log_window.move_window(Position::TopRight).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        self.log_window
+            .set_size(Size::Physical(PhysicalSize {
+                width: 480,
+                height: monitor_size.height,
+            }))
+            .unwrap();
+        self.log_window.show().unwrap();
+        self.log_window.move_window(Position::TopRight).unwrap();
+    }
+
+    fn shrink_log(&self) {
+        self.log_window
+            .set_size(Size::Logical(LogicalSize::new(0.2, 2.0)))
+            .unwrap();
+        self.log_window.move_window(Position::TopRight).unwrap();
+        self.log_window.show().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    fn raise_log(&self) {
+        // The log window is small during a game, make the question readable
+        self.log_size.set(self.log_window.inner_size().ok());
+        self.log_window
+            .set_size(Size::Logical(LogicalSize::new(480.0, 320.0)))
+            .unwrap();
+        self.log_window.move_window(Position::TopRight).unwrap();
+        self.log_window.show().unwrap();
+        self.log_window.set_focus().unwrap();
+    }
+
+    fn restore_log(&self) {
+        if let Some(size) = self.log_size.take() {
+            self.log_window.set_size(Size::Physical(size)).unwrap();
+            self.log_window.move_window(Position::TopRight).unwrap();
+        }
+    }
+}
@@ -1,36 +1,60 @@
 use core::panic;
 use std::{
+    cell::RefCell,
     fs::File,
+    io::Read,
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
         Arc,
     },
 };
 
+use crate::{
+    archive_cache::ArchiveCache,
+    storage::{self, StorageBackend},
+};
 use crossbeam_channel::{Receiver, Sender};
-use futures::TryStreamExt;
-use ipfs_api_backend_hyper::{IpfsApi, IpfsClient as Client};
-
-use tauri::async_runtime::RwLock;
+use gear_connector_api::{Checksum, ConnectorError};
 
 #[derive(Debug)]
 pub enum IpfsCommand {
-    UploadArchive { filename: String, archive: File },
-    UploadData { filename: String, data: Vec<u8> },
-    DownloadArchive { hash: String },
-    DownloadData { hash: String },
+    UploadArchive {
+        filename: String,
+        archive: File,
+    },
+    UploadData {
+        filename: String,
+        data: Vec<u8>,
+    },
+    DownloadArchive {
+        hash: String,
+    },
+    DownloadData {
+        hash: String,
+        checksum: Option<Checksum>,
+    },
+    Unpin {
+        hash: String,
+    },
 }
 
 #[derive(Debug)]
 pub enum IpfsReply {
     Uploaded { name: String, hash: String },
     Downloaded { data: Vec<u8> },
+    Unpinned { hash: String },
+    Error(ConnectorError),
 }
 
+/// Serves the storage commands of Logic. Despite the name, the archives are kept
+/// wherever the storage backend keeps them, an IPFS node or a local directory.
+/// Downloaded archives are cached, so loading a save again reads it from disk.
 pub struct IpfsClient {
     need_stop: Arc<AtomicBool>,
     ipfs_reply_sender: Sender<IpfsReply>,
     ipfs_command_receiver: Receiver<IpfsCommand>,
+    storage: Box<dyn StorageBackend>,
+    cache: RefCell<ArchiveCache>,
 }
 
 impl IpfsClient {
@@ -38,126 +62,132 @@ impl IpfsClient {
         need_stop: Arc<AtomicBool>,
         ipfs_reply_sender: Sender<IpfsReply>,
         ipfs_command_receiver: Receiver<IpfsCommand>,
+        storage: Box<dyn StorageBackend>,
+        cache: ArchiveCache,
     ) -> Self {
         Self {
             need_stop,
             ipfs_reply_sender,
             ipfs_command_receiver,
+            storage,
+            cache: RefCell::new(cache),
         }
     }
 
     pub fn run(&self) -> std::io::Result<()> {
-        let ipfs_command_receiver = self.ipfs_command_receiver.clone();
-        let ipfs_reply_sender = self.ipfs_reply_sender.clone();
-        let need_stop_clone = self.need_stop.clone();
-        let ipfs_client = Arc::new(RwLock::new(Client::default()));
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async move {
-            while !need_stop_clone.load(Relaxed) {
-                let command = ipfs_command_receiver.recv();
-
-                match command {
-                    Ok(command) => match command {
-                        IpfsCommand::UploadArchive {
-                            filename,
-                            archive: file,
-                        } => {
-                            tracing::debug!("Received Upload Command, filename {}", filename);
-                            let result = ipfs_client
-                                .read()
-                                .await
-                                .add(file)
-                                .await
-                                .expect("Can't upload to ipfs");
-                            tracing::info!(
-                                "File {filename} uploaded to IPFS. Hash: {}, Name: {}",
-                                result.hash,
-                                result.name
-                            );
-                            ipfs_reply_sender
-                                .send(IpfsReply::Uploaded {
-                                    name: result.name,
-                                    hash: result.hash,
-                                })
-                                .unwrap_or_else(|e| {
-                                    need_stop_clone.store(true, Relaxed);
-                                    panic!("{e}");
-                                });
-                        }
-                        IpfsCommand::DownloadArchive { hash } => {
-                            tracing::debug!("Received Download command, hash: {:?}", hash);
-                            match ipfs_client
-                                .read()
-                                .await
-                                .tar_cat(&hash)
-                                .map_ok(|chunk| chunk.to_vec())
-                                .try_concat()
-                                .await
-                            {
-                                Ok(data) => ipfs_reply_sender
-                                    .send(IpfsReply::Downloaded { data })
-                                    .unwrap_or_else(|e| {
-                                        need_stop_clone.store(true, Relaxed);
-                                        panic!("{e}");
-                                    }),
-                                Err(error) => panic!("Game State Is Not Found in ipfs: {}", error),
-                            }
-                        }
-                        IpfsCommand::UploadData { filename, data } => {
-                            let data = std::io::Cursor::new(data);
-                            let result =
-                                ipfs_client
-                                    .read()
-                                    .await
-                                    .add(data)
-                                    .await
-                                    .unwrap_or_else(|e| {
-                                        need_stop_clone.store(true, Relaxed);
-                                        panic!("{e}");
-                                    });
-                            tracing::info!(
-                                "File {filename} uploaded to IPFS. Hash: {}, Name: {}, Size: {}",
-                                result.hash,
-                                result.name,
-                                result.size
-                            );
-                            ipfs_reply_sender
-                                .send(IpfsReply::Uploaded {
-                                    name: result.name,
-                                    hash: result.hash,
-                                })
-                                .unwrap_or_else(|e| {
-                                    need_stop_clone.store(true, Relaxed);
-                                    panic!("{e}");
-                                });
-                        }
-                        IpfsCommand::DownloadData { hash } => {
-                            match ipfs_client
-                                .read()
-                                .await
-                                .cat(&hash)
-                                .map_ok(|chunk| chunk.to_vec())
-                                .try_concat()
-                                .await
-                            {
-                                Ok(data) => ipfs_reply_sender
-                                    .send(IpfsReply::Downloaded { data })
-                                    .unwrap_or_else(|e| {
-                                        need_stop_clone.store(true, Relaxed);
-                                        panic!("{e}");
-                                    }),
-                                Err(error) => panic!("Game State Is Not Found in ipfs: {}", error),
-                            }
-                        }
-                    },
+            while !self.need_stop.load(Relaxed) {
+                match self.ipfs_command_receiver.recv() {
+                    Ok(command) => {
+                        let reply = self.process_command(command).await;
+                        self.ipfs_reply_sender.send(reply).unwrap_or_else(|e| {
+                            self.need_stop.store(true, Relaxed);
+                            panic!("{e}");
+                        });
+                    }
                     Err(error) => {
                         tracing::error!("Error in another thread: {}", error);
-                        need_stop_clone.store(true, Relaxed);
-                        // panic!("Error in another thread: {}", error);
+                        self.need_stop.store(true, Relaxed);
                     }
                 }
             }
         });
         Ok(())
     }
+
+    async fn process_command(&self, command: IpfsCommand) -> IpfsReply {
+        match command {
+            IpfsCommand::UploadArchive {
+                filename,
+                archive: mut file,
+            } => {
+                tracing::debug!("Received Upload Command, filename {}", filename);
+                let mut data = Vec::new();
+                match file.read_to_end(&mut data) {
+                    Ok(_) => self.upload(filename, data).await,
+                    Err(e) => upload_error(&filename, e),
+                }
+            }
+            IpfsCommand::UploadData { filename, data } => self.upload(filename, data).await,
+            IpfsCommand::DownloadArchive { hash } => {
+                tracing::debug!("Received Download command, hash: {:?}", hash);
+                match self.storage.tar_cat(&hash).await {
+                    Ok(data) => IpfsReply::Downloaded { data },
+                    Err(e) => download_error(&hash, e),
+                }
+            }
+            IpfsCommand::DownloadData { hash, checksum } => self.download(hash, checksum).await,
+            IpfsCommand::Unpin { hash } => match self.storage.unpin(&hash).await {
+                Ok(()) => {
+                    tracing::info!("Archive {hash} unpinned");
+                    self.cache.borrow_mut().remove(&hash);
+                    IpfsReply::Unpinned { hash }
+                }
+                Err(e) => {
+                    tracing::error!("Can't unpin {}: {}", hash, e);
+                    IpfsReply::Error(ConnectorError::Ipfs(format!("can't unpin {hash}: {e}")))
+                }
+            },
+        }
+    }
+
+    async fn upload(&self, filename: String, data: Vec<u8>) -> IpfsReply {
+        let size = data.len();
+        match self.storage.add(data).await {
+            Ok(hash) => {
+                tracing::info!("File {filename} uploaded. Hash: {hash}, Size: {size}");
+                IpfsReply::Uploaded {
+                    name: filename,
+                    hash,
+                }
+            }
+            Err(e) => upload_error(&filename, e),
+        }
+    }
+
+    /// Reads the archive from the cache, or from the storage if it isn't cached or
+    /// the cached copy is damaged. Corrupted and unverifiable downloads are rejected.
+    async fn download(&self, hash: String, checksum: Option<Checksum>) -> IpfsReply {
+        let cached = self.cache.borrow_mut().get(&hash);
+        if let Some(data) = cached {
+            match storage::verify(&hash, &data, checksum.as_ref()) {
+                Ok(()) => {
+                    tracing::debug!("Archive {hash} is read from the cache");
+                    return IpfsReply::Downloaded { data };
+                }
+                Err(e) => {
+                    tracing::warn!("Cached archive {} is damaged: {}", hash, e);
+                    self.cache.borrow_mut().remove(&hash);
+                }
+            }
+        }
+
+        let data = match self.storage.cat(&hash).await {
+            Ok(data) => data,
+            Err(e) => return download_error(&hash, e),
+        };
+        if let Err(e) = storage::verify(&hash, &data, checksum.as_ref()) {
+            tracing::error!("Archive {} is rejected: {}", hash, e);
+            return IpfsReply::Error(ConnectorError::Ipfs(format!(
+                "archive {hash} is rejected: {e}"
+            )));
+        }
+        self.cache.borrow_mut().put(&hash, &data);
+        IpfsReply::Downloaded { data }
+    }
+}
+
+fn upload_error(filename: &str, error: impl std::fmt::Display) -> IpfsReply {
+    tracing::error!("Can't upload {} to IPFS: {}", filename, error);
+    IpfsReply::Error(ConnectorError::Ipfs(format!(
+        "can't upload {filename}: {error}"
+    )))
+}
+
+fn download_error(hash: &str, error: impl std::fmt::Display) -> IpfsReply {
+    tracing::error!("Can't download {} from IPFS: {}", hash, error);
+    IpfsReply::Error(ConnectorError::Ipfs(format!(
+        "archive {hash} is not found: {error}"
+    )))
 }
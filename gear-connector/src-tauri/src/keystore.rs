@@ -0,0 +1,212 @@
+use crate::utils::{read_json, store_json_atomically};
+use argon2::Argon2;
+use bip39::Mnemonic;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Name of the keystore file in the app data directory.
+const KEYSTORE_FILE_NAME: &str = "keystore.json";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const WORDS_IN_MNEMONIC: usize = 12;
+
+/// A password or a secret URI. Its `Debug` output is redacted, so it can't leak through
+/// tracing, and the memory is wiped when it is dropped.
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: String) -> Self {
+        Self(secret)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Secret URI of an account, encrypted by XChaCha20-Poly1305 with a key derived
+/// from the password by Argon2id.
+#[derive(Serialize, Deserialize)]
+struct StoredAccount {
+    name: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Accounts of the player, kept encrypted in the app data directory.
+pub struct Keystore {
+    path: Option<PathBuf>,
+    accounts: Vec<StoredAccount>,
+    /// Why the keystore file couldn't be loaded. The file is moved aside before the
+    /// next write instead of being overwritten, so its accounts can still be recovered.
+    load_error: Option<String>,
+}
+
+impl Keystore {
+    /// Loads the keystore from `dir`, starting an empty one if it is missing or unreadable.
+    pub fn load(dir: Option<PathBuf>) -> Self {
+        let path = dir.map(|dir| dir.join(KEYSTORE_FILE_NAME));
+        let (accounts, load_error) = match path.as_deref().map(read_json) {
+            Some(Ok(accounts)) => (accounts.unwrap_or_default(), None),
+            Some(Err(e)) => {
+                tracing::error!("{}, it is moved aside on the first change of accounts", e);
+                (Vec::new(), Some(e))
+            }
+            None => (Vec::new(), None),
+        };
+        Self {
+            path,
+            accounts,
+            load_error,
+        }
+    }
+
+    /// Why the keystore file couldn't be loaded, the accounts in it aren't listed then.
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
+    }
+
+    pub fn account_names(&self) -> Vec<String> {
+        self.accounts
+            .iter()
+            .map(|account| account.name.clone())
+            .collect()
+    }
+
+    /// Stores a new account with a random mnemonic, which is returned for a backup.
+    pub fn create(&mut self, name: String, password: &Secret) -> Result<Secret, String> {
+        let mnemonic = Mnemonic::generate(WORDS_IN_MNEMONIC)
+            .map_err(|e| format!("Can't generate a mnemonic: {e}"))?;
+        let suri = Secret::new(mnemonic.to_string());
+        self.import(name, &suri, password)?;
+        Ok(suri)
+    }
+
+    /// Stores an account given by its secret URI: a mnemonic, a seed or a derivation path.
+    pub fn import(&mut self, name: String, suri: &Secret, password: &Secret) -> Result<(), String> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err("An account needs a name".to_string());
+        }
+        if self.accounts.iter().any(|account| account.name == name) {
+            return Err(format!("Account {name} already exists"));
+        }
+        if suri.is_empty() {
+            return Err("The secret phrase is empty".to_string());
+        }
+        if password.is_empty() {
+            return Err("An account needs a password".to_string());
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = cipher(password, &salt)?;
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), suri.expose().as_bytes())
+            .map_err(|e| format!("Can't encrypt the account: {e}"))?;
+
+        self.accounts.push(StoredAccount {
+            name,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        });
+        self.store()
+    }
+
+    /// Decrypts the secret URI of the account.
+    pub fn unlock(&self, name: &str, password: &Secret) -> Result<Secret, String> {
+        let account = self
+            .accounts
+            .iter()
+            .find(|account| account.name == name)
+            .ok_or_else(|| format!("No account {name} in the keystore"))?;
+        let corrupted = |e: hex::FromHexError| format!("Account {name} is corrupted: {e}");
+        let salt = hex::decode(&account.salt).map_err(corrupted)?;
+        let nonce = hex::decode(&account.nonce).map_err(corrupted)?;
+        let ciphertext = hex::decode(&account.ciphertext).map_err(corrupted)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(format!("Account {name} is corrupted: wrong nonce"));
+        }
+
+        let suri = Zeroizing::new(
+            cipher(password, &salt)?
+                .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+                .map_err(|_| format!("Wrong password for account {name}"))?,
+        );
+        String::from_utf8(suri.to_vec())
+            .map(Secret::new)
+            .map_err(|_| format!("Account {name} is corrupted: not UTF-8"))
+    }
+
+    /// Removes the account, checking the password first.
+    pub fn remove(&mut self, name: &str, password: &Secret) -> Result<(), String> {
+        self.unlock(name, password)?;
+        self.accounts.retain(|account| account.name != name);
+        self.store()
+    }
+
+    fn store(&mut self) -> Result<(), String> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| "The app data directory is unknown".to_string())?;
+        if let Some(load_error) = &self.load_error {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs());
+            let aside = path.with_extension(format!("json.broken-{secs}"));
+            match fs::rename(path, &aside) {
+                Ok(()) => tracing::warn!("Moved the keystore aside to {}", aside.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(format!(
+                        "{load_error}, and it can't be moved aside to {}: {e}",
+                        aside.display()
+                    ))
+                }
+            }
+            self.load_error = None;
+        }
+        store_json_atomically(path, &self.accounts)
+            .map_err(|e| format!("Can't write {}: {}", path.display(), e))
+    }
+}
+
+fn cipher(password: &Secret, salt: &[u8]) -> Result<XChaCha20Poly1305, String> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(password.expose().as_bytes(), salt, key.as_mut())
+        .map_err(|e| format!("Can't derive the key: {e}"))?;
+    Ok(XChaCha20Poly1305::new(key.as_ref().into()))
+}
@@ -0,0 +1,96 @@
+pub mod archive_cache;
+pub mod archive_cipher;
+pub mod budget;
+pub mod chain;
+#[cfg(feature = "fake-chain")]
+pub mod fake_chain;
+pub mod gear_client;
+pub mod gui;
+pub mod ipfs_client;
+pub mod keystore;
+pub mod lobby;
+pub mod logic;
+pub mod network;
+pub mod retry;
+pub mod save_index;
+pub mod storage;
+pub mod transfer;
+pub mod utils;
+pub mod vcmi_server;
+
+use keystore::Secret;
+use network::NetworkProfile;
+
+/// We start vcmiclient together with gear-connector.
+/// When user chooses multiplayer game, we show dialog with offer to connect to GEAR.
+/// If user agrees - we connect, minimize window, show connection status.
+/// If user declines - close dialog.
+// gui  <-> connector
+// vcmi <-> connector -> gear
+
+#[derive(Debug)]
+pub enum GuiCommand {
+    Connect {
+        lobby_address: String,
+        username: String,
+        node_address: String,
+        program_id: String,
+        meta_program_id: String,
+        battle_program_id: String,
+        /// Name of the account in the keystore, empty for the dev account Alice.
+        account: String,
+        password: Secret,
+    },
+    NewRoom {
+        room_name: String,
+        password: String,
+        max_players: u8,
+        mods: String,
+    },
+    JoinRoom {
+        room_name: String,
+        password: String,
+        mods: String,
+    },
+    Ready {
+        room_name: String,
+    },
+    Leave {
+        room_name: String,
+    },
+    HostMode {
+        mode: u8,
+    },
+    ListSaves,
+    DeleteSave {
+        cid: String,
+        unpin: bool,
+    },
+    /// Answer to a fee that takes the spending over the budget,
+    /// `for_all` allows the rest of the session or game too.
+    ConfirmSpending {
+        approved: bool,
+        for_all: bool,
+    },
+    ListAccounts,
+    /// Stores a new account with a random mnemonic.
+    CreateAccount {
+        name: String,
+        password: Secret,
+    },
+    ImportAccount {
+        name: String,
+        suri: Secret,
+        password: Secret,
+    },
+    DeleteAccount {
+        name: String,
+        password: Secret,
+    },
+    ListNetworks,
+    SaveNetwork(NetworkProfile),
+    DeleteNetwork {
+        name: String,
+    },
+    Cancel,
+}
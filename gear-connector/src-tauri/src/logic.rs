@@ -1,23 +1,37 @@
 use crate::{
+    archive_cipher::{self, ArchiveCipher},
+    budget::{Budget, FeeEstimate},
     gear_client::{GearCommand, GearReply, RECV_TIMEOUT},
+    gui::{Gui, TauriGui},
     ipfs_client::{IpfsCommand, IpfsReply},
+    keystore::{Keystore, Secret},
     lobby::{LobbyCommand, LobbyReply, VCMI_VERSION},
+    network::{NetworkProfiles, NodeUrl},
+    save_index::SaveIndex,
+    transfer::Transfers,
     utils::convert_battle_info2,
+    vcmi_server::{self, ReplyTo},
     GuiCommand,
 };
 use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
-use gclient::WSAddress;
-use gear_connector_api::{BattleInfo, PlayerState, VcmiCommand, VcmiReply, VcmiSavedGame};
-use homm3_archive_io::{Action, ArchiveDescription, Event};
+use gear_connector_api::{
+    endpoint::ADDRESS_ENV, transfer::checksum, BattleInfo, Checksum, ConnectorError, PlayerState,
+    SaveDescription, SaveMetadata, VcmiCommand, VcmiReply,
+};
+use gmeta::{Decode, Encode};
+use homm3_archive_io::{ArchiveDescription, Config as ArchiveConfig, Event};
 use std::{
+    collections::VecDeque,
     process::Command,
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
         Arc,
     },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tauri::{LogicalSize, PhysicalSize, Size, Window};
-use tauri_plugin_positioner::{Position, WindowExt};
+
+/// How long the player has to allow spending over the budget, declined after that.
+const CONFIRM_SPENDING_TIMEOUT: Duration = Duration::from_secs(60);
 
 pub enum Recipient {
     GearClient,
@@ -32,36 +46,61 @@ pub enum Message {
     Event,
 }
 
-pub struct Logic {
+/// Key of the connected account for its archives.
+enum ArchiveKey {
+    /// Not connected, the account isn't known yet.
+    Unknown,
+    /// The dev account Alice. Its secret is public, so its archives are left plain.
+    DevAccount,
+    Account(ArchiveCipher),
+}
+
+/// Archive put to the storage, recorded in the save index once it is on chain.
+struct UploadedArchive {
+    description: ArchiveDescription,
+    encrypted: bool,
+}
+
+pub struct Logic<G: Gui = TauriGui> {
     need_stop: Arc<AtomicBool>,
     gear_command_sender: Sender<GearCommand>,
     gear_reply_receiver: Receiver<GearReply>,
-    vcmi_command_receiver: Receiver<VcmiCommand>,
-    vcmi_reply_sender: Sender<VcmiReply>,
+    vcmi_command_receiver: Receiver<(ReplyTo, VcmiCommand)>,
+    vcmi_reply_sender: Sender<(ReplyTo, VcmiReply)>,
     ipfs_reply_receiver: Receiver<IpfsReply>,
     ipfs_command_sender: Sender<IpfsCommand>,
     gui_command_receiver: Receiver<GuiCommand>,
+    /// GUI commands which came while a spending waited for confirmation, run next.
+    pending_gui_commands: VecDeque<GuiCommand>,
     lobby_command_sender: Sender<LobbyCommand>,
     lobby_reply_receiver: Receiver<LobbyReply>,
-    main_window: Window,
-    log_window: Window,
+    gui: G,
+    /// VCMI request which opened the connect dialog, answered when the dialog is canceled.
+    connect_request: Option<ReplyTo>,
+    transfers: Transfers,
+    save_index: SaveIndex,
+    network_profiles: NetworkProfiles,
+    keystore: Keystore,
+    budget: Budget,
+    /// Key of the connected account, encrypts and decrypts save archives.
+    archive_key: ArchiveKey,
+    encrypt_saves: bool,
 }
 
-impl Logic {
+impl<G: Gui> Logic<G> {
     pub fn new(
         need_stop: Arc<AtomicBool>,
         gear_command_sender: Sender<GearCommand>,
         gear_reply_receiver: Receiver<GearReply>,
-        vcmi_command_receiver: Receiver<VcmiCommand>,
-        vcmi_reply_sender: Sender<VcmiReply>,
+        vcmi_command_receiver: Receiver<(ReplyTo, VcmiCommand)>,
+        vcmi_reply_sender: Sender<(ReplyTo, VcmiReply)>,
         ipfs_reply_receiver: Receiver<IpfsReply>,
         ipfs_command_sender: Sender<IpfsCommand>,
         gui_command_receiver: Receiver<GuiCommand>,
         lobby_command_sender: Sender<LobbyCommand>,
         lobby_reply_receiver: Receiver<LobbyReply>,
-
-        main_window: Window,
-        log_window: Window,
+        gui: G,
+        keystore: Keystore,
     ) -> Self {
         Self {
             need_stop,
@@ -72,13 +111,27 @@ impl Logic {
             ipfs_reply_receiver,
             ipfs_command_sender,
             gui_command_receiver,
+            pending_gui_commands: VecDeque::new(),
             lobby_command_sender,
             lobby_reply_receiver,
-            main_window,
-            log_window,
+            gui,
+            connect_request: None,
+            transfers: Transfers::default(),
+            save_index: SaveIndex::load(),
+            network_profiles: NetworkProfiles::load(),
+            keystore,
+            budget: Budget::from_env(),
+            archive_key: ArchiveKey::Unknown,
+            encrypt_saves: archive_cipher::encrypt_saves_from_env(),
         }
     }
 
+    /// Replaces the budget read from the environment.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
     pub async fn run(&mut self) {
         while !self.need_stop.load(Relaxed) {
             self.process_gui_command();
@@ -87,13 +140,10 @@ impl Logic {
         }
     }
 
-    fn connect_to_gear(&self) {
-        self.main_window.center().unwrap();
-        self.main_window.show().unwrap();
-        self.main_window.set_focus().unwrap();
-        self.vcmi_reply_sender
-            .send(VcmiReply::ConnectDialogShowed)
-            .expect("Error in another thread");
+    fn connect_to_gear(&mut self, reply_to: ReplyTo) {
+        self.gui.show_main();
+        self.connect_request = Some(reply_to);
+        self.send_to_vcmi(reply_to, VcmiReply::ConnectDialogShowed);
     }
 
     // fn show_load_game_dialog(&self) {
@@ -110,128 +160,455 @@ impl Logic {
     //         .expect("Error in another thread");
     // }
 
-    fn simulate_battle(&self, battle_info: BattleInfo) {
+    fn simulate_battle(&mut self, battle_info: BattleInfo) -> Result<VcmiReply, ConnectorError> {
         let battle_info = crate::utils::convert_battle_info(battle_info);
         let gear_command = GearCommand::SimulateBattle(battle_info);
 
-        self.gear_command_sender
-            .send(gear_command)
-            .expect("Send error");
-        let gear_reply = self.gear_reply_receiver.recv().expect("Recv error");
+        let gear_reply = self.gear_request(gear_command)?;
         tracing::debug!("simulate battle reply: {:?}", gear_reply);
         match gear_reply {
-            GearReply::Simulated(e) => match e {
-                homm3_battle_io::Event::BattleResult(res) => {
-                    self.vcmi_reply_sender
-                        .send(VcmiReply::BattleInfo(convert_battle_info2(res)))
-                        .expect("Send error");
-                }
-            },
-            _ => {}
+            GearReply::Simulated(homm3_battle_io::Event::BattleResult(res)) => {
+                Ok(VcmiReply::BattleInfo(convert_battle_info2(res)))
+            }
+            reply => Err(unexpected_reply("SimulateBattle", reply)),
         }
     }
 
-    fn save_archive(&self, filename: String, compressed_archive: Vec<u8>) {
-        let archive_name = format!("{filename}");
+    /// Saves the complete upload with `save`. The upload is kept if the save fails,
+    /// VCMI retries the commit without uploading the archive again.
+    fn save_upload(
+        &mut self,
+        checksum: Checksum,
+        save: impl FnOnce(&mut Self, String, &[u8]) -> Result<VcmiReply, ConnectorError>,
+    ) -> Result<VcmiReply, ConnectorError> {
+        let upload = self.transfers.commit_upload(&checksum)?;
+        let result = save(self, upload.filename.clone(), &upload.data);
+        if result.is_err() {
+            self.transfers.keep_upload(checksum, upload);
+        }
+        result
+    }
 
-        tracing::info!("Archive len: {}", compressed_archive.len());
+    fn save_archive(
+        &mut self,
+        filename: String,
+        compressed_archive: &[u8],
+        metadata: SaveMetadata,
+    ) -> Result<VcmiReply, ConnectorError> {
+        let compressed_archive_len = compressed_archive.len() as u64;
+        let archive = self.upload_archive(filename, compressed_archive, &metadata)?;
+        let command = GearCommand::SaveArchive(archive.description.clone());
+        match self.gear_request(command)? {
+            GearReply::Saved(Event::SavedArchive) => {
+                self.record_save(archive, compressed_archive_len, metadata);
+                Ok(VcmiReply::Saved)
+            }
+            reply => Err(unexpected_reply("SaveArchive", reply)),
+        }
+    }
 
+    /// Saves the archive in place of the save with IPFS CID `cid`.
+    /// The replaced archive is unpinned, nothing on chain refers to it anymore.
+    fn replace_save(
+        &mut self,
+        cid: String,
+        filename: String,
+        compressed_archive: &[u8],
+        metadata: SaveMetadata,
+    ) -> Result<VcmiReply, ConnectorError> {
+        let compressed_archive_len = compressed_archive.len() as u64;
+        let archive = self.upload_archive(filename, compressed_archive, &metadata)?;
+        let command = GearCommand::ReplaceArchive {
+            hash: cid.clone(),
+            archive: archive.description.clone(),
+        };
+        match self.gear_request(command)? {
+            GearReply::Saved(Event::Replaced) => {
+                if archive.description.hash != cid {
+                    self.save_index.remove(&cid);
+                    self.unpin(cid);
+                }
+                self.record_save(archive, compressed_archive_len, metadata);
+                Ok(VcmiReply::Saved)
+            }
+            reply => Err(unexpected_reply("ReplaceArchive", reply)),
+        }
+    }
+
+    /// Uploads the archive, encrypted unless the player turned the encryption off,
+    /// and describes it for the archive program together with its metadata.
+    fn upload_archive(
+        &self,
+        filename: String,
+        compressed_archive: &[u8],
+        metadata: &SaveMetadata,
+    ) -> Result<UploadedArchive, ConnectorError> {
+        tracing::info!("Archive len: {}", compressed_archive.len());
+        let (data, encrypted) = match &self.archive_key {
+            _ if !self.encrypt_saves => (compressed_archive.to_vec(), false),
+            ArchiveKey::Account(cipher) => (cipher.encrypt(compressed_archive), true),
+            ArchiveKey::DevAccount => {
+                tracing::warn!(
+                    "{} isn't encrypted: the secret of the dev account Alice is public",
+                    filename
+                );
+                (compressed_archive.to_vec(), false)
+            }
+            // The plain archive must not reach IPFS when the account is unknown yet
+            ArchiveKey::Unknown => {
+                return Err(ConnectorError::NotConnected(
+                    "no account to encrypt the archive with".to_string(),
+                ))
+            }
+        };
+        let checksum = checksum(&data);
         let command = IpfsCommand::UploadData {
-            filename,
-            data: compressed_archive,
+            filename: filename.clone(),
+            data,
         };
-        self.ipfs_command_sender.send(command).expect("Send error");
-
-        let reply = self.ipfs_reply_receiver.recv().expect("Recv error");
-
-        if let IpfsReply::Uploaded { name: _, hash } = reply {
-            let archive = ArchiveDescription {
-                filename: archive_name,
-                hash,
-            };
-
-            let gear_command = GearCommand::SaveArchive(archive);
-            self.gear_command_sender
-                .send(gear_command)
-                .expect("Send error");
-            let gear_reply = self.gear_reply_receiver.recv().expect("Recv error");
-
-            if let GearReply::Saved(e) = gear_reply {
-                if matches!(e, Event::SavedArchive) {
-                    self.vcmi_reply_sender
-                        .send(VcmiReply::Saved)
-                        .expect("Send error");
-                    return;
+        match self.ipfs_request(command)? {
+            IpfsReply::Uploaded { name: _, hash } => Ok(UploadedArchive {
+                description: ArchiveDescription {
+                    filename,
+                    hash,
+                    checksum,
+                    metadata: encode_metadata(&SaveMetadata {
+                        encrypted: Some(encrypted),
+                        ..metadata.clone()
+                    }),
+                },
+                encrypted,
+            }),
+            reply => Err(unexpected_reply("UploadData", reply)),
+        }
+    }
+
+    fn record_save(&mut self, archive: UploadedArchive, len: u64, metadata: SaveMetadata) {
+        let description = archive.description;
+        let save = self
+            .save_index
+            .entry(description.filename, description.hash);
+        save.size = Some(len);
+        save.encrypted = archive.encrypted;
+        save.checksum = Some(description.checksum);
+        save.saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|now| now.as_secs());
+        save.metadata = Some(SaveMetadata {
+            encrypted: Some(archive.encrypted),
+            ..metadata
+        });
+        self.save_index.store();
+    }
+
+    fn delete_save(&mut self, cid: String, unpin: bool) -> Result<VcmiReply, ConnectorError> {
+        let command = GearCommand::DeleteArchive { hash: cid.clone() };
+        match self.gear_request(command)? {
+            GearReply::Saved(Event::Deleted) => {
+                self.save_index.remove(&cid);
+                self.save_index.store();
+                if unpin {
+                    self.unpin(cid);
                 }
+                Ok(VcmiReply::Deleted)
             }
+            reply => Err(unexpected_reply("DeleteArchive", reply)),
         }
+    }
 
-        unreachable!();
+    fn rename_save(&mut self, cid: String, name: String) -> Result<VcmiReply, ConnectorError> {
+        let command = GearCommand::RenameArchive {
+            hash: cid.clone(),
+            filename: name.clone(),
+        };
+        match self.gear_request(command)? {
+            GearReply::Saved(Event::Renamed) => {
+                self.save_index.entry(name, cid);
+                self.save_index.store();
+                Ok(VcmiReply::Saved)
+            }
+            reply => Err(unexpected_reply("RenameArchive", reply)),
+        }
     }
 
-    fn save_game_state(&self, day: u32, current_player: String, player_states: Vec<PlayerState>) {
+    /// Unpins an archive from the local IPFS node. The save is already gone from
+    /// chain at this point, so a failure is only logged.
+    fn unpin(&self, hash: String) {
+        match self.ipfs_request(IpfsCommand::Unpin { hash }) {
+            Ok(IpfsReply::Unpinned { hash }) => tracing::debug!("Unpinned {}", hash),
+            Ok(reply) => tracing::warn!("{}", unexpected_reply("Unpin", reply)),
+            Err(e) => tracing::warn!("Can't unpin archive: {}", e),
+        }
+    }
+
+    fn save_game_state(
+        &mut self,
+        day: u32,
+        current_player: String,
+        player_states: Vec<PlayerState>,
+    ) {
         let gear_command = GearCommand::SaveGameState {
             day,
             current_player,
             player_states,
         };
-        self.gear_command_sender
-            .send(gear_command)
-            .expect("Send error");
+        match self.gear_request(gear_command) {
+            Ok(GearReply::StateSaved) => {}
+            Ok(reply) => tracing::error!("{}", unexpected_reply("SaveGameState", reply)),
+            Err(e) => tracing::error!("Can't save game state: {}", e),
+        }
     }
 
-    fn load_all(&self) {
-        self.gear_command_sender
-            .send(GearCommand::GetSavedGames)
-            .expect("Send error");
+    /// Lists the saves on chain without downloading them from IPFS.
+    fn list_saves(&mut self) -> Result<Vec<SaveDescription>, ConnectorError> {
+        let games = match self.gear_request(GearCommand::GetSavedGames)? {
+            GearReply::SavedGames(games) => games,
+            reply => return Err(unexpected_reply("GetSavedGames", reply)),
+        };
 
-        let gear_reply = self.gear_reply_receiver.recv().expect("Recv Error");
-        match gear_reply {
-            GearReply::SavedGames(games) => {
-                let mut archives = Vec::with_capacity(games.len());
-                for state in games.into_iter() {
-                    let hash = state.archive.hash;
-                    let ipfs_command = IpfsCommand::DownloadData { hash };
-                    self.ipfs_command_sender
-                        .send(ipfs_command)
-                        .expect("Send err");
-                    let ipfs_reply = self.ipfs_reply_receiver.recv().expect("Recv err");
-                    match ipfs_reply {
-                        IpfsReply::Downloaded { data } => {
-                            archives.push(VcmiSavedGame {
-                                filename: state.archive.filename,
-                                data,
-                            });
-                        }
-                        _ => unreachable!("Wrong reply to Ipfs Download command"),
+        let saves = games
+            .into_iter()
+            .map(|archive| {
+                // Metadata on chain is there for saves made on other computers too
+                let metadata = decode_metadata(&archive.metadata);
+                let save = self.save_index.entry(archive.filename, archive.hash);
+                save.checksum = Some(archive.checksum);
+                if let Some(metadata) = metadata {
+                    if let Some(encrypted) = metadata.encrypted {
+                        save.encrypted = encrypted;
                     }
+                    save.metadata = Some(metadata);
                 }
-                let vcmi_reply = VcmiReply::AllLoaded { archives };
-                self.vcmi_reply_sender.send(vcmi_reply).expect("Send err");
+                save.clone()
+            })
+            .collect();
+        self.save_index.store();
+        Ok(saves)
+    }
+
+    fn show_accounts(&self) {
+        if let Some(e) = self.keystore.load_error() {
+            self.gui.emit_main(
+                "alert",
+                format!("{e}. Its accounts aren't listed, the file is kept aside when an account is added"),
+            );
+        }
+        self.gui
+            .emit_main("accounts", self.keystore.account_names());
+    }
+
+    fn show_networks(&self) {
+        self.gui.emit_main("networks", self.network_profiles.all());
+    }
+
+    /// Shows the saves on chain in the save manager of the lobby window.
+    fn show_saves(&mut self) {
+        match self.list_saves() {
+            Ok(saves) => self.gui.emit_main("saves", saves),
+            Err(e) => self.gui.emit_main("alert", e.to_string()),
+        }
+    }
+
+    fn fetch_save(&mut self, cid: String) -> Result<VcmiReply, ConnectorError> {
+        // The checksum on chain verifies the download, the index has it once the saves are listed
+        if self.save_index.checksum(&cid).is_none() {
+            self.list_saves()?;
+        }
+        let command = IpfsCommand::DownloadData {
+            hash: cid.clone(),
+            checksum: self.save_index.checksum(&cid),
+        };
+        let data = match self.ipfs_request(command)? {
+            IpfsReply::Downloaded { data } => data,
+            reply => return Err(unexpected_reply("DownloadData", reply)),
+        };
+        // The metadata of older saves has no flag, their archive starts with the magic if encrypted
+        let encrypted = self
+            .save_index
+            .metadata(&cid)
+            .and_then(|metadata| metadata.encrypted)
+            .unwrap_or_else(|| archive_cipher::is_encrypted(&data));
+        let data = if encrypted {
+            self.decrypt_archive(&cid, &data)?
+        } else {
+            data
+        };
+        let name = self.save_index.name(&cid).unwrap_or(&cid).to_string();
+        let save = self.save_index.entry(name.clone(), cid);
+        save.size = Some(data.len() as u64);
+        save.encrypted = encrypted;
+        self.save_index.store();
+        Ok(VcmiReply::Fetched(
+            self.transfers.stage_download(name, data),
+        ))
+    }
+
+    fn decrypt_archive(&self, cid: &str, data: &[u8]) -> Result<Vec<u8>, ConnectorError> {
+        let cipher = match &self.archive_key {
+            ArchiveKey::Account(cipher) => cipher,
+            _ => {
+                return Err(ConnectorError::NotConnected(format!(
+                    "archive {cid} is encrypted, connect with the account which saved it"
+                )))
             }
-            _ => unreachable!("Wrong reply to GetSavedGames"),
+        };
+        cipher
+            .decrypt(data)
+            .map_err(|e| ConnectorError::Codec(format!("can't decrypt archive {cid}: {e}")))
+    }
+
+    async fn update_balance(&mut self) {
+        match self.gear_request(GearCommand::GetFreeBalance) {
+            Ok(GearReply::FreeBalance(balance)) => {
+                self.gui.emit_log("update_balance", balance);
+                tracing::info!("Free balance: {}", balance);
+            }
+            Ok(reply) => tracing::error!("{}", unexpected_reply("GetFreeBalance", reply)),
+            Err(e) => tracing::error!("Can't update balance: {}", e),
         }
     }
 
-    async fn update_balance(&self) {
+    /// Sends the command to GearClient, approving the fees and recording the spending
+    /// of the messages it sends on the way.
+    fn gear_request(&mut self, command: GearCommand) -> Result<GearReply, ConnectorError> {
         self.gear_command_sender
-            .send(GearCommand::GetFreeBalance)
-            .expect("Send Error");
+            .send(command)
+            .map_err(|e| ConnectorError::Internal(format!("GearClient is down: {e}")))?;
+        loop {
+            match self.gear_reply_receiver.recv() {
+                Ok(GearReply::Fee(fee)) => {
+                    let approved = self.approve_fee(fee);
+                    self.gear_command_sender
+                        .send(GearCommand::ApproveFee(approved))
+                        .map_err(|e| {
+                            ConnectorError::Internal(format!("GearClient is down: {e}"))
+                        })?;
+                }
+                Ok(GearReply::Spent {
+                    action,
+                    estimated,
+                    spent,
+                }) => {
+                    let entry = self.budget.record(action, estimated, spent);
+                    tracing::info!(
+                        "{} cost {} (gas estimated at {}), {} spent in total",
+                        entry.action,
+                        entry.spent,
+                        entry.estimated,
+                        entry.total
+                    );
+                    self.gui.emit_log("spending", entry);
+                }
+                Ok(GearReply::Error(e)) => return Err(e),
+                Ok(reply) => return Ok(reply),
+                Err(e) => return Err(ConnectorError::Internal(format!("GearClient is down: {e}"))),
+            }
+        }
+    }
 
-        let reply = self.gear_reply_receiver.recv().expect("Recv error");
-        match reply {
-            GearReply::FreeBalance(balance) => {
-                self.log_window.emit("update_balance", balance).unwrap();
-                tracing::info!("Free balance: {}", balance);
+    /// Fees within the budget are approved, the player is asked about the others.
+    fn approve_fee(&mut self, fee: FeeEstimate) -> bool {
+        if !self.budget.needs_confirmation(&fee) {
+            return true;
+        }
+        tracing::warn!(
+            "{} would take the spending over the cap: {} + {} for gas > {:?}",
+            fee.action,
+            self.budget.spent(),
+            fee.gas_value,
+            self.budget.cap()
+        );
+
+        self.gui.raise_log();
+        self.gui.emit_log(
+            "confirm_spending",
+            (&fee, self.budget.spent(), self.budget.cap()),
+        );
+
+        let approved = self.wait_spending_confirmation();
+        self.gui.emit_log("spending_confirmed", approved);
+        self.gui.restore_log();
+        tracing::info!(
+            "Spending on {} is {}",
+            fee.action,
+            if approved { "allowed" } else { "declined" }
+        );
+        approved
+    }
+
+    /// Waits for the answer of the player. Other GUI commands are run after it,
+    /// closing the window declines the spending.
+    fn wait_spending_confirmation(&mut self) -> bool {
+        let deadline = Instant::now() + CONFIRM_SPENDING_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.gui_command_receiver.recv_timeout(timeout) {
+                Ok(GuiCommand::ConfirmSpending { approved, for_all }) => {
+                    if approved && for_all {
+                        self.budget.waive();
+                    }
+                    return approved;
+                }
+                Ok(GuiCommand::Cancel) => {
+                    self.pending_gui_commands.push_back(GuiCommand::Cancel);
+                    return false;
+                }
+                Ok(command) => {
+                    tracing::debug!("Delay {:?} until spending is confirmed", command);
+                    self.pending_gui_commands.push_back(command);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    tracing::warn!("No answer about spending, declined");
+                    return false;
+                }
+                Err(e) => {
+                    tracing::error!("Error in another thread: {}", e);
+                    self.need_stop.store(true, Relaxed);
+                    return false;
+                }
             }
-            _ => unreachable!("Reply {reply:?} is wrong to command FreeBalance"),
         }
     }
 
-    async fn process_vcmi_command(&self) {
+    fn ipfs_request(&self, command: IpfsCommand) -> Result<IpfsReply, ConnectorError> {
+        self.ipfs_command_sender
+            .send(command)
+            .map_err(|e| ConnectorError::Internal(format!("IpfsClient is down: {e}")))?;
+        match self.ipfs_reply_receiver.recv() {
+            Ok(IpfsReply::Error(e)) => Err(e),
+            Ok(reply) => Ok(reply),
+            Err(e) => Err(ConnectorError::Internal(format!("IpfsClient is down: {e}"))),
+        }
+    }
+
+    fn reply_to_vcmi(&self, reply_to: ReplyTo, result: Result<VcmiReply, ConnectorError>) {
+        let reply = result.unwrap_or_else(|e| {
+            tracing::error!("Request {} failed: {}", reply_to.request_id, e);
+            e.into()
+        });
+        self.send_to_vcmi(reply_to, reply);
+    }
+
+    fn send_to_vcmi(&self, reply_to: ReplyTo, reply: VcmiReply) {
+        if let Err(e) = self.vcmi_reply_sender.send((reply_to, reply)) {
+            tracing::error!("Error in another thread: {}", e);
+            self.need_stop.store(true, Relaxed);
+        }
+    }
+
+    fn send_to_lobby(&self, command: LobbyCommand) {
+        if let Err(e) = self.lobby_command_sender.send(command) {
+            let error = ConnectorError::Lobby(format!("LobbyClient is down: {e}"));
+            tracing::error!("{}", error);
+            self.gui.emit_main("alert", error.to_string());
+        }
+    }
+
+    async fn process_vcmi_command(&mut self) {
         match self.vcmi_command_receiver.recv_timeout(RECV_TIMEOUT) {
-            Ok(vcmi_command) => match vcmi_command {
-                VcmiCommand::Connect => self.connect_to_gear(),
+            Ok((reply_to, vcmi_command)) => match vcmi_command {
+                VcmiCommand::Connect => self.connect_to_gear(reply_to),
                 VcmiCommand::SaveGameState {
                     day,
                     current_player,
@@ -240,22 +617,92 @@ impl Logic {
                     self.save_game_state(day, current_player, player_states);
                     self.update_balance().await;
                 }
-                VcmiCommand::SaveArchive {
+                VcmiCommand::UploadBegin {
                     filename,
-                    compressed_archive,
+                    len,
+                    checksum,
+                } => {
+                    let result = self
+                        .transfers
+                        .begin_upload(filename, len, checksum)
+                        .map(|offset| VcmiReply::UploadOffset { offset });
+                    self.reply_to_vcmi(reply_to, result);
+                }
+                VcmiCommand::UploadChunk {
+                    checksum,
+                    offset,
+                    data,
                 } => {
-                    self.save_archive(filename, compressed_archive);
+                    let result = self
+                        .transfers
+                        .write_chunk(&checksum, offset, data)
+                        .map(|offset| VcmiReply::UploadOffset { offset });
+                    self.reply_to_vcmi(reply_to, result);
+                }
+                VcmiCommand::UploadCommit { checksum, metadata } => {
+                    let result = self.save_upload(checksum, |logic, filename, data| {
+                        logic.save_archive(filename, data, metadata)
+                    });
+                    self.reply_to_vcmi(reply_to, result);
                     self.update_balance().await;
                 }
-                VcmiCommand::Load(name) => self
-                    .gear_command_sender
-                    .send(GearCommand::SendAction(Action::Load { hash: name }))
-                    .expect("Error in another thread"),
-                VcmiCommand::ShowLoadGameDialog => {
-                    unreachable!("Shouldn't request ShowLoadGameDialog")
+                VcmiCommand::DownloadChunk {
+                    checksum,
+                    offset,
+                    len,
+                } => {
+                    let result = self
+                        .transfers
+                        .read_chunk(&checksum, offset, len)
+                        .map(|data| VcmiReply::DownloadedChunk { offset, data });
+                    self.reply_to_vcmi(reply_to, result);
+                }
+                VcmiCommand::Load(name) => self.reply_to_vcmi(
+                    reply_to,
+                    Err(ConnectorError::Internal(format!(
+                        "can't load {name}: loading a single save by name is not supported"
+                    ))),
+                ),
+                VcmiCommand::ShowLoadGameDialog => self.reply_to_vcmi(
+                    reply_to,
+                    Err(ConnectorError::Internal(
+                        "Shouldn't request ShowLoadGameDialog".to_string(),
+                    )),
+                ),
+                VcmiCommand::ListSaves => {
+                    let result = self.list_saves().map(VcmiReply::Saves);
+                    self.reply_to_vcmi(reply_to, result);
+                }
+                VcmiCommand::FetchSave { cid } => {
+                    let result = self.fetch_save(cid);
+                    self.reply_to_vcmi(reply_to, result);
                 }
-                VcmiCommand::LoadAll => self.load_all(),
-                VcmiCommand::SimulateBattle(battle_info) => self.simulate_battle(battle_info),
+                VcmiCommand::DeleteSave { cid, unpin } => {
+                    let result = self.delete_save(cid, unpin);
+                    self.reply_to_vcmi(reply_to, result);
+                    self.update_balance().await;
+                }
+                VcmiCommand::RenameSave { cid, name } => {
+                    let result = self.rename_save(cid, name);
+                    self.reply_to_vcmi(reply_to, result);
+                    self.update_balance().await;
+                }
+                VcmiCommand::ReplaceSave {
+                    cid,
+                    checksum,
+                    metadata,
+                } => {
+                    let result = self.save_upload(checksum, |logic, filename, data| {
+                        logic.replace_save(cid, filename, data, metadata)
+                    });
+                    self.reply_to_vcmi(reply_to, result);
+                    self.update_balance().await;
+                }
+                VcmiCommand::SimulateBattle(battle_info) => {
+                    let result = self.simulate_battle(battle_info);
+                    self.reply_to_vcmi(reply_to, result);
+                }
+                VcmiCommand::Ping => self.reply_to_vcmi(reply_to, Ok(VcmiReply::Pong)),
             },
             Err(e) if e == RecvTimeoutError::Timeout => {}
             Err(e) => {
@@ -266,56 +713,66 @@ impl Logic {
     }
 
     fn connect_to_node(
-        &self,
-        address: String,
+        &mut self,
+        address: NodeUrl,
         program_id: String,
         meta_program_id: String,
         battle_program_id: String,
-        account_id: String,
-        password: String,
+        suri: Option<Secret>,
     ) {
-        let port = match address.starts_with("ws://localhost") {
-            true => 9944,
-            false => 443,
+        let archive_key = match &suri {
+            Some(suri) => match ArchiveCipher::for_account(suri) {
+                Ok(cipher) => ArchiveKey::Account(cipher),
+                Err(e) => {
+                    self.gui.emit_main("alert", e);
+                    return;
+                }
+            },
+            None => ArchiveKey::DevAccount,
         };
-        let address = WSAddress::new(address, port);
-        self.gear_command_sender
-            .send(GearCommand::ConnectToNode {
-                address,
-                program_id,
-                meta_program_id,
-                battle_program_id,
-                password,
-                account_id,
-            })
-            .expect("Error in another thread");
-
-        let reply = self.gear_reply_receiver.recv().expect("Recv error");
+        let reply = self.gear_request(GearCommand::ConnectToNode {
+            address: address.ws_address(),
+            program_id,
+            meta_program_id,
+            battle_program_id,
+            suri,
+        });
 
         match reply {
-            GearReply::Connected { username } => {
+            Ok(GearReply::Connected { username }) => {
                 tracing::info!("Connected to node. Account ID: {username}");
-                self.log_window.emit("update_account_id", username).unwrap();
+                if matches!(archive_key, ArchiveKey::DevAccount) && self.encrypt_saves {
+                    tracing::warn!(
+                        "Saves of the dev account Alice aren't encrypted, anyone can read them"
+                    );
+                }
+                self.archive_key = archive_key;
+                self.gui.emit_log("update_account_id", username);
             }
-            GearReply::NotConnected(reason) => self.main_window.emit("alert", reason).unwrap(),
-            GearReply::ProgramNotFound { program_id } => {
-                self.main_window.emit("alert", program_id).unwrap()
+            Ok(GearReply::NotConnected(reason)) => self.gui.emit_main("alert", reason),
+            Ok(GearReply::ProgramNotFound { program_id }) => {
+                self.gui.emit_main("alert", program_id)
             }
-            _ => unreachable!("Reply {reply:?} is wrong to command Connect"),
+            Ok(reply) => {
+                let error = unexpected_reply("ConnectToNode", reply);
+                tracing::error!("{}", error);
+                self.gui.emit_main("alert", error.to_string());
+            }
+            Err(e) => self.gui.emit_main("alert", e.to_string()),
         }
     }
 
     fn connect_to_lobby(&mut self, address: String, username: String) {
-        self.lobby_command_sender
-            .send(LobbyCommand::Connect(address, username.clone()))
-            .expect("Send error");
-        self.lobby_command_sender
-            .send(LobbyCommand::Greeting(username, VCMI_VERSION.to_string()))
-            .expect("Send Error")
+        self.send_to_lobby(LobbyCommand::Connect(address, username.clone()));
+        self.send_to_lobby(LobbyCommand::Greeting(username, VCMI_VERSION.to_string()));
     }
 
     fn process_gui_command(&mut self) {
-        match self.gui_command_receiver.recv_timeout(RECV_TIMEOUT) {
+        let gui_command = match self.pending_gui_commands.pop_front() {
+            Some(gui_command) => Ok(gui_command),
+            None => self.gui_command_receiver.recv_timeout(RECV_TIMEOUT),
+        };
+        match gui_command {
             Ok(gui_command) => {
                 tracing::debug!("Process Gui Command: {:?}", gui_command);
                 match gui_command {
@@ -326,24 +783,42 @@ impl Logic {
                         program_id,
                         meta_program_id,
                         battle_program_id,
+                        account,
                         password,
-                        account_id,
                     } => {
+                        let node_address = match NodeUrl::parse(&node_address) {
+                            Ok(node_address) => node_address,
+                            Err(e) => {
+                                self.gui.emit_main("alert", e);
+                                return;
+                            }
+                        };
+                        // Without an account the dev node account Alice is used
+                        let suri = if account.is_empty() {
+                            None
+                        } else {
+                            match self.keystore.unlock(&account, &password) {
+                                Ok(suri) => Some(suri),
+                                Err(e) => {
+                                    self.gui.emit_main("alert", e);
+                                    return;
+                                }
+                            }
+                        };
                         self.connect_to_lobby(lobby_address, username);
                         self.connect_to_node(
                             node_address,
                             program_id,
                             meta_program_id,
                             battle_program_id,
-                            account_id,
-                            password,
+                            suri,
                         );
                     }
                     GuiCommand::Cancel => {
-                        self.main_window.hide().unwrap();
-                        self.vcmi_reply_sender
-                            .send(VcmiReply::CanceledDialog)
-                            .expect("Panic in another thread");
+                        self.gui.hide_main();
+                        if let Some(reply_to) = self.connect_request.take() {
+                            self.send_to_vcmi(reply_to, VcmiReply::CanceledDialog);
+                        }
                         self.need_stop.store(true, Relaxed);
                     }
                     GuiCommand::NewRoom {
@@ -354,9 +829,7 @@ impl Logic {
                     } => {
                         let lobby_command =
                             LobbyCommand::Create(room_name, password, max_players, mods);
-                        self.lobby_command_sender
-                            .send(lobby_command)
-                            .expect("Send Error");
+                        self.send_to_lobby(lobby_command);
                     }
                     GuiCommand::JoinRoom {
                         room_name,
@@ -364,27 +837,61 @@ impl Logic {
                         mods,
                     } => {
                         let lobby_command = LobbyCommand::Join(room_name, password, mods);
-                        self.lobby_command_sender
-                            .send(lobby_command)
-                            .expect("Send Error");
+                        self.send_to_lobby(lobby_command);
                     }
                     GuiCommand::Ready { room_name } => {
                         let lobby_command = LobbyCommand::Ready(room_name);
-                        self.lobby_command_sender
-                            .send(lobby_command)
-                            .expect("Send Error");
+                        self.send_to_lobby(lobby_command);
                     }
                     GuiCommand::HostMode { mode } => {
                         let lobby_command = LobbyCommand::HostMode(mode);
-                        self.lobby_command_sender
-                            .send(lobby_command)
-                            .expect("Send Error");
+                        self.send_to_lobby(lobby_command);
                     }
                     GuiCommand::Leave { room_name } => {
                         let lobby_command = LobbyCommand::Leave(room_name);
-                        self.lobby_command_sender
-                            .send(lobby_command)
-                            .expect("Send Error");
+                        self.send_to_lobby(lobby_command);
+                    }
+                    GuiCommand::ListSaves => self.show_saves(),
+                    GuiCommand::DeleteSave { cid, unpin } => match self.delete_save(cid, unpin) {
+                        Ok(_) => self.show_saves(),
+                        Err(e) => self.gui.emit_main("alert", e.to_string()),
+                    },
+                    GuiCommand::ConfirmSpending { .. } => {
+                        tracing::debug!("No spending waits for confirmation")
+                    }
+                    GuiCommand::ListAccounts => self.show_accounts(),
+                    GuiCommand::CreateAccount { name, password } => {
+                        match self.keystore.create(name, &password) {
+                            // Shown once, so the player can write the phrase down
+                            Ok(mnemonic) => {
+                                self.gui.emit_main("mnemonic", mnemonic.expose());
+                                self.show_accounts();
+                            }
+                            Err(e) => self.gui.emit_main("alert", e),
+                        }
+                    }
+                    GuiCommand::ImportAccount {
+                        name,
+                        suri,
+                        password,
+                    } => match self.keystore.import(name, &suri, &password) {
+                        Ok(()) => self.show_accounts(),
+                        Err(e) => self.gui.emit_main("alert", e),
+                    },
+                    GuiCommand::DeleteAccount { name, password } => {
+                        match self.keystore.remove(&name, &password) {
+                            Ok(()) => self.show_accounts(),
+                            Err(e) => self.gui.emit_main("alert", e),
+                        }
+                    }
+                    GuiCommand::ListNetworks => self.show_networks(),
+                    GuiCommand::SaveNetwork(profile) => match self.network_profiles.save(profile) {
+                        Ok(()) => self.show_networks(),
+                        Err(e) => self.gui.emit_main("alert", e),
+                    },
+                    GuiCommand::DeleteNetwork { name } => {
+                        self.network_profiles.remove(&name);
+                        self.show_networks();
                     }
                 }
             }
@@ -404,39 +911,20 @@ impl Logic {
                     LobbyReply::Connected { error } => {
                         if error.is_empty() {
                             tracing::debug!("Connected to lobby");
-                            self.main_window.emit("showRooms", "").unwrap();
-
-                            let mon = self.log_window.current_monitor();
-                            let monitor_size = mon.unwrap().unwrap().size().clone();
-                            self.log_window.move_window(Position::TopRight).unwrap();
-                            std::thread::sleep(std::time::Duration::from_millis(10));
-                            self.log_window
-                                .set_size(Size::Physical(PhysicalSize {
-                                    width: 480,
-                                    height: monitor_size.height,
-                                }))
-                                .unwrap();
-                            // std::thread::sleep(std::time::Duration::from_millis(1));
-                            self.log_window.show().unwrap();
-                            self.log_window.move_window(Position::TopRight).unwrap();
+                            self.gui.emit_main("showRooms", "");
+                            self.gui.dock_log();
                         } else {
-                            self.main_window.emit("alert", error).unwrap();
+                            self.gui.emit_main("alert", error);
                         }
                     }
-                    LobbyReply::Created(room_name) => {
-                        self.main_window.emit("created", room_name).unwrap()
+                    LobbyReply::Created(room_name) => self.gui.emit_main("created", room_name),
+                    LobbyReply::Sessions(rooms) => self.gui.emit_main("addSessions", &rooms),
+                    LobbyReply::Joined(room_name, username) => {
+                        self.gui.emit_main("joined", (room_name, username))
                     }
-                    LobbyReply::Sessions(rooms) => {
-                        self.main_window.emit("addSessions", &rooms).unwrap()
+                    LobbyReply::Kicked(room_name, username) => {
+                        self.gui.emit_main("kicked", (room_name, username))
                     }
-                    LobbyReply::Joined(room_name, username) => self
-                        .main_window
-                        .emit("joined", (room_name, username))
-                        .unwrap(),
-                    LobbyReply::Kicked(room_name, username) => self
-                        .main_window
-                        .emit("kicked", (room_name, username))
-                        .unwrap(),
                     LobbyReply::Start {
                         lobby_address,
                         lobby_port,
@@ -447,6 +935,7 @@ impl Logic {
                         players_count,
                     } => {
                         tracing::debug!("connection_uuid: {}", connection_uuid);
+                        self.budget.start_game();
                         let mut args = vec![];
                         args.push("--lobby".to_string());
                         if let Some(vcmiserver_uuid) = vcmiserver_uuid {
@@ -469,39 +958,27 @@ impl Logic {
                         args.push("--uuid".to_string());
                         args.push(connection_uuid);
 
-                        self.log_window
-                            .set_size(Size::Logical(LogicalSize::new(0.2, 2.0)))
-                            .unwrap();
-                        self.log_window.move_window(Position::TopRight).unwrap();
-                        self.log_window.show().unwrap();
-                        std::thread::sleep(std::time::Duration::from_millis(1));
+                        self.gui.shrink_log();
 
                         start_game(args);
                     }
                     LobbyReply::Host(_, _) => unreachable!(),
-                    LobbyReply::Status(users_count, statuses) => self
-                        .main_window
-                        .emit("status", (users_count, statuses))
-                        .unwrap(),
-                    LobbyReply::ServerError(error) => {
-                        self.main_window.emit("alert", error).unwrap()
+                    LobbyReply::Status(users_count, statuses) => {
+                        self.gui.emit_main("status", (users_count, statuses))
                     }
+                    LobbyReply::ServerError(error) => self.gui.emit_main("alert", error),
                     LobbyReply::Mods => {}
                     LobbyReply::ClientMods => {}
                     LobbyReply::Chat(username, message) => {
-                        self.main_window
-                            .emit("chatMessage", (username, message))
-                            .unwrap();
+                        self.gui.emit_main("chatMessage", (username, message));
                     }
                     LobbyReply::Users(users) => {
-                        self.main_window
-                            .emit("addUsers", users)
-                            .expect("Can't emit addUsers");
+                        self.gui.emit_main("addUsers", users);
                         tracing::debug!("add user");
                     }
                     LobbyReply::Health => todo!(),
                     LobbyReply::GameMode(game_mode) => {
-                        self.main_window.emit("updateGameMode", game_mode).unwrap();
+                        self.gui.emit_main("updateGameMode", game_mode);
                     }
                 }
             }
@@ -514,6 +991,35 @@ impl Logic {
     }
 }
 
+/// Metadata as the archive program keeps it. Metadata over the default limit of the
+/// program is left out, the save index of this computer still has it.
+fn encode_metadata(metadata: &SaveMetadata) -> Vec<u8> {
+    let encoded = metadata.encode();
+    let max_metadata_len = ArchiveConfig::default().max_metadata_len as usize;
+    if encoded.len() > max_metadata_len {
+        tracing::warn!(
+            "Metadata of {} takes {} bytes, more than {}, it isn't saved on chain",
+            metadata.map_name,
+            encoded.len(),
+            max_metadata_len
+        );
+        return Vec::new();
+    }
+    encoded
+}
+
+/// Metadata written before `SaveMetadata::encrypted` lacks its last byte,
+/// the `None` it stands for is appended to decode it.
+fn decode_metadata(encoded: &[u8]) -> Option<SaveMetadata> {
+    SaveMetadata::decode(&mut &encoded[..])
+        .or_else(|_| SaveMetadata::decode(&mut &[encoded, &[0]].concat()[..]))
+        .ok()
+}
+
+fn unexpected_reply(command: &str, reply: impl std::fmt::Debug) -> ConnectorError {
+    ConnectorError::Internal(format!("unexpected reply to {command}: {reply:?}"))
+}
+
 fn start_game(args: Vec<String>) {
     let arg = args.join(" ");
     let vcmiclient_path = match std::env::var("VCMICLIENT_PATH") {
@@ -521,8 +1027,10 @@ fn start_game(args: Vec<String>) {
         Err(_) => "./vcmiclient".to_string(),
     };
     tracing::info!("Start game: {vcmiclient_path} {arg}");
-    Command::new(&vcmiclient_path)
-        .args(&args)
-        .spawn()
-        .expect("Failed to spawn process");
+    let mut command = Command::new(&vcmiclient_path);
+    command.args(&args);
+    if let Some(address) = vcmi_server::local_address() {
+        command.env(ADDRESS_ENV, address.to_string());
+    }
+    command.spawn().expect("Failed to spawn process");
 }
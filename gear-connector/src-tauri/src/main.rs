@@ -1,84 +1,40 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-pub mod gear_client;
-pub mod ipfs_client;
-pub mod lobby;
-pub mod logic;
-pub mod program_io;
-pub mod utils;
-pub mod vcmi_server;
-
 use std::net::SocketAddr;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
-use crate::vcmi_server::VcmiServer;
 use crossbeam_channel::{bounded, Sender};
-
-use gear_client::GearClient;
-use gear_client::GearCommand;
-
-use gear_client::GearReply;
+use gear_connector::vcmi_server::{ReplyTo, VcmiServer};
+
+use gear_connector::archive_cache::ArchiveCache;
+use gear_connector::gear_client::GearClient;
+use gear_connector::gear_client::GearCommand;
+
+use gear_connector::gear_client::GearReply;
+use gear_connector::gui::TauriGui;
+use gear_connector::ipfs_client::IpfsClient;
+use gear_connector::ipfs_client::IpfsCommand;
+use gear_connector::ipfs_client::IpfsReply;
+use gear_connector::keystore::{Keystore, Secret};
+use gear_connector::lobby::{self, LobbyClient};
+use gear_connector::logic::Logic;
+use gear_connector::network::{NetworkProfile, NodeUrl};
+use gear_connector::storage;
+use gear_connector::utils::MainWindowSubscriber;
+use gear_connector::GuiCommand;
 use gear_connector_api::VcmiCommand;
 use gear_connector_api::VcmiReply;
 use gstd::FromStr;
-use ipfs_client::IpfsClient;
-use ipfs_client::IpfsCommand;
-use ipfs_client::IpfsReply;
-use lobby::LobbyClient;
-use logic::Logic;
 use tauri::Manager;
 use tracing::info;
 use tracing_core::LevelFilter;
 use tracing_subscriber::{prelude::*, Registry};
-use utils::MainWindowSubscriber;
-
-/// We start vcmiclient together with gear-connector.
-/// When user chooses multiplayer game, we show dialog with offer to connect to GEAR.
-/// If user agrees - we connect, minimize window, show connection status.
-/// If user declines - close dialog.
-// gui  <-> connector
-// vcmi <-> connector -> gear
-
-#[derive(Debug)]
-pub enum GuiCommand {
-    Connect {
-        lobby_address: String,
-        username: String,
-        node_address: String,
-        program_id: String,
-        meta_program_id: String,
-        battle_program_id: String,
-        account_id: String,
-        password: String,
-    },
-    NewRoom {
-        room_name: String,
-        password: String,
-        max_players: u8,
-        mods: String,
-    },
-    JoinRoom {
-        room_name: String,
-        password: String,
-        mods: String,
-    },
-    Ready {
-        room_name: String,
-    },
-    Leave {
-        room_name: String,
-    },
-    HostMode {
-        mode: u8,
-    },
-    Cancel,
-}
 
 fn main() {
-    let (vcmi_command_sender, vcmi_command_receiver) = bounded::<VcmiCommand>(1);
-    let (vcmi_reply_sender, vcmi_reply_receiver) = bounded::<VcmiReply>(1);
+    let (vcmi_command_sender, vcmi_command_receiver) = bounded::<(ReplyTo, VcmiCommand)>(1);
+    let (vcmi_reply_sender, vcmi_reply_receiver) = bounded::<(ReplyTo, VcmiReply)>(1);
 
     let (gui_sender, gui_command_receiver) = bounded::<GuiCommand>(1);
 
@@ -98,13 +54,31 @@ fn main() {
         .manage(gui_sender)
         .plugin(tauri_plugin_positioner::init())
         .invoke_handler(tauri::generate_handler![
-            connect, skip, new_room, join_room, ready, hostmode, leave
+            connect,
+            skip,
+            new_room,
+            join_room,
+            ready,
+            hostmode,
+            leave,
+            list_saves,
+            delete_save,
+            confirm_spending,
+            list_accounts,
+            create_account,
+            import_account,
+            delete_account,
+            validate_node_address,
+            list_networks,
+            save_network,
+            delete_network
         ])
         .setup(|app| {
             let app_handle = app.handle();
 
             let main_window = app_handle.get_window("lobby").unwrap();
             let log_window = app_handle.get_window("log").unwrap();
+            let keystore = Keystore::load(app_handle.path_resolver().app_data_dir());
 
             let filter = LevelFilter::DEBUG;
             let stdout_log = tracing_subscriber::fmt::layer().with_filter(filter);
@@ -131,9 +105,15 @@ fn main() {
 
             let need_stop = need_stop_clone.clone();
             std::thread::spawn(move || {
-                IpfsClient::new(need_stop.clone(), ipfs_reply_sender, ipfs_command_receiver)
-                    .run()
-                    .expect("IpfsClient error");
+                IpfsClient::new(
+                    need_stop.clone(),
+                    ipfs_reply_sender,
+                    ipfs_command_receiver,
+                    storage::from_env(),
+                    ArchiveCache::from_env(),
+                )
+                .run()
+                .expect("IpfsClient error");
             });
 
             let need_stop = need_stop_clone.clone();
@@ -148,8 +128,8 @@ fn main() {
                 gui_command_receiver,
                 lobby_command_sender,
                 lobby_reply_receiver,
-                main_window,
-                log_window,
+                TauriGui::new(main_window, log_window),
+                keystore,
             );
 
             tauri::async_runtime::spawn(async move {
@@ -180,21 +160,21 @@ async fn connect(
     lobby_address: String,
     username: String,
     node_address: String,
-    account_id: String,
+    account: String,
     program_id: String,
     meta_program_id: String,
     battle_program_id: String,
-    password: String,
+    password: Secret,
     gui_sender: tauri::State<'_, Sender<GuiCommand>>,
 ) -> Result<(), String> {
     info!(
-        "Received Connect from js: LobbyAddress: {lobby_address}, Username: {username}, NodeAddress: {node_address}, ProgramID: {program_id}, AccountID: {account_id}");
+        "Received Connect from js: LobbyAddress: {lobby_address}, Username: {username}, NodeAddress: {node_address}, ProgramID: {program_id}, Account: {account}");
 
     let cmd = GuiCommand::Connect {
         lobby_address,
         username,
         node_address,
-        account_id,
+        account,
         program_id,
         meta_program_id,
         battle_program_id,
@@ -283,3 +263,120 @@ async fn hostmode(
 
     Ok(())
 }
+
+#[tauri::command]
+async fn list_saves(gui_sender: tauri::State<'_, Sender<GuiCommand>>) -> Result<(), String> {
+    let cmd = GuiCommand::ListSaves;
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_save(
+    cid: String,
+    unpin: bool,
+    gui_sender: tauri::State<'_, Sender<GuiCommand>>,
+) -> Result<(), String> {
+    let cmd = GuiCommand::DeleteSave { cid, unpin };
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn confirm_spending(
+    approved: bool,
+    for_all: bool,
+    gui_sender: tauri::State<'_, Sender<GuiCommand>>,
+) -> Result<(), String> {
+    let cmd = GuiCommand::ConfirmSpending { approved, for_all };
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_accounts(gui_sender: tauri::State<'_, Sender<GuiCommand>>) -> Result<(), String> {
+    let cmd = GuiCommand::ListAccounts;
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn create_account(
+    name: String,
+    password: Secret,
+    gui_sender: tauri::State<'_, Sender<GuiCommand>>,
+) -> Result<(), String> {
+    let cmd = GuiCommand::CreateAccount { name, password };
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn import_account(
+    name: String,
+    suri: Secret,
+    password: Secret,
+    gui_sender: tauri::State<'_, Sender<GuiCommand>>,
+) -> Result<(), String> {
+    let cmd = GuiCommand::ImportAccount {
+        name,
+        suri,
+        password,
+    };
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_account(
+    name: String,
+    password: Secret,
+    gui_sender: tauri::State<'_, Sender<GuiCommand>>,
+) -> Result<(), String> {
+    let cmd = GuiCommand::DeleteAccount { name, password };
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+/// Returns the node address as it will be used, or why it can't be.
+#[tauri::command]
+fn validate_node_address(node_address: String) -> Result<String, String> {
+    NodeUrl::parse(&node_address).map(|url| url.to_string())
+}
+
+#[tauri::command]
+async fn list_networks(gui_sender: tauri::State<'_, Sender<GuiCommand>>) -> Result<(), String> {
+    let cmd = GuiCommand::ListNetworks;
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn save_network(
+    profile: NetworkProfile,
+    gui_sender: tauri::State<'_, Sender<GuiCommand>>,
+) -> Result<(), String> {
+    let cmd = GuiCommand::SaveNetwork(profile);
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_network(
+    name: String,
+    gui_sender: tauri::State<'_, Sender<GuiCommand>>,
+) -> Result<(), String> {
+    let cmd = GuiCommand::DeleteNetwork { name };
+    gui_sender.send(cmd).expect("Send Error");
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use crate::utils::{load_json_or_default, store_json_atomically};
+use gclient::WSAddress;
+use gear_connector_api::endpoint::vcmi_user_data_dir;
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
+use url::Url;
+
+/// Name of the profiles file in the VCMI user data directory.
+const PROFILES_FILE_NAME: &str = "gear-connector-networks.json";
+
+/// WebSocket address of a Gear node, e.g. `wss://testnet.vara.rs` or `ws://127.0.0.1:9944`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeUrl {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+    /// Path with the query, empty for the root.
+    pub path: String,
+}
+
+impl NodeUrl {
+    /// Parses a full URL. Only `ws` and `wss` are accepted, the port defaults
+    /// to 80 and 443 respectively.
+    pub fn parse(address: &str) -> Result<Self, String> {
+        let url = Url::parse(address.trim()).map_err(|e| format!("Invalid node address: {e}"))?;
+        let scheme = url.scheme();
+        if scheme != "ws" && scheme != "wss" {
+            return Err(format!(
+                "Invalid node address: scheme {scheme} isn't ws or wss"
+            ));
+        }
+        let host = match url.host_str() {
+            Some(host) if !host.is_empty() => host,
+            _ => return Err("Invalid node address: no host".to_string()),
+        };
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err("Invalid node address: credentials aren't supported".to_string());
+        }
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| "Invalid node address: no port".to_string())?;
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path = format!("{path}?{query}");
+        }
+        if path == "/" {
+            path.clear();
+        }
+        Ok(Self {
+            scheme: scheme.to_string(),
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    pub fn ws_address(&self) -> WSAddress {
+        // WSAddress appends the port after everything else, so a path goes into the URL itself
+        if self.path.is_empty() {
+            WSAddress::new(format!("{}://{}", self.scheme, self.host), self.port)
+        } else {
+            WSAddress::new(self.to_string(), None)
+        }
+    }
+}
+
+impl fmt::Display for NodeUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}:{}{}",
+            self.scheme, self.host, self.port, self.path
+        )
+    }
+}
+
+/// A node together with the programs the game uses on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkProfile {
+    pub name: String,
+    pub node_address: String,
+    pub program_id: String,
+    pub meta_program_id: String,
+    pub battle_program_id: String,
+    /// Built-in profiles can't be changed or deleted.
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+/// Network profiles offered in the lobby: the built-in ones followed by the ones
+/// the player saved, which are kept in the VCMI user data directory.
+pub struct NetworkProfiles {
+    path: Option<PathBuf>,
+    custom: Vec<NetworkProfile>,
+}
+
+impl NetworkProfiles {
+    /// Loads the saved profiles, starting with none if the file is missing or unreadable.
+    pub fn load() -> Self {
+        let path = vcmi_user_data_dir().map(|dir| dir.join(PROFILES_FILE_NAME));
+        let custom = path
+            .as_deref()
+            .map(load_json_or_default)
+            .unwrap_or_default();
+        Self { path, custom }
+    }
+
+    pub fn all(&self) -> Vec<NetworkProfile> {
+        builtin_profiles()
+            .into_iter()
+            .chain(self.custom.iter().cloned())
+            .collect()
+    }
+
+    /// Adds the profile or replaces the saved one with the same name.
+    pub fn save(&mut self, mut profile: NetworkProfile) -> Result<(), String> {
+        profile.name = profile.name.trim().to_string();
+        if profile.name.is_empty() {
+            return Err("A network profile needs a name".to_string());
+        }
+        if builtin_profiles().iter().any(|p| p.name == profile.name) {
+            return Err(format!("{} is a built-in network profile", profile.name));
+        }
+        profile.node_address = NodeUrl::parse(&profile.node_address)?.to_string();
+        profile.builtin = false;
+        match self.custom.iter_mut().find(|p| p.name == profile.name) {
+            Some(saved) => *saved = profile,
+            None => self.custom.push(profile),
+        }
+        self.store();
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.custom.retain(|profile| profile.name != name);
+        self.store();
+    }
+
+    fn store(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if let Err(e) = store_json_atomically(path, &self.custom) {
+            tracing::error!("Can't write {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn builtin_profiles() -> Vec<NetworkProfile> {
+    vec![
+        NetworkProfile {
+            name: "Testnet".to_string(),
+            node_address: "wss://testnet.vara.rs:443".to_string(),
+            program_id: "0x06a07d5c399af5e41fddb3c23209136c8ab38a1864e3a1607f4591825b0f1f08"
+                .to_string(),
+            meta_program_id: "0x8aa397ec9c5eff7fac6d97963ca941029ef229a48bfe008f932cc3ed8db425b7"
+                .to_string(),
+            battle_program_id: "0x2daec8e695ba66de7af85e3f861139046f3bee463e701ea4fb981d7fe8e98494"
+                .to_string(),
+            builtin: true,
+        },
+        // Programs of a dev node are uploaded by the player, so their IDs are typed in
+        NetworkProfile {
+            name: "Local dev node".to_string(),
+            node_address: "ws://127.0.0.1:9944".to_string(),
+            program_id: String::new(),
+            meta_program_id: String::new(),
+            battle_program_id: String::new(),
+            builtin: true,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_url(scheme: &str, host: &str, port: u16, path: &str) -> NodeUrl {
+        NodeUrl {
+            scheme: scheme.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn local_node() {
+        let url = NodeUrl::parse("ws://127.0.0.1:9944").unwrap();
+        assert_eq!(url, node_url("ws", "127.0.0.1", 9944, ""));
+        assert_eq!(url.to_string(), "ws://127.0.0.1:9944");
+        assert_eq!(url.ws_address().url(), "ws://127.0.0.1:9944");
+    }
+
+    #[test]
+    fn default_ports() {
+        assert_eq!(NodeUrl::parse("ws://node.local").unwrap().port, 80);
+        assert_eq!(
+            NodeUrl::parse(" wss://testnet.vara.rs/ ").unwrap(),
+            node_url("wss", "testnet.vara.rs", 443, "")
+        );
+    }
+
+    #[test]
+    fn custom_port() {
+        assert_eq!(
+            NodeUrl::parse("ws://node.local:19944").unwrap(),
+            node_url("ws", "node.local", 19944, "")
+        );
+    }
+
+    #[test]
+    fn wss_on_non_standard_port() {
+        let url = NodeUrl::parse("wss://rpc.example.com:8443").unwrap();
+        assert_eq!(url, node_url("wss", "rpc.example.com", 8443, ""));
+        assert_eq!(url.ws_address().url(), "wss://rpc.example.com:8443");
+    }
+
+    #[test]
+    fn path_and_query() {
+        let url = NodeUrl::parse("wss://rpc.example.com/vara?apikey=123").unwrap();
+        assert_eq!(
+            url,
+            node_url("wss", "rpc.example.com", 443, "/vara?apikey=123")
+        );
+        assert_eq!(url.to_string(), "wss://rpc.example.com:443/vara?apikey=123");
+    }
+
+    #[test]
+    fn ws_address_keeps_the_path_before_the_port() {
+        let url = NodeUrl::parse("wss://rpc.example.com:8443/vara/ws").unwrap();
+        assert_eq!(url.ws_address().url(), "wss://rpc.example.com:8443/vara/ws");
+    }
+
+    #[test]
+    fn rejects_other_addresses() {
+        for address in [
+            "https://testnet.vara.rs",
+            "testnet.vara.rs:443",
+            "ws://user:password@127.0.0.1:9944",
+            "ws://",
+        ] {
+            assert!(NodeUrl::parse(address).is_err(), "{address}");
+        }
+    }
+}
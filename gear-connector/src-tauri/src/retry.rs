@@ -0,0 +1,196 @@
+use crate::utils::env_number;
+use std::time::Duration;
+
+/// Number of attempts to send a Gear transaction, including the first one.
+pub const TX_ATTEMPTS_ENV: &str = "GEAR_CONNECTOR_TX_ATTEMPTS";
+/// Delay before the first resend in milliseconds, doubled on every next one.
+pub const TX_BACKOFF_ENV: &str = "GEAR_CONNECTOR_TX_BACKOFF_MS";
+
+/// How Gear transactions are repeated after a retryable failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy, changed by `TX_ATTEMPTS_ENV` and `TX_BACKOFF_ENV`.
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Some(max_attempts) = env_number::<u64>(TX_ATTEMPTS_ENV) {
+            policy.max_attempts = u32::try_from(max_attempts)
+                .unwrap_or_else(|_| {
+                    tracing::warn!(
+                        "{}={} is too large, use {}",
+                        TX_ATTEMPTS_ENV,
+                        max_attempts,
+                        u32::MAX
+                    );
+                    u32::MAX
+                })
+                .max(1);
+        }
+        if let Some(initial_delay) = env_number(TX_BACKOFF_ENV) {
+            policy.initial_delay = Duration::from_millis(initial_delay);
+            policy.max_delay = policy.max_delay.max(policy.initial_delay);
+        }
+        policy
+    }
+
+    /// Pause after the failed attempt number `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The transaction pool failed, the same transaction may pass later.
+    Retryable,
+    /// The connection to the node is lost, the transaction may pass on a new one.
+    Reconnect,
+    /// The transaction can't pass as it is, e.g. the account can't pay for it.
+    Fatal,
+}
+
+/// Fatal failures, checked first as the node wraps some of them into RPC errors.
+const FATAL_MARKERS: &[&str] = &[
+    "insufficientbalance",
+    "insufficient balance",
+    "inability to pay",
+    "programnotfound",
+    "program not found",
+    "bad origin",
+    "badorigin",
+];
+
+const RETRYABLE_MARKERS: &[&str] = &[
+    "retracted",
+    "dropped",
+    "usurped",
+    "finality timeout",
+    "finalitytimeout",
+    // Nonce races with another transaction of the same account.
+    "priority is too low",
+    "transaction is outdated",
+    "temporarily banned",
+    "timed out",
+    "timeout",
+];
+
+/// Failures of the connection itself, which the same connection doesn't recover from.
+const RECONNECT_MARKERS: &[&str] = &["connection", "restart", "broken pipe", "subscription"];
+
+/// Sorts a gclient error into an `ErrorClass`. gsdk reports most node
+/// failures as RPC errors with a message, so the message is what is checked.
+pub fn classify(error: &gclient::Error) -> ErrorClass {
+    if let gclient::Error::GearSDK(gsdk::Error::Tx(gsdk::result::TxError::Retracted(_))) = error {
+        return ErrorClass::Retryable;
+    }
+    classify_message(&format!("{error} {error:?}"))
+}
+
+fn classify_message(message: &str) -> ErrorClass {
+    let message = message.to_lowercase();
+    let has_marker = |markers: &[&str]| markers.iter().any(|marker| message.contains(marker));
+    if has_marker(FATAL_MARKERS) {
+        ErrorClass::Fatal
+    } else if has_marker(RECONNECT_MARKERS) {
+        ErrorClass::Reconnect
+    } else if has_marker(RETRYABLE_MARKERS) {
+        ErrorClass::Retryable
+    } else {
+        ErrorClass::Fatal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_errors_are_fatal() {
+        assert_eq!(
+            classify_message("Module error: Gear::Whatever"),
+            ErrorClass::Fatal
+        );
+    }
+
+    #[test]
+    fn fatal_markers_win() {
+        assert_eq!(
+            classify_message("Rpc error: Transaction is outdated: InsufficientBalance"),
+            ErrorClass::Fatal
+        );
+        assert_eq!(
+            classify_message("Invalid Transaction: Inability to pay some fees"),
+            ErrorClass::Fatal
+        );
+    }
+
+    #[test]
+    fn pool_failures_are_retried() {
+        for message in [
+            "Transaction has been retracted",
+            "Transaction was dropped",
+            "Priority is too low: (1 vs 1)",
+            "Request timed out",
+        ] {
+            assert_eq!(
+                classify_message(message),
+                ErrorClass::Retryable,
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn lost_connection_needs_reconnect() {
+        for message in [
+            "Rpc error: RPC error: The background task been terminated because: Networking or low-level protocol error: Connection reset by peer; restart required",
+            "Networking error: Broken pipe (os error 32)",
+            "Subscription dropped",
+        ] {
+            assert_eq!(classify_message(message), ErrorClass::Reconnect, "{message}");
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_millis(1000));
+        assert_eq!(policy.delay(3), Duration::from_millis(2000));
+        assert_eq!(policy.delay(4), Duration::from_secs(3));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn delay_does_not_overflow() {
+        let policy = RetryPolicy {
+            max_attempts: u32::MAX,
+            initial_delay: Duration::MAX,
+            max_delay: Duration::MAX,
+        };
+        assert_eq!(policy.delay(40), Duration::MAX);
+    }
+}
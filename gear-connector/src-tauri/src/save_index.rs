@@ -0,0 +1,70 @@
+use crate::utils::{load_json_or_default, store_json_atomically};
+use gear_connector_api::{endpoint::vcmi_user_data_dir, Checksum, SaveDescription, SaveMetadata};
+use std::{collections::HashMap, path::PathBuf};
+
+/// Name of the index file in the VCMI user data directory.
+const INDEX_FILE_NAME: &str = "gear-connector-saves.json";
+
+/// What the connector knows about the saves on chain, keyed by CID.
+/// The chain stores the name, CID and metadata of a save, so the rest is kept here
+/// and survives restarts of the connector.
+pub struct SaveIndex {
+    path: Option<PathBuf>,
+    saves: HashMap<String, SaveDescription>,
+}
+
+impl SaveIndex {
+    /// Loads the index, starting an empty one if it is missing or unreadable.
+    pub fn load() -> Self {
+        let path = vcmi_user_data_dir().map(|dir| dir.join(INDEX_FILE_NAME));
+        let saves = path
+            .as_deref()
+            .map(load_json_or_default)
+            .unwrap_or_default();
+        Self { path, saves }
+    }
+
+    /// Returns the entry of `cid`, adding it if the save is new to the index.
+    pub fn entry(&mut self, name: String, cid: String) -> &mut SaveDescription {
+        let save = self
+            .saves
+            .entry(cid.clone())
+            .or_insert_with(|| SaveDescription {
+                name: String::new(),
+                cid,
+                size: None,
+                saved_at: None,
+                metadata: None,
+                encrypted: false,
+                checksum: None,
+            });
+        save.name = name;
+        save
+    }
+
+    pub fn remove(&mut self, cid: &str) -> Option<SaveDescription> {
+        self.saves.remove(cid)
+    }
+
+    pub fn name(&self, cid: &str) -> Option<&str> {
+        self.saves.get(cid).map(|save| save.name.as_str())
+    }
+
+    pub fn checksum(&self, cid: &str) -> Option<Checksum> {
+        self.saves.get(cid).and_then(|save| save.checksum)
+    }
+
+    pub fn metadata(&self, cid: &str) -> Option<&SaveMetadata> {
+        self.saves.get(cid).and_then(|save| save.metadata.as_ref())
+    }
+
+    pub fn store(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if let Err(e) = store_json_atomically(path, &self.saves) {
+            tracing::error!("Can't write {}: {}", path.display(), e);
+        }
+    }
+}
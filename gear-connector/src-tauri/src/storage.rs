@@ -0,0 +1,235 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use gear_connector_api::{
+    endpoint::vcmi_user_data_dir,
+    transfer::{checksum, checksum_hex},
+    Checksum,
+};
+use ipfs_api_backend_hyper::{IpfsApi, IpfsClient as HyperClient, TryFromUri};
+use sha2::{Digest, Sha256};
+use std::{fs, io::Cursor, path::PathBuf};
+
+/// `ipfs` to keep archives on an IPFS node, `local` to keep them in a local directory.
+pub const STORAGE_ENV: &str = "GEAR_CONNECTOR_STORAGE";
+/// URL of the IPFS HTTP API.
+pub const IPFS_URL_ENV: &str = "GEAR_CONNECTOR_IPFS_URL";
+/// `user:password` sent to the IPFS API with basic authentication, e.g. for a pinning service.
+pub const IPFS_AUTH_ENV: &str = "GEAR_CONNECTOR_IPFS_AUTH";
+/// Directory of the local store.
+pub const STORE_DIR_ENV: &str = "GEAR_CONNECTOR_STORE_DIR";
+
+const DEFAULT_IPFS_URL: &str = "http://127.0.0.1:5001";
+/// Starts every CID made by `cid_of`: base32, version 1, codec raw, sha2-256.
+const RAW_SHA256_CID_PREFIX: &str = "bafkrei";
+/// Name of the local store in the VCMI user data directory.
+const STORE_DIR_NAME: &str = "gear-connector-store";
+
+/// Where the archives are kept. Archives are addressed by the CID the backend gives them.
+/// Errors are reasons, `IpfsClient` tells which archive they are about.
+#[async_trait(?Send)]
+pub trait StorageBackend {
+    /// Stores `data` and returns its CID.
+    async fn add(&self, data: Vec<u8>) -> Result<String, String>;
+
+    async fn cat(&self, cid: &str) -> Result<Vec<u8>, String>;
+
+    /// Reads an archive added as a directory, packed into a tar.
+    async fn tar_cat(&self, cid: &str) -> Result<Vec<u8>, String> {
+        self.cat(cid).await
+    }
+
+    /// Lets the backend drop the archive, nothing refers to it anymore.
+    async fn unpin(&self, cid: &str) -> Result<(), String>;
+}
+
+/// The backend chosen by `STORAGE_ENV`, an IPFS node unless it says `local`.
+pub fn from_env() -> Box<dyn StorageBackend> {
+    match std::env::var(STORAGE_ENV) {
+        Ok(storage) if storage.eq_ignore_ascii_case("local") => {
+            let dir = std::env::var_os(STORE_DIR_ENV)
+                .map(PathBuf::from)
+                .or_else(|| vcmi_user_data_dir().map(|dir| dir.join(STORE_DIR_NAME)))
+                .unwrap_or_else(|| PathBuf::from(STORE_DIR_NAME));
+            tracing::info!("Keep archives in {}", dir.display());
+            Box::new(LocalStore::new(dir))
+        }
+        Ok(storage) if !storage.eq_ignore_ascii_case("ipfs") => {
+            tracing::warn!("Ignore {}={:?}: not ipfs or local", STORAGE_ENV, storage);
+            Box::new(IpfsStorage::from_env())
+        }
+        _ => Box::new(IpfsStorage::from_env()),
+    }
+}
+
+/// Archives on an IPFS node, reached through its HTTP API.
+pub struct IpfsStorage {
+    client: HyperClient,
+}
+
+impl IpfsStorage {
+    /// Uses `IPFS_URL_ENV` and `IPFS_AUTH_ENV`, the local daemon by default.
+    pub fn from_env() -> Self {
+        let url = std::env::var(IPFS_URL_ENV).unwrap_or_else(|_| DEFAULT_IPFS_URL.to_string());
+        let mut client = HyperClient::from_str(&url).unwrap_or_else(|e| {
+            tracing::warn!("Ignore {}={:?}: {}", IPFS_URL_ENV, url, e);
+            HyperClient::default()
+        });
+        if let Ok(auth) = std::env::var(IPFS_AUTH_ENV) {
+            match auth.split_once(':') {
+                Some((user, password)) => client = client.with_credentials(user, password),
+                None => tracing::warn!("Ignore {}: not user:password", IPFS_AUTH_ENV),
+            }
+        }
+        Self { client }
+    }
+}
+
+#[async_trait(?Send)]
+impl StorageBackend for IpfsStorage {
+    async fn add(&self, data: Vec<u8>) -> Result<String, String> {
+        let result = self
+            .client
+            .add(Cursor::new(data))
+            .await
+            .map_err(|e| e.to_string())?;
+        tracing::debug!("Added {} to IPFS, size: {}", result.hash, result.size);
+        Ok(result.hash)
+    }
+
+    async fn cat(&self, cid: &str) -> Result<Vec<u8>, String> {
+        self.client
+            .cat(cid)
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn tar_cat(&self, cid: &str) -> Result<Vec<u8>, String> {
+        self.client
+            .tar_cat(cid)
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<(), String> {
+        self.client
+            .pin_rm(cid, true)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Archives in a local directory, one file per archive named by its CID.
+/// Needs no IPFS node, for development, LAN games and tests.
+pub struct LocalStore {
+    dir: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, cid: &str) -> Result<PathBuf, String> {
+        // The CID becomes a file name, so it must not lead out of the store
+        if cid.is_empty() || !cid.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(format!("{cid:?} is not a CID"));
+        }
+        Ok(self.dir.join(cid))
+    }
+}
+
+#[async_trait(?Send)]
+impl StorageBackend for LocalStore {
+    async fn add(&self, data: Vec<u8>) -> Result<String, String> {
+        let cid = cid_of(&data);
+        let path = self.path(&cid)?;
+        if !path.exists() {
+            let tmp_path = path.with_extension("tmp");
+            fs::create_dir_all(&self.dir)
+                .and_then(|()| fs::write(&tmp_path, data))
+                .and_then(|()| fs::rename(&tmp_path, &path))
+                .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+        }
+        Ok(cid)
+    }
+
+    async fn cat(&self, cid: &str) -> Result<Vec<u8>, String> {
+        let path = self.path(cid)?;
+        fs::read(&path).map_err(|e| format!("can't read {}: {}", path.display(), e))
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<(), String> {
+        let path = self.path(cid)?;
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("can't remove {}: {}", path.display(), e))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// CIDv1 of `data` as a single raw block hashed by SHA-256, in base32. IPFS gives
+/// this CID only to a file added with `cid-version=1` and `raw-leaves` which fits
+/// in one block, the default CIDs of IPFS are of a UnixFS DAG.
+pub fn cid_of(data: &[u8]) -> String {
+    // version 1, codec raw, multihash sha2-256 of 32 bytes
+    let mut cid = vec![0x01, 0x55, 0x12, 0x20];
+    cid.extend_from_slice(&Sha256::digest(data));
+    format!("b{}", base32(&cid))
+}
+
+/// Checks `data` against `expected`, the SHA-256 of the archive, and against its
+/// CID when the CID is made as by `cid_of`. Content which neither of them checks
+/// is rejected, as it can be anything.
+pub fn verify(cid: &str, data: &[u8], expected: Option<&Checksum>) -> Result<(), String> {
+    let raw_cid = cid.starts_with(RAW_SHA256_CID_PREFIX);
+    if raw_cid {
+        let actual = cid_of(data);
+        if actual != cid {
+            return Err(format!("the content has CID {actual}"));
+        }
+    }
+    match expected {
+        Some(expected) => {
+            let actual = checksum(data);
+            if actual != *expected {
+                return Err(format!(
+                    "the content has SHA-256 {}, not {}",
+                    checksum_hex(&actual),
+                    checksum_hex(expected)
+                ));
+            }
+        }
+        None if !raw_cid => {
+            return Err("it can't be verified, its SHA-256 is unknown".to_string());
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// RFC 4648 base32 in lower case without padding, as multibase `b` wants it.
+fn base32(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut encoded = String::with_capacity(data.len() * 8 / 5 + 1);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
@@ -0,0 +1,256 @@
+use gear_connector_api::{
+    transfer::{self, checksum_hex, CHUNK_LEN, MAX_ARCHIVE_LEN},
+    ArchiveInfo, Checksum, ConnectorError,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// How many fetched archives stay staged for `DownloadChunk`.
+const MAX_STAGED_DOWNLOADS: usize = 4;
+/// How many unfinished uploads are kept, each of them buffers up to `MAX_ARCHIVE_LEN`.
+const MAX_PARTIAL_UPLOADS: usize = 4;
+/// Unfinished uploads untouched for this long are dropped.
+const PARTIAL_UPLOAD_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Upload which isn't committed yet. It outlives the VCMI connection,
+/// so `UploadBegin` of the same archive after a reconnect resumes it.
+struct PartialUpload {
+    filename: String,
+    len: u64,
+    data: Vec<u8>,
+    /// When the upload was begun or got its last chunk.
+    touched: Instant,
+}
+
+/// Upload with all its bytes received and checked, ready to be saved.
+pub struct CompleteUpload {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// Archives in flight between VCMI and the connector, keyed by their checksum.
+#[derive(Default)]
+pub struct Transfers {
+    uploads: HashMap<Checksum, PartialUpload>,
+    downloads: HashMap<Checksum, Vec<u8>>,
+    /// Checksums of `downloads`, oldest first.
+    staged: VecDeque<Checksum>,
+}
+
+impl Transfers {
+    /// Returns the offset the upload continues from.
+    pub fn begin_upload(
+        &mut self,
+        filename: String,
+        len: u64,
+        checksum: Checksum,
+    ) -> Result<u64, ConnectorError> {
+        if len > MAX_ARCHIVE_LEN {
+            return Err(ConnectorError::Codec(format!(
+                "{filename} is {len} bytes, at most {MAX_ARCHIVE_LEN} are accepted"
+            )));
+        }
+        let now = Instant::now();
+        self.uploads
+            .retain(|_, upload| now.duration_since(upload.touched) < PARTIAL_UPLOAD_TTL);
+        if !self.uploads.contains_key(&checksum) {
+            // A newer save of the same game supersedes the unfinished one.
+            self.uploads.retain(|_, upload| upload.filename != filename);
+            self.evict_uploads(MAX_PARTIAL_UPLOADS - 1);
+        }
+        let upload = self
+            .uploads
+            .entry(checksum)
+            .or_insert_with(|| PartialUpload {
+                filename: filename.clone(),
+                len,
+                // `len` comes from the peer, so the buffer grows with the chunks received
+                data: Vec::new(),
+                touched: now,
+            });
+        upload.filename = filename;
+        upload.touched = now;
+        tracing::info!(
+            "Upload {} ({}) from {}/{}",
+            upload.filename,
+            checksum_hex(&checksum),
+            upload.data.len(),
+            upload.len
+        );
+        Ok(upload.data.len() as u64)
+    }
+
+    /// Returns the offset of the next expected chunk.
+    pub fn write_chunk(
+        &mut self,
+        checksum: &Checksum,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<u64, ConnectorError> {
+        let upload = self
+            .uploads
+            .get_mut(checksum)
+            .ok_or_else(|| unknown_transfer(checksum))?;
+        let received = upload.data.len() as u64;
+        if offset != received {
+            // Chunk resent after a dropped connection, the peer continues from `received`.
+            return Ok(received);
+        }
+        if received + data.len() as u64 > upload.len {
+            return Err(ConnectorError::Codec(format!(
+                "chunk at {offset} overflows {} of {} bytes",
+                upload.filename, upload.len
+            )));
+        }
+        upload.data.extend_from_slice(&data);
+        upload.touched = Instant::now();
+        Ok(upload.data.len() as u64)
+    }
+
+    /// Takes a complete upload out to be saved. If the save fails, `keep_upload` puts it
+    /// back, so the commit can be retried without uploading the archive again.
+    pub fn commit_upload(&mut self, checksum: &Checksum) -> Result<CompleteUpload, ConnectorError> {
+        let upload = self
+            .uploads
+            .remove(checksum)
+            .ok_or_else(|| unknown_transfer(checksum))?;
+        if upload.data.len() as u64 != upload.len {
+            let error = ConnectorError::Codec(format!(
+                "{} is incomplete: {}/{} bytes",
+                upload.filename,
+                upload.data.len(),
+                upload.len
+            ));
+            self.uploads.insert(*checksum, upload);
+            return Err(error);
+        }
+        if transfer::checksum(&upload.data) != *checksum {
+            return Err(ConnectorError::Codec(format!(
+                "checksum mismatch of {}, upload it again",
+                upload.filename
+            )));
+        }
+        Ok(CompleteUpload {
+            filename: upload.filename,
+            data: upload.data,
+        })
+    }
+
+    pub fn keep_upload(&mut self, checksum: Checksum, upload: CompleteUpload) {
+        self.uploads.insert(
+            checksum,
+            PartialUpload {
+                filename: upload.filename,
+                len: upload.data.len() as u64,
+                data: upload.data,
+                touched: Instant::now(),
+            },
+        );
+        self.evict_uploads(MAX_PARTIAL_UPLOADS);
+    }
+
+    /// Drops the least recently touched uploads until at most `max` are left.
+    fn evict_uploads(&mut self, max: usize) {
+        while self.uploads.len() > max {
+            let oldest = self
+                .uploads
+                .iter()
+                .min_by_key(|(_, upload)| upload.touched)
+                .map(|(checksum, _)| *checksum);
+            if let Some(checksum) = oldest {
+                if let Some(upload) = self.uploads.remove(&checksum) {
+                    tracing::warn!(
+                        "Drop unfinished upload of {} at {}/{}",
+                        upload.filename,
+                        upload.data.len(),
+                        upload.len
+                    );
+                }
+            }
+        }
+    }
+
+    /// Stages a fetched archive, dropping the oldest one if too many are staged.
+    pub fn stage_download(&mut self, filename: String, data: Vec<u8>) -> ArchiveInfo {
+        let info = ArchiveInfo {
+            filename,
+            len: data.len() as u64,
+            checksum: transfer::checksum(&data),
+        };
+        if self.downloads.insert(info.checksum, data).is_none() {
+            self.staged.push_back(info.checksum);
+        }
+        while self.staged.len() > MAX_STAGED_DOWNLOADS {
+            if let Some(checksum) = self.staged.pop_front() {
+                self.downloads.remove(&checksum);
+            }
+        }
+        info
+    }
+
+    pub fn read_chunk(
+        &self,
+        checksum: &Checksum,
+        offset: u64,
+        len: u32,
+    ) -> Result<Vec<u8>, ConnectorError> {
+        let data = self
+            .downloads
+            .get(checksum)
+            .ok_or_else(|| unknown_transfer(checksum))?;
+        let start = offset.min(data.len() as u64) as usize;
+        let len = (len as usize).min(CHUNK_LEN).min(data.len() - start);
+        Ok(data[start..start + len].to_vec())
+    }
+}
+
+fn unknown_transfer(checksum: &Checksum) -> ConnectorError {
+    ConnectorError::Internal(format!("unknown transfer {}", checksum_hex(checksum)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn begin(transfers: &mut Transfers, filename: &str) -> Checksum {
+        let checksum = transfer::checksum(filename.as_bytes());
+        transfers
+            .begin_upload(filename.to_string(), filename.len() as u64, checksum)
+            .unwrap();
+        checksum
+    }
+
+    #[test]
+    fn least_recently_touched_upload_is_dropped() {
+        let mut transfers = Transfers::default();
+        let first = begin(&mut transfers, "first.vsgm1");
+        for n in 1..MAX_PARTIAL_UPLOADS {
+            begin(&mut transfers, &format!("save{n}.vsgm1"));
+        }
+        // A chunk makes the first upload the latest one
+        transfers.write_chunk(&first, 0, b"first".to_vec()).unwrap();
+
+        let another = begin(&mut transfers, "another.vsgm1");
+        assert_eq!(transfers.uploads.len(), MAX_PARTIAL_UPLOADS);
+        assert!(transfers.uploads.contains_key(&first));
+        assert!(transfers.uploads.contains_key(&another));
+    }
+
+    #[test]
+    fn stale_uploads_are_dropped() {
+        let mut transfers = Transfers::default();
+        let stale = begin(&mut transfers, "stale.vsgm1");
+        let upload = transfers.uploads.get_mut(&stale).unwrap();
+        upload.touched = match upload.touched.checked_sub(PARTIAL_UPLOAD_TTL) {
+            Some(touched) => touched,
+            // The clock started less than the TTL ago
+            None => return,
+        };
+
+        let fresh = begin(&mut transfers, "fresh.vsgm1");
+        assert!(!transfers.uploads.contains_key(&stale));
+        assert!(transfers.uploads.contains_key(&fresh));
+    }
+}
@@ -1,6 +1,12 @@
-use std::fmt::{self};
+use std::{
+    fmt::{self},
+    fs, io,
+    path::Path,
+    str::FromStr,
+};
 
 use gear_connector_api::SecondarySkill;
+use serde::{de::DeserializeOwned, Serialize};
 use tauri::Window;
 use tracing::{
     field::{Field, Visit},
@@ -432,3 +438,49 @@ fn convert_terrain_type2(terrain_type: homm3_battle_io::Terrain) -> gear_connect
         }
     }
 }
+
+/// Reads `path` as JSON, `None` if the file doesn't exist.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    match fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| format!("Can't parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Can't read {}: {}", path.display(), e)),
+    }
+}
+
+/// Reads `path` as JSON, the default value if the file is missing or unreadable.
+pub fn load_json_or_default<T: DeserializeOwned + Default>(path: &Path) -> T {
+    read_json(path)
+        .unwrap_or_else(|e| {
+            tracing::error!("{}", e);
+            None
+        })
+        .unwrap_or_default()
+}
+
+/// Writes `value` to `path` as JSON, through a temporary file so a crash doesn't truncate it.
+pub fn store_json_atomically<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let data = serde_json::to_vec_pretty(value)?;
+    let tmp_path = path.with_extension("json.tmp");
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&tmp_path, data))
+        .and_then(|()| fs::rename(&tmp_path, path))
+}
+
+/// Number in the environment variable `name`, ignored with a warning if it isn't one.
+pub fn env_number<T: FromStr>(name: &str) -> Option<T>
+where
+    T::Err: fmt::Display,
+{
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(number) => Some(number),
+        Err(e) => {
+            tracing::warn!("Ignore {}={:?}: {}", name, value, e);
+            None
+        }
+    }
+}
@@ -1,27 +1,60 @@
 use crate::gear_client::RECV_TIMEOUT;
 use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
 use futures::{SinkExt, StreamExt};
-use gear_connector_api::{utils::*, VcmiCommand, VcmiReply};
+use gear_connector_api::{
+    codec::WireFormat,
+    endpoint::{publish_address, unpublish_address},
+    handshake::{server_handshake, Capability},
+    utils::*,
+    ReplyEnvelope, RequestId, VcmiCommand, VcmiReply,
+};
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::Relaxed;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::{Arc, Mutex};
 use tokio::net::TcpListener;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+pub const BUILD_ID: &str = concat!("gear-connector-", env!("CARGO_PKG_VERSION"));
+pub const CAPABILITIES: &[Capability] = &[
+    Capability::BattleSimulation,
+    Capability::ArchiveSave,
+    Capability::GameStateSave,
+];
+
+/// Where the reply to a `VcmiCommand` has to be delivered: the VCMI connection
+/// which sent the command and the request ID chosen by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReplyTo {
+    pub connection_id: u64,
+    pub request_id: RequestId,
+}
+
+type ReplyRoutes = Arc<Mutex<HashMap<u64, UnboundedSender<ReplyEnvelope>>>>;
+
+static LOCAL_ADDRESS: OnceCell<SocketAddr> = OnceCell::new();
+
+/// Address the server is bound to, passed to the vcmiclient spawned by the connector.
+pub fn local_address() -> Option<SocketAddr> {
+    LOCAL_ADDRESS.get().copied()
+}
 
 #[derive(Debug)]
 pub struct VcmiServer {
     need_stop: Arc<AtomicBool>,
     address: SocketAddr,
-    vcmi_command_sender: Sender<VcmiCommand>,
-    vcmi_reply_receiver: Receiver<VcmiReply>,
+    vcmi_command_sender: Sender<(ReplyTo, VcmiCommand)>,
+    vcmi_reply_receiver: Receiver<(ReplyTo, VcmiReply)>,
 }
 
 impl VcmiServer {
     pub async fn new(
         need_stop: Arc<AtomicBool>,
         address: SocketAddr,
-        vcmi_command_sender: Sender<VcmiCommand>,
-        vcmi_reply_receiver: Receiver<VcmiReply>,
+        vcmi_command_sender: Sender<(ReplyTo, VcmiCommand)>,
+        vcmi_reply_receiver: Receiver<(ReplyTo, VcmiReply)>,
     ) -> Self {
         tracing::debug!("Create Server");
         Self {
@@ -38,65 +71,170 @@ impl VcmiServer {
         let vcmi_reply_receiver = self.vcmi_reply_receiver.clone();
         let need_stop_clone = self.need_stop.clone();
         let listener = TcpListener::bind(self.address).await?;
+        let local_address = listener.local_addr()?;
+        let _ = LOCAL_ADDRESS.set(local_address);
+        match publish_address(local_address) {
+            Ok(path) => tracing::info!(
+                "Listen VCMI on {}, published to {}",
+                local_address,
+                path.display()
+            ),
+            Err(e) => tracing::warn!("Listen VCMI on {}, can't publish it: {}", local_address, e),
+        }
+        let routes: ReplyRoutes = Default::default();
+
+        // Logic takes one command at a time. Commands wait for it in this queue, so the
+        // connection tasks never block and keep answering pings while Logic is busy.
+        let (command_sender, mut command_receiver) = unbounded_channel();
+        let need_stop = need_stop_clone.clone();
+        std::thread::spawn(move || {
+            while let Some(command) = command_receiver.blocking_recv() {
+                if vcmi_command_sender.send(command).is_err() {
+                    tracing::error!(
+                        "Can't send command to Logic. Maybe thread crashed incorrectly"
+                    );
+                    need_stop.store(true, Relaxed);
+                    break;
+                }
+            }
+        });
+
+        let need_stop = need_stop_clone.clone();
+        let reply_routes = routes.clone();
+        std::thread::spawn(move || {
+            while !need_stop.load(Relaxed) {
+                match vcmi_reply_receiver.recv_timeout(RECV_TIMEOUT) {
+                    Ok((reply_to, reply)) => {
+                        match &reply {
+                            VcmiReply::DownloadedChunk { offset, data } => {
+                                tracing::debug!(
+                                    "Send Reply to VCMI: DownloadedChunk offset: {}, len: {}",
+                                    offset,
+                                    data.len()
+                                );
+                            }
+                            _ => tracing::info!("Send Reply to VCMI: {:?}", reply),
+                        }
+
+                        let route = reply_routes
+                            .lock()
+                            .unwrap()
+                            .get(&reply_to.connection_id)
+                            .cloned();
+                        let envelope = ReplyEnvelope {
+                            request_id: reply_to.request_id,
+                            reply,
+                        };
+                        match route {
+                            Some(route) if route.send(envelope).is_ok() => {}
+                            _ => tracing::warn!(
+                                "VCMI connection {} is closed, drop reply to request {}",
+                                reply_to.connection_id,
+                                reply_to.request_id
+                            ),
+                        }
+                    }
+                    Err(error) if error == RecvTimeoutError::Timeout => {}
+                    Err(error) => {
+                        tracing::error!("Error in another thread: {}", error);
+                        need_stop.store(true, Relaxed);
+                    }
+                }
+            }
+        });
 
         tokio::spawn(async move {
             let need_stop = need_stop_clone.clone();
+            let next_connection_id = AtomicU64::new(0);
             while !need_stop.load(Relaxed) {
                 match listener.accept().await {
-                    Ok((stream, _addr)) => {
-                        tracing::info!("Connected");
-                        let (mut read_stream, mut write_stream) =
-                            wrap_to_command_read_reply_write(stream);
+                    Ok((mut stream, addr)) => {
+                        let connection_id = next_connection_id.fetch_add(1, Relaxed);
                         let need_stop_clone = need_stop.clone();
-                        let vcmi_command_sender = vcmi_command_sender.clone();
-
+                        let command_sender = command_sender.clone();
+                        let routes = routes.clone();
+                        // The handshake runs in the task of the connection, so a peer
+                        // which sends nothing doesn't hold up the other ones
                         tokio::spawn(async move {
-                            while !need_stop_clone.load(Relaxed) {
-                                let command = match read_stream.next().await {
-                                    Some(data) => data.expect("Can't parse"),
-                                    None => continue,
-                                };
+                            let handshake = server_handshake(
+                                &mut stream,
+                                BUILD_ID,
+                                CAPABILITIES,
+                                WireFormat::ALL,
+                            )
+                            .await;
+                            let wire_format = match handshake {
+                                Ok((hello, negotiated)) => {
+                                    tracing::info!(
+                                        "Connected {} ({}), capabilities: {:?}, wire format: {}",
+                                        addr,
+                                        hello.build_id,
+                                        negotiated.capabilities,
+                                        negotiated.wire_format
+                                    );
+                                    negotiated.wire_format
+                                }
+                                Err(e) => {
+                                    tracing::error!("Refused VCMI peer {}: {}", addr, e);
+                                    return;
+                                }
+                            };
+                            let (mut read_stream, mut write_stream) =
+                                wrap_to_command_read_reply_write(stream, wire_format);
+                            let (reply_sender, mut reply_receiver) = unbounded_channel();
+                            routes.lock().unwrap().insert(connection_id, reply_sender);
 
-                                vcmi_command_sender.send(command).expect(
-                                    "Can't send command to Logic. Maybe thread crashed incorrectly",
-                                );
-                            }
-                        });
+                            tokio::spawn(async move {
+                                while let Some(envelope) = reply_receiver.recv().await {
+                                    if let Err(e) = write_stream.send(envelope).await {
+                                        tracing::error!("Cant' send VcmiReply to {}: {}", addr, e);
+                                        break;
+                                    }
+                                }
+                            });
 
-                        let need_stop_clone = need_stop.clone();
-                        let vcmi_reply_receiver = vcmi_reply_receiver.clone();
-                        tokio::spawn(async move {
                             while !need_stop_clone.load(Relaxed) {
-                                let reply = vcmi_reply_receiver.recv_timeout(RECV_TIMEOUT);
-                                match reply {
-                                    Ok(reply) => {
-                                        match &reply {
-                                            VcmiReply::AllLoaded { archives } => {
-                                                tracing::info!(
-                                                    "Send Reply to VCMI: AllLoaded len: {}",
-                                                    archives.len()
-                                                );
-                                            }
-                                            _ => tracing::info!("Send Reply to VCMI: {:?}", reply),
-                                        }
-
-                                        write_stream
-                                            .send(reply)
-                                            .await
-                                            .expect("Cant' send VcmiReply");
+                                let envelope = match read_stream.next().await {
+                                    Some(Ok(envelope)) => envelope,
+                                    Some(Err(e)) => {
+                                        tracing::error!(
+                                            "Can't parse VcmiCommand from {}: {}",
+                                            addr,
+                                            e
+                                        );
+                                        break;
                                     }
-                                    Err(error) if error == RecvTimeoutError::Timeout => {}
-                                    Err(error) => {
-                                        tracing::error!("Error in another thread: {}", error);
-                                        need_stop_clone.store(true, Relaxed);
+                                    None => break,
+                                };
+                                if let VcmiCommand::Ping = envelope.command {
+                                    // Answered here, so a long request in Logic doesn't
+                                    // look like a dead connection.
+                                    let pong = ReplyEnvelope {
+                                        request_id: envelope.request_id,
+                                        reply: VcmiReply::Pong,
+                                    };
+                                    if let Some(route) = routes.lock().unwrap().get(&connection_id)
+                                    {
+                                        let _ = route.send(pong);
                                     }
+                                    continue;
+                                }
+                                let reply_to = ReplyTo {
+                                    connection_id,
+                                    request_id: envelope.request_id,
+                                };
+                                if command_sender.send((reply_to, envelope.command)).is_err() {
+                                    break;
                                 }
                             }
+                            routes.lock().unwrap().remove(&connection_id);
+                            tracing::info!("Disconnected {}", addr);
                         });
                     }
                     Err(e) => tracing::error!("{}", e),
                 }
             }
+            unpublish_address(local_address);
         });
 
         Ok(())
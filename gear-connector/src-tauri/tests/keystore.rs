@@ -0,0 +1,42 @@
+use gear_connector::keystore::{Keystore, Secret};
+use std::fs;
+
+#[test]
+fn damaged_keystore_is_moved_aside_not_overwritten() {
+    let dir = std::env::temp_dir().join(format!("gear-connector-keystore-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let damaged = b"[{\"name\": \"Bob\", \"salt\": ";
+    fs::write(dir.join("keystore.json"), damaged).unwrap();
+
+    let mut keystore = Keystore::load(Some(dir.clone()));
+    assert!(keystore.load_error().is_some());
+    assert!(keystore.account_names().is_empty());
+
+    let password = Secret::new("password".to_string());
+    keystore
+        .import(
+            "Alice".to_string(),
+            &Secret::new("//Alice".to_string()),
+            &password,
+        )
+        .unwrap();
+    assert!(keystore.load_error().is_none());
+
+    let aside: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().contains("keystore.json.broken-"))
+        .collect();
+    assert_eq!(aside.len(), 1);
+    assert_eq!(fs::read(&aside[0]).unwrap(), damaged);
+
+    let keystore = Keystore::load(Some(dir.clone()));
+    assert!(keystore.load_error().is_none());
+    assert_eq!(keystore.account_names(), ["Alice"]);
+    assert_eq!(
+        keystore.unlock("Alice", &password).unwrap().expose(),
+        "//Alice"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,774 @@
+//! Runs `Logic` against the fake chain and a local store. The test plays VCMI and the GUI.
+//! Archives of keystore accounts are encrypted, as by default, and read through a cache.
+//! Most tests connect as the dev account Alice, whose archives are plain.
+
+use async_trait::async_trait;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use gear_connector::{
+    archive_cache::ArchiveCache,
+    archive_cipher::is_encrypted,
+    budget::{Budget, BudgetScope},
+    fake_chain::{
+        FakeChain, FAKE_ARCHIVE_PROGRAM, FAKE_BATTLE_PROGRAM, FAKE_GAME_STATE_PROGRAM,
+        FAKE_INITIAL_BALANCE,
+    },
+    gear_client::GearClient,
+    gui::Gui,
+    ipfs_client::IpfsClient,
+    keystore::{Keystore, Secret},
+    lobby::LobbyReply,
+    logic::Logic,
+    storage::{cid_of, LocalStore, StorageBackend},
+    vcmi_server::ReplyTo,
+    GuiCommand,
+};
+use gear_connector_api::{
+    transfer::checksum, BattleInfo, BattleSide, ErrorCode, Hero, SaveMetadata, Stack, Terrain,
+    VcmiCommand, VcmiReply,
+};
+use parity_scale_codec::Decode;
+use serde::Serialize;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+        Arc, Mutex, Once,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+type Events = Arc<Mutex<Vec<(String, serde_json::Value)>>>;
+
+/// Local store which counts the archives read from it, as if they were downloaded.
+struct CountingStore {
+    store: LocalStore,
+    reads: Arc<AtomicUsize>,
+}
+
+#[async_trait(?Send)]
+impl StorageBackend for CountingStore {
+    async fn add(&self, data: Vec<u8>) -> Result<String, String> {
+        self.store.add(data).await
+    }
+
+    async fn cat(&self, cid: &str) -> Result<Vec<u8>, String> {
+        self.reads.fetch_add(1, Relaxed);
+        self.store.cat(cid).await
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<(), String> {
+        self.store.unpin(cid).await
+    }
+}
+
+/// Records what Logic shows, events of the log window are prefixed with `log:`.
+struct RecordingGui {
+    events: Events,
+}
+
+impl Gui for RecordingGui {
+    fn emit_main<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        let payload = serde_json::to_value(payload).unwrap();
+        self.events
+            .lock()
+            .unwrap()
+            .push((event.to_string(), payload));
+    }
+
+    fn emit_log<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        let payload = serde_json::to_value(payload).unwrap();
+        self.events
+            .lock()
+            .unwrap()
+            .push((format!("log:{event}"), payload));
+    }
+
+    fn show_main(&self) {}
+    fn hide_main(&self) {}
+    fn dock_log(&self) {}
+    fn shrink_log(&self) {}
+    fn raise_log(&self) {}
+    fn restore_log(&self) {}
+}
+
+struct Connector {
+    need_stop: Arc<AtomicBool>,
+    chain: FakeChain,
+    store_dir: PathBuf,
+    /// Archives read from the store, not from the cache.
+    downloads: Arc<AtomicUsize>,
+    events: Events,
+    gui_sender: Sender<GuiCommand>,
+    vcmi_command_sender: Sender<(ReplyTo, VcmiCommand)>,
+    vcmi_reply_receiver: Receiver<(ReplyTo, VcmiReply)>,
+    /// Kept so Logic doesn't see the lobby go down.
+    _lobby_reply_sender: Sender<LobbyReply>,
+    next_request_id: u64,
+}
+
+impl Connector {
+    fn start() -> Self {
+        Self::start_with_budget(Budget::new(None, BudgetScope::Session))
+    }
+
+    fn start_with_budget(budget: Budget) -> Self {
+        static DATA_DIR: Once = Once::new();
+        static STORES: AtomicUsize = AtomicUsize::new(0);
+        // The save index goes to the VCMI user data directory
+        DATA_DIR.call_once(|| {
+            let dir = test_dir();
+            std::env::set_var("XDG_DATA_HOME", &dir);
+            std::env::set_var("HOME", &dir);
+            std::env::set_var("USERPROFILE", &dir);
+        });
+        let store = STORES.fetch_add(1, Relaxed);
+        let store_dir = test_dir().join(format!("store-{store}"));
+        let cache_dir = test_dir().join(format!("cache-{store}"));
+        let downloads = Arc::new(AtomicUsize::new(0));
+
+        let (vcmi_command_sender, vcmi_command_receiver) = bounded(1);
+        let (vcmi_reply_sender, vcmi_reply_receiver) = bounded(1);
+        let (gui_sender, gui_command_receiver) = bounded(1);
+        let (gear_command_sender, gear_command_receiver) = bounded(1);
+        let (gear_reply_sender, gear_reply_receiver) = bounded(1);
+        let (ipfs_command_sender, ipfs_command_receiver) = bounded(1);
+        let (ipfs_reply_sender, ipfs_reply_receiver) = bounded(1);
+        let (lobby_command_sender, lobby_command_receiver) = bounded(1);
+        let (lobby_reply_sender, lobby_reply_receiver) = bounded(1);
+
+        let need_stop = Arc::new(AtomicBool::new(false));
+        let chain = FakeChain::new();
+        let events = Events::default();
+
+        let stop = need_stop.clone();
+        let backend = chain.clone();
+        thread::spawn(move || {
+            GearClient::with_backend(stop, gear_command_receiver, gear_reply_sender, backend).run()
+        });
+        let stop = need_stop.clone();
+        let storage = CountingStore {
+            store: LocalStore::new(store_dir.clone()),
+            reads: downloads.clone(),
+        };
+        thread::spawn(move || {
+            let cache = ArchiveCache::new(cache_dir, 1024 * 1024);
+            IpfsClient::new(
+                stop,
+                ipfs_reply_sender,
+                ipfs_command_receiver,
+                Box::new(storage),
+                cache,
+            )
+            .run()
+            .unwrap()
+        });
+        thread::spawn(move || for _ in lobby_command_receiver.iter() {});
+
+        let stop = need_stop.clone();
+        let gui = RecordingGui {
+            events: events.clone(),
+        };
+        thread::spawn(move || {
+            let mut logic = Logic::new(
+                stop,
+                gear_command_sender,
+                gear_reply_receiver,
+                vcmi_command_receiver,
+                vcmi_reply_sender,
+                ipfs_reply_receiver,
+                ipfs_command_sender,
+                gui_command_receiver,
+                lobby_command_sender,
+                lobby_reply_receiver,
+                gui,
+                Keystore::load(None),
+            )
+            .with_budget(budget);
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(logic.run());
+        });
+
+        Self {
+            need_stop,
+            chain,
+            store_dir,
+            downloads,
+            events,
+            gui_sender,
+            vcmi_command_sender,
+            vcmi_reply_receiver,
+            _lobby_reply_sender: lobby_reply_sender,
+            next_request_id: 1,
+        }
+    }
+
+    /// Connects as Alice and waits until Logic shows the account.
+    fn connect(&self) {
+        self.connect_as(String::new(), Secret::default());
+    }
+
+    /// Imports the account with secret URI `suri` to the keystore and connects as it.
+    fn connect_with_account(&self, suri: &str) {
+        let password = Secret::new("password".to_string());
+        self.gui_sender
+            .send(GuiCommand::ImportAccount {
+                name: "player".to_string(),
+                suri: Secret::new(suri.to_string()),
+                password: password.clone(),
+            })
+            .unwrap();
+        self.wait_for_event("accounts");
+        self.connect_as("player".to_string(), password);
+    }
+
+    fn connect_as(&self, account: String, password: Secret) {
+        let program_id = |id: [u8; 32]| format!("0x{}", hex::encode(id));
+        self.gui_sender
+            .send(GuiCommand::Connect {
+                lobby_address: "127.0.0.1:5002".to_string(),
+                username: "player".to_string(),
+                node_address: "ws://127.0.0.1:9944".to_string(),
+                program_id: program_id(FAKE_ARCHIVE_PROGRAM),
+                meta_program_id: program_id(FAKE_GAME_STATE_PROGRAM),
+                battle_program_id: program_id(FAKE_BATTLE_PROGRAM),
+                account,
+                password,
+            })
+            .unwrap();
+        self.wait_for_event("log:update_account_id");
+    }
+
+    fn request(&mut self, command: VcmiCommand) -> VcmiReply {
+        let reply_to = self.send(command);
+        self.reply(reply_to)
+    }
+
+    /// Sends a request without waiting for its reply.
+    fn send(&mut self, command: VcmiCommand) -> ReplyTo {
+        let reply_to = ReplyTo {
+            connection_id: 1,
+            request_id: self.next_request_id,
+        };
+        self.next_request_id += 1;
+        self.vcmi_command_sender.send((reply_to, command)).unwrap();
+        reply_to
+    }
+
+    fn reply(&self, reply_to: ReplyTo) -> VcmiReply {
+        let (to, reply) = self.vcmi_reply_receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(to, reply_to);
+        reply
+    }
+
+    /// Uploads `data` as VCMI does after a save and returns the CID it got.
+    fn save(&mut self, filename: &str, data: &[u8]) -> String {
+        let checksum = checksum(data);
+        let reply = self.request(VcmiCommand::UploadBegin {
+            filename: filename.to_string(),
+            len: data.len() as u64,
+            checksum,
+        });
+        assert!(matches!(reply, VcmiReply::UploadOffset { offset: 0 }));
+        let reply = self.request(VcmiCommand::UploadChunk {
+            checksum,
+            offset: 0,
+            data: data.to_vec(),
+        });
+        assert!(matches!(reply, VcmiReply::UploadOffset { offset } if offset == data.len() as u64));
+        let reply = self.request(VcmiCommand::UploadCommit {
+            checksum,
+            metadata: metadata(),
+        });
+        assert!(matches!(reply, VcmiReply::Saved), "{reply:?}");
+        self.cid_of_save(filename)
+    }
+
+    /// CID of the archive on chain, which differs from `cid_of` the saved data when it is encrypted.
+    fn cid_of_save(&mut self, filename: &str) -> String {
+        self.list_saves()
+            .into_iter()
+            .find(|(name, _)| name == filename)
+            .map(|(_, cid)| cid)
+            .unwrap_or_else(|| panic!("No save {filename}"))
+    }
+
+    /// Reads an archive as anyone can read it from IPFS.
+    fn stored_archive(&self, cid: &str) -> Vec<u8> {
+        futures::executor::block_on(LocalStore::new(self.store_dir.clone()).cat(cid)).unwrap()
+    }
+
+    /// Fetches a save and downloads the archive VCMI gets.
+    fn fetch(&mut self, cid: String) -> Vec<u8> {
+        let info = match self.request(VcmiCommand::FetchSave { cid }) {
+            VcmiReply::Fetched(info) => info,
+            reply => panic!("Unexpected reply to FetchSave: {reply:?}"),
+        };
+        let reply = self.request(VcmiCommand::DownloadChunk {
+            checksum: info.checksum,
+            offset: 0,
+            len: info.len as u32,
+        });
+        match reply {
+            VcmiReply::DownloadedChunk { data, .. } => data,
+            reply => panic!("Unexpected reply to DownloadChunk: {reply:?}"),
+        }
+    }
+
+    fn list_saves(&mut self) -> Vec<(String, String)> {
+        match self.request(VcmiCommand::ListSaves) {
+            VcmiReply::Saves(saves) => saves
+                .into_iter()
+                .map(|save| (save.name, save.cid))
+                .collect(),
+            reply => panic!("Unexpected reply to ListSaves: {reply:?}"),
+        }
+    }
+
+    fn wait_for_event(&self, event: &str) -> serde_json::Value {
+        wait_until(|| {
+            self.events
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(name, _)| name == event)
+                .map(|(_, payload)| payload.clone())
+        })
+    }
+
+    fn events_named(&self, event: &str) -> usize {
+        let events = self.events.lock().unwrap();
+        events.iter().filter(|(name, _)| name == event).count()
+    }
+}
+
+impl Drop for Connector {
+    fn drop(&mut self) {
+        self.need_stop.store(true, Relaxed);
+    }
+}
+
+fn test_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("gear-connector-{}", std::process::id()))
+}
+
+fn wait_until<T>(mut condition: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = condition() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "Timed out");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn metadata() -> SaveMetadata {
+    SaveMetadata {
+        map_name: "Arrogance".to_string(),
+        map_width: 72,
+        map_height: 72,
+        two_levels: false,
+        day: 3,
+        players: Vec::new(),
+        vcmi_version: "1.2".to_string(),
+        mods: Vec::new(),
+        uncompressed_size: 4096,
+        encrypted: None,
+    }
+}
+
+fn hero(name: &str) -> Hero {
+    Hero {
+        name: name.to_string(),
+        level: 1,
+        mana: 10,
+        sex: 0,
+        experience_points: 0,
+        secondary_skills: Vec::new(),
+        stacks: Default::default(),
+    }
+}
+
+fn battle_info() -> BattleInfo {
+    let stack = |name: &str, count| Stack {
+        name: name.to_string(),
+        level: 1,
+        count,
+    };
+    BattleInfo {
+        stacks: vec![stack("Pikeman", 20), stack("Imp", 40)],
+        sides: [
+            BattleSide {
+                color: "red".to_string(),
+                hero: hero("Orrin"),
+            },
+            BattleSide {
+                color: "blue".to_string(),
+                hero: hero("Calh"),
+            },
+        ],
+        round: 1,
+        active_stack: 0,
+        terrain_type: Terrain::Grass,
+    }
+}
+
+#[test]
+fn save_list_and_fetch() {
+    let mut connector = Connector::start();
+    connector.connect();
+
+    let data = b"zipped save of the first week".to_vec();
+    let cid = connector.save("Arrogance.vsgm1", &data);
+    assert_eq!(
+        connector.list_saves(),
+        vec![("Arrogance.vsgm1".to_string(), cid.clone())]
+    );
+    let on_chain = connector.chain.saves_of(FakeChain::account_of(None));
+    assert_eq!(on_chain.len(), 1);
+    assert_eq!(
+        SaveMetadata::decode(&mut on_chain[0].metadata.as_slice()).unwrap(),
+        SaveMetadata {
+            encrypted: Some(false),
+            ..metadata()
+        }
+    );
+    assert_eq!(connector.fetch(cid), data);
+}
+
+#[test]
+fn archives_are_encrypted_and_plain_ones_still_load() {
+    let mut connector = Connector::start();
+    connector.connect_with_account("//Bob");
+
+    let data = b"zipped save with the fog of war".to_vec();
+    let cid = connector.save("Arrogance.vsgm1", &data);
+    let stored = connector.stored_archive(&cid);
+    assert!(is_encrypted(&stored));
+    assert!(!stored.windows(data.len()).any(|window| window == data));
+    let on_chain = connector
+        .chain
+        .saves_of(FakeChain::account_of(Some("//Bob")));
+    let on_chain = SaveMetadata::decode(&mut on_chain[0].metadata.as_slice()).unwrap();
+    assert_eq!(on_chain.encrypted, Some(true));
+    match connector.request(VcmiCommand::ListSaves) {
+        VcmiReply::Saves(saves) => assert!(saves[0].encrypted),
+        reply => panic!("Unexpected reply to ListSaves: {reply:?}"),
+    }
+    assert_eq!(connector.fetch(cid), data);
+
+    // Uploaded before the archives were encrypted
+    let plain = b"PK zipped save of an older connector".to_vec();
+    let store = LocalStore::new(connector.store_dir.clone());
+    let plain_cid = futures::executor::block_on(store.add(plain.clone())).unwrap();
+    assert_eq!(plain_cid, cid_of(&plain));
+    assert_eq!(connector.fetch(plain_cid), plain);
+
+    let mut tampered = stored;
+    *tampered.last_mut().unwrap() ^= 1;
+    let tampered_cid = futures::executor::block_on(store.add(tampered)).unwrap();
+    match connector.request(VcmiCommand::FetchSave { cid: tampered_cid }) {
+        VcmiReply::Error { code, .. } => assert_eq!(code, ErrorCode::Codec),
+        reply => panic!("Unexpected reply to FetchSave: {reply:?}"),
+    }
+}
+
+#[test]
+fn dev_account_archives_are_plain() {
+    let mut connector = Connector::start();
+    connector.connect();
+
+    let data = b"zipped save anyone can read".to_vec();
+    let cid = connector.save("public.vsgm1", &data);
+    assert_eq!(connector.stored_archive(&cid), data);
+    match connector.request(VcmiCommand::ListSaves) {
+        VcmiReply::Saves(saves) => assert!(!saves[0].encrypted),
+        reply => panic!("Unexpected reply to ListSaves: {reply:?}"),
+    }
+    assert_eq!(connector.fetch(cid), data);
+
+    // The flag on chain tells a plain archive from an encrypted one, not its first bytes
+    let data = b"GCSAVE\x01\x00 plain archive starting like an encrypted one".to_vec();
+    let cid = connector.save("lookalike.vsgm1", &data);
+    assert_eq!(connector.fetch(cid), data);
+}
+
+#[test]
+fn downloads_are_verified_and_cached() {
+    let mut connector = Connector::start();
+    connector.connect();
+
+    let data = b"zipped save to load twice".to_vec();
+    let cid = connector.save("twice.vsgm1", &data);
+    assert_eq!(connector.fetch(cid.clone()), data);
+    assert_eq!(connector.downloads.load(Relaxed), 1);
+    assert_eq!(connector.fetch(cid), data);
+    assert_eq!(connector.downloads.load(Relaxed), 1);
+
+    // The store gives other content than the CID names
+    let corrupted_cid = cid_of(b"zipped save");
+    std::fs::write(connector.store_dir.join(&corrupted_cid), b"garbage").unwrap();
+    match connector.request(VcmiCommand::FetchSave { cid: corrupted_cid }) {
+        VcmiReply::Error { code, message, .. } => {
+            assert_eq!(code, ErrorCode::Ipfs);
+            assert!(message.contains("the content has CID"), "{message}");
+        }
+        reply => panic!("Unexpected reply to FetchSave: {reply:?}"),
+    }
+
+    // Neither the CID nor the chain tells the hash of this one
+    let unknown_cid = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+    std::fs::write(connector.store_dir.join(unknown_cid), b"zipped save").unwrap();
+    let fetch = VcmiCommand::FetchSave {
+        cid: unknown_cid.to_string(),
+    };
+    match connector.request(fetch) {
+        VcmiReply::Error { code, message, .. } => {
+            assert_eq!(code, ErrorCode::Ipfs);
+            assert!(message.contains("can't be verified"), "{message}");
+        }
+        reply => panic!("Unexpected reply to FetchSave: {reply:?}"),
+    }
+}
+
+#[test]
+fn rename_replace_and_delete() {
+    let mut connector = Connector::start();
+    connector.connect();
+
+    let cid = connector.save("first.vsgm1", b"first");
+    let reply = connector.request(VcmiCommand::RenameSave {
+        cid: cid.clone(),
+        name: "renamed.vsgm1".to_string(),
+    });
+    assert!(matches!(reply, VcmiReply::Saved), "{reply:?}");
+    assert_eq!(
+        connector.list_saves(),
+        vec![("renamed.vsgm1".to_string(), cid.clone())]
+    );
+
+    let data = b"second";
+    let checksum = checksum(data);
+    connector.request(VcmiCommand::UploadBegin {
+        filename: "second.vsgm1".to_string(),
+        len: data.len() as u64,
+        checksum,
+    });
+    connector.request(VcmiCommand::UploadChunk {
+        checksum,
+        offset: 0,
+        data: data.to_vec(),
+    });
+    let reply = connector.request(VcmiCommand::ReplaceSave {
+        cid,
+        checksum,
+        metadata: metadata(),
+    });
+    assert!(matches!(reply, VcmiReply::Saved), "{reply:?}");
+    let replaced = connector.cid_of_save("second.vsgm1");
+    assert_eq!(
+        connector.list_saves(),
+        vec![("second.vsgm1".to_string(), replaced.clone())]
+    );
+
+    let reply = connector.request(VcmiCommand::DeleteSave {
+        cid: replaced,
+        unpin: true,
+    });
+    assert!(matches!(reply, VcmiReply::Deleted), "{reply:?}");
+    assert!(connector.list_saves().is_empty());
+}
+
+#[test]
+fn battle_and_game_state_are_paid_for() {
+    let mut connector = Connector::start();
+    connector.connect();
+
+    let reply = connector.request(VcmiCommand::SimulateBattle(battle_info()));
+    let result = match reply {
+        VcmiReply::BattleInfo(result) => result,
+        reply => panic!("Unexpected reply to SimulateBattle: {reply:?}"),
+    };
+    assert_eq!(result.round, 2);
+    assert_eq!(
+        result
+            .stacks
+            .iter()
+            .map(|stack| stack.count)
+            .collect::<Vec<_>>(),
+        vec![15, 30]
+    );
+    assert_eq!(connector.chain.battles(), 1);
+
+    // Saving the game state isn't answered, VCMI goes on with the turn
+    connector
+        .vcmi_command_sender
+        .send((
+            ReplyTo {
+                connection_id: 1,
+                request_id: 100,
+            },
+            VcmiCommand::SaveGameState {
+                day: 7,
+                current_player: "red".to_string(),
+                player_states: Vec::new(),
+            },
+        ))
+        .unwrap();
+    let states = wait_until(|| Some(connector.chain.game_states()).filter(|s| !s.is_empty()));
+    assert_eq!(states[0].day, 7);
+    assert_eq!(states[0].saver_id, FakeChain::account_of(None));
+
+    wait_until(|| (connector.events_named("log:spending") == 2).then_some(()));
+    let balance = connector.chain.balance_of(FakeChain::account_of(None));
+    assert!(balance < FAKE_INITIAL_BALANCE);
+    assert_eq!(
+        connector.wait_for_event("log:update_balance"),
+        serde_json::json!(balance)
+    );
+}
+
+#[test]
+fn spending_over_the_cap_can_be_declined() {
+    let mut connector = Connector::start_with_budget(Budget::new(Some(1), BudgetScope::Session));
+    connector.connect();
+
+    let reply_to = connector.send(VcmiCommand::SimulateBattle(battle_info()));
+    let question = connector.wait_for_event("log:confirm_spending");
+    assert_eq!(question[0]["action"], "SimulateBattle");
+    assert_eq!(question[2], serde_json::json!(1));
+    connector
+        .gui_sender
+        .send(GuiCommand::ConfirmSpending {
+            approved: false,
+            for_all: false,
+        })
+        .unwrap();
+
+    match connector.reply(reply_to) {
+        VcmiReply::Error { .. } => {}
+        reply => panic!("Unexpected reply to SimulateBattle: {reply:?}"),
+    }
+    assert_eq!(
+        connector.wait_for_event("log:spending_confirmed"),
+        serde_json::json!(false)
+    );
+    assert_eq!(connector.chain.battles(), 0);
+    assert_eq!(connector.events_named("log:spending"), 0);
+    assert_eq!(
+        connector.chain.balance_of(FakeChain::account_of(None)),
+        FAKE_INITIAL_BALANCE
+    );
+}
+
+#[test]
+fn commands_during_confirmation_run_after_it() {
+    let mut connector = Connector::start_with_budget(Budget::new(Some(1), BudgetScope::Session));
+    connector.connect();
+
+    let reply_to = connector.send(VcmiCommand::SimulateBattle(battle_info()));
+    connector.wait_for_event("log:confirm_spending");
+    connector.gui_sender.send(GuiCommand::ListNetworks).unwrap();
+    // Closing the window declines the spending
+    connector.gui_sender.send(GuiCommand::Cancel).unwrap();
+
+    match connector.reply(reply_to) {
+        VcmiReply::Error { .. } => {}
+        reply => panic!("Unexpected reply to SimulateBattle: {reply:?}"),
+    }
+    assert_eq!(
+        connector.wait_for_event("log:spending_confirmed"),
+        serde_json::json!(false)
+    );
+    connector.wait_for_event("networks");
+    wait_until(|| connector.need_stop.load(Relaxed).then_some(()));
+    assert_eq!(connector.chain.battles(), 0);
+}
+
+#[test]
+fn spending_allowed_for_all_is_not_asked_again() {
+    let mut connector = Connector::start_with_budget(Budget::new(Some(1), BudgetScope::Session));
+    connector.connect();
+
+    let reply_to = connector.send(VcmiCommand::SimulateBattle(battle_info()));
+    connector.wait_for_event("log:confirm_spending");
+    connector
+        .gui_sender
+        .send(GuiCommand::ConfirmSpending {
+            approved: true,
+            for_all: true,
+        })
+        .unwrap();
+    let reply = connector.reply(reply_to);
+    assert!(matches!(reply, VcmiReply::BattleInfo(_)), "{reply:?}");
+
+    let reply = connector.request(VcmiCommand::SimulateBattle(battle_info()));
+    assert!(matches!(reply, VcmiReply::BattleInfo(_)), "{reply:?}");
+    assert_eq!(connector.chain.battles(), 2);
+    assert_eq!(connector.events_named("log:confirm_spending"), 1);
+    wait_until(|| (connector.events_named("log:spending") == 2).then_some(()));
+}
+
+#[test]
+fn failed_commit_keeps_the_upload() {
+    let mut connector = Connector::start();
+    let data = b"zipped save made before connecting".to_vec();
+    let checksum = checksum(&data);
+    let begin = VcmiCommand::UploadBegin {
+        filename: "early.vsgm1".to_string(),
+        len: data.len() as u64,
+        checksum,
+    };
+    connector.request(begin.clone());
+    connector.request(VcmiCommand::UploadChunk {
+        checksum,
+        offset: 0,
+        data: data.clone(),
+    });
+    let commit = VcmiCommand::UploadCommit {
+        checksum,
+        metadata: metadata(),
+    };
+    match connector.request(commit.clone()) {
+        VcmiReply::Error { retryable, .. } => assert!(retryable),
+        reply => panic!("Unexpected reply to UploadCommit: {reply:?}"),
+    }
+
+    // The retry after connecting sends no chunks
+    connector.connect();
+    let reply = connector.request(begin);
+    assert!(matches!(reply, VcmiReply::UploadOffset { offset } if offset == data.len() as u64));
+    let reply = connector.request(commit);
+    assert!(matches!(reply, VcmiReply::Saved), "{reply:?}");
+    assert_eq!(connector.list_saves().len(), 1);
+}
+
+#[test]
+fn requests_before_connecting_fail() {
+    let mut connector = Connector::start();
+    match connector.request(VcmiCommand::ListSaves) {
+        VcmiReply::Error { code, .. } => assert_eq!(code, ErrorCode::NotConnected),
+        reply => panic!("Unexpected reply to ListSaves: {reply:?}"),
+    }
+}
+
+#[test]
+fn connecting_again_is_answered() {
+    let connector = Connector::start();
+    connector.connect();
+    connector.connect_as(String::new(), Secret::default());
+    wait_until(|| (connector.events_named("log:update_account_id") == 2).then_some(()));
+
+    // Saves are keyed by the account, so it can't be switched on the fly
+    connector.connect_with_account("//Bob");
+    let alert = connector.wait_for_event("alert");
+    assert!(
+        alert.as_str().unwrap().contains("already connected"),
+        "{alert}"
+    );
+    assert_eq!(connector.events_named("log:update_account_id"), 2);
+}
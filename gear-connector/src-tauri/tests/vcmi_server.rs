@@ -0,0 +1,82 @@
+//! Talks to `VcmiServer` as rust_vcmi does, with Logic played by the test.
+
+use crossbeam_channel::bounded;
+use futures::{SinkExt, StreamExt};
+use gear_connector::vcmi_server::{local_address, VcmiServer, CAPABILITIES};
+use gear_connector_api::{
+    handshake::{client_handshake, Hello},
+    utils::split_to_reply_read_command_write,
+    CommandEnvelope, VcmiCommand, VcmiReply,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::net::TcpStream;
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// One server runs per process, as its address is kept once, so the test covers
+/// both a silent peer and a busy Logic.
+#[tokio::test(flavor = "multi_thread")]
+async fn silent_peer_and_busy_logic_block_nobody() {
+    // The server publishes its address to the VCMI user data directory
+    let dir = std::env::temp_dir().join(format!("gear-connector-server-{}", std::process::id()));
+    std::env::set_var("XDG_DATA_HOME", &dir);
+    std::env::set_var("HOME", &dir);
+    std::env::set_var("USERPROFILE", &dir);
+
+    // Nobody takes commands from Logic's queue, as if Logic waited for the chain
+    let (command_sender, command_receiver) = bounded(1);
+    let (_reply_sender, reply_receiver) = bounded(1);
+    let need_stop = Arc::new(AtomicBool::new(false));
+    let address = "127.0.0.1:0".parse().unwrap();
+    VcmiServer::new(need_stop.clone(), address, command_sender, reply_receiver)
+        .await
+        .run()
+        .await
+        .unwrap();
+
+    // Connects and never says hello, the next peer is served meanwhile
+    let _silent = TcpStream::connect(local_address().unwrap()).await.unwrap();
+
+    let mut stream = TcpStream::connect(local_address().unwrap()).await.unwrap();
+    let hello = Hello::new("rust_vcmi test", CAPABILITIES);
+    let negotiated = tokio::time::timeout(TIMEOUT, client_handshake(&mut stream, hello))
+        .await
+        .expect("A silent peer holds up the handshake")
+        .unwrap();
+    let (mut replies, mut commands) =
+        split_to_reply_read_command_write(stream, negotiated.wire_format);
+
+    for (request_id, command) in [
+        (1, VcmiCommand::ListSaves),
+        (2, VcmiCommand::ListSaves),
+        (3, VcmiCommand::ListSaves),
+        (4, VcmiCommand::Ping),
+    ] {
+        let envelope = CommandEnvelope {
+            request_id,
+            command,
+        };
+        commands.send(envelope).await.unwrap();
+    }
+    let pong = tokio::time::timeout(TIMEOUT, replies.next())
+        .await
+        .expect("No Pong while Logic is busy")
+        .unwrap()
+        .unwrap();
+    assert_eq!(pong.request_id, 4);
+    assert!(matches!(pong.reply, VcmiReply::Pong));
+
+    // Logic gets the queued commands in order once it is free
+    for request_id in 1..=3 {
+        let (reply_to, command) = command_receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reply_to.request_id, request_id);
+        assert!(matches!(command, VcmiCommand::ListSaves));
+    }
+    need_stop.store(true, Relaxed);
+}
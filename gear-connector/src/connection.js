@@ -7,8 +7,32 @@ window.addEventListener("DOMContentLoaded", () => {
     document
         .querySelector("#expand-log")
         .addEventListener("click", () => invoke("expand_log"));
+    document
+        .querySelector("#allow-spending")
+        .addEventListener("click", () => confirmSpending(true, false));
+    document
+        .querySelector("#allow-all-spending")
+        .addEventListener("click", () => confirmSpending(true, true));
+    document
+        .querySelector("#decline-spending")
+        .addEventListener("click", () => confirmSpending(false, false));
 });
 
+async function confirmSpending(approved, forAll) {
+    document.getElementById("spending-confirm").hidden = true;
+    await invoke("confirm_spending", {
+        approved: approved,
+        forAll: forAll
+    });
+}
+
+function formatValue(value) {
+    return new Intl.NumberFormat("en-GB", {
+        notation: "compact",
+        compactDisplay: "short",
+    }).format(value);
+}
+
 await listen('log', (event) => {
     console.log("js: log: " + event)
     let incoming = event.payload;
@@ -49,6 +73,33 @@ await listen('update_balance', (event) => {
     balance.innerText = n
 })
 
+await listen('confirm_spending', (event) => {
+    console.log("js: confirm_spending: ", event.payload)
+    const [fee, spent, cap] = event.payload;
+    document.getElementById("spending-question").innerText =
+        `${fee.action} costs about ${formatValue(fee.gas_value)} for ${fee.gas} gas, plus the transaction fee. ` +
+        `Spent ${formatValue(spent)} of ${formatValue(cap)} allowed. Send it anyway?`;
+    document.getElementById("spending-confirm").hidden = false;
+})
+
+// The connector declines by itself when nobody answers in time
+await listen('spending_confirmed', (event) => {
+    document.getElementById("spending-confirm").hidden = true;
+})
+
+await listen('spending', (event) => {
+    console.log("js: spending: ", event.payload)
+    const entry = event.payload;
+    document.getElementById("spent-value").innerText =
+        entry.cap === null ? formatValue(entry.total) : `${formatValue(entry.total)} / ${formatValue(entry.cap)}`;
+    const time = entry.at === null ? "" : new Date(entry.at * 1000).toLocaleTimeString() + " ";
+    let ledger = document.getElementById("ledger");
+    const item = document.createElement("li");
+    item.className = "list-group-item list-group-item-secondary text-break";
+    item.textContent = `${time}${entry.action}: ${formatValue(entry.spent)} (gas estimated at ${formatValue(entry.estimated)}), total ${formatValue(entry.total)}`;
+    ledger.appendChild(item);
+})
+
 await listen('update_account_id', (event) => {
     console.log("js: log: " + event)
     let incoming = event.payload;
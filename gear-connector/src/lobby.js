@@ -1,6 +1,6 @@
 const { invoke } = window.__TAURI__.tauri;
 const { emit, listen } = window.__TAURI__.event;
-const { fetch, Body } = window.__TAURI__.http;
+const { fetch } = window.__TAURI__.http;
 
 let lobbyAddressInputEl;
 let usernameInputEl;
@@ -8,49 +8,103 @@ let nodeAddressInputEl;
 let programIdInputEl;
 let programId2InputEl;
 let programId3InputEl;
-let accountIdInputEl;
-let passwordInputEl;
+let accountEl;
 
 let roomNameEl;
 let roomPasswordEl;
 let userPasswordEl;
 let roomMaxPlayersEl;
 
+let networkProfileEl;
+let networkNameEl;
+
 let intervalId;
 let isRoomCreator;
+let networkProfiles = [];
+
+function showAlert(message) {
+  let alert = document.getElementById("alert");
+  alert.hidden = false
+
+  document.getElementById("connection-message").innerText = message
+  // The account dialog covers the alert
+  showAccountMessage(message)
+}
+
+function showAccountMessage(message) {
+  const accountMessage = document.getElementById("account-message");
+  accountMessage.hidden = false;
+  accountMessage.innerText = message;
+}
+
+// Resolves to the address the connector will use, shows why it can't be used otherwise
+async function validateNodeAddress() {
+  try {
+    const address = await invoke("validate_node_address", {
+      nodeAddress: nodeAddressInputEl.value,
+    });
+    nodeAddressInputEl.classList.remove("is-invalid");
+    return address;
+  } catch (error) {
+    nodeAddressInputEl.classList.add("is-invalid");
+    showAlert(error);
+    return null;
+  }
+}
+
 async function connect() {
-  console.log("request mnemonic phrase");
-  const url = 'https://vcmi.gear-tech.io/user/get_keys';
-  const body = Body.json({
-    nickname: usernameInputEl.value,
-    password: userPasswordEl.value
+  const nodeAddress = await validateNodeAddress();
+  if (nodeAddress === null) {
+    return;
   }
-  );
-  fetch(url, {
-    method: "POST",
-    headers: {
-      'Content-Type': 'application/json'
-    },
-    body: body
-  })
-    .then(async response => {
-
-      console.log(response.data)
-      await invoke("connect", {
-        lobbyAddress: lobbyAddressInputEl.value,
-        username: usernameInputEl.value,
-        programId: programIdInputEl.value,
-        metaProgramId: programId2InputEl.value,
-        battleProgramId: programId3InputEl.value,
-        nodeAddress: nodeAddressInputEl.innerText,
-        accountId: response.data.privateKey,
-        password: ""
-      })
+  await invoke("connect", {
+    lobbyAddress: lobbyAddressInputEl.value,
+    username: usernameInputEl.value,
+    programId: programIdInputEl.value,
+    metaProgramId: programId2InputEl.value,
+    battleProgramId: programId3InputEl.value,
+    nodeAddress: nodeAddress,
+    account: accountEl.value,
+    password: userPasswordEl.value
+  });
+  userPasswordEl.value = "";
+}
 
-    })
-    .catch(error => {
-      console.log('Error:', error);
+async function listAccounts() {
+  await invoke("list_accounts");
+}
+
+// Creates an account when no secret phrase is given, imports it otherwise
+async function addAccount() {
+  const nameEl = document.getElementById("new-account-name");
+  const passwordEl = document.getElementById("new-account-password");
+  const suriEl = document.getElementById("new-account-suri");
+  console.log("add account", nameEl.value);
+  if (suriEl.value === "") {
+    await invoke("create_account", {
+      name: nameEl.value,
+      password: passwordEl.value,
+    });
+  } else {
+    await invoke("import_account", {
+      name: nameEl.value,
+      suri: suriEl.value,
+      password: passwordEl.value,
     });
+  }
+  suriEl.value = "";
+  passwordEl.value = "";
+}
+
+async function deleteAccount() {
+  if (accountEl.value === "") {
+    return;
+  }
+  console.log("delete account", accountEl.value);
+  await invoke("delete_account", {
+    name: accountEl.value,
+    password: userPasswordEl.value,
+  });
 }
 
 function checkIpfs() {
@@ -132,6 +186,66 @@ async function leave(roomName) {
   });
 }
 
+async function listSaves() {
+  console.log("list saves");
+  await invoke("list_saves");
+}
+
+async function deleteSave(save) {
+  const unpin = document.getElementById("unpin-saves").checked;
+  console.log("delete save", save.cid, "unpin:", unpin);
+  await invoke("delete_save", {
+    cid: save.cid,
+    unpin: unpin,
+  });
+}
+
+async function listNetworks() {
+  await invoke("list_networks");
+}
+
+function selectNetwork(profile) {
+  networkProfileEl.textContent = profile.name;
+  nodeAddressInputEl.value = profile.node_address;
+  nodeAddressInputEl.classList.remove("is-invalid");
+  // Programs of a dev node differ from run to run, keep what is typed in
+  if (profile.program_id) {
+    programIdInputEl.value = profile.program_id;
+    programId2InputEl.value = profile.meta_program_id;
+    programId3InputEl.value = profile.battle_program_id;
+  }
+  networkNameEl.value = profile.builtin ? "" : profile.name;
+}
+
+async function saveNetwork() {
+  if (await validateNodeAddress() === null) {
+    return;
+  }
+  console.log("save network", networkNameEl.value);
+  await invoke("save_network", {
+    profile: {
+      name: networkNameEl.value,
+      node_address: nodeAddressInputEl.value,
+      program_id: programIdInputEl.value,
+      meta_program_id: programId2InputEl.value,
+      battle_program_id: programId3InputEl.value,
+      builtin: false,
+    }
+  });
+}
+
+async function deleteNetwork() {
+  const profile = networkProfiles.find(p => p.name === networkNameEl.value && !p.builtin);
+  if (profile === undefined) {
+    showAlert("Choose a saved network profile to delete");
+    return;
+  }
+  console.log("delete network", profile.name);
+  await invoke("delete_network", {
+    name: profile.name,
+  });
+}
+
 async function hostmode(mode) {
   console.log("hostmode", mode);
   await invoke("hostmode", {
@@ -148,8 +262,7 @@ window.addEventListener("DOMContentLoaded", () => {
   programIdInputEl = document.querySelector("#program-id");
   programId2InputEl = document.querySelector("#program-id-meta");
   programId3InputEl = document.querySelector("#program-id-battle");
-  accountIdInputEl = document.querySelector("#account-id")
-  passwordInputEl = document.querySelector("#password")
+  accountEl = document.querySelector("#account")
   document.querySelector("#password-ok").addEventListener("click", () => connect());
 
   roomNameEl = document.querySelector("#room-name")
@@ -157,16 +270,67 @@ window.addEventListener("DOMContentLoaded", () => {
   userPasswordEl = document.querySelector("#user-password")
   roomMaxPlayersEl = document.querySelector("#room-max-players")
   document.querySelector("#new-room-button").addEventListener("click", () => newRoom());
+  document.querySelector("#refresh-saves-button").addEventListener("click", () => listSaves());
+
+  networkProfileEl = document.querySelector("#network-profile")
+  networkNameEl = document.querySelector("#network-name")
+  nodeAddressInputEl.addEventListener("change", () => validateNodeAddress());
+  document.querySelector("#save-network-button").addEventListener("click", () => saveNetwork());
+  document.querySelector("#delete-network-button").addEventListener("click", () => deleteNetwork());
+  listNetworks();
+
+  document.querySelector("#add-account-button").addEventListener("click", () => addAccount());
+  document.querySelector("#delete-account-button").addEventListener("click", () => deleteAccount());
+  listAccounts();
 });
 
 await listen('alert', (event) => {
   console.log("js: connection_view: " + event)
-  let payload = event.payload;
+  showAlert(event.payload)
+})
 
-  let alert = document.getElementById("alert");
-  alert.hidden = false
+await listen('accounts', (event) => {
+  const accounts = event.payload;
+  console.log("accounts:", accounts);
+  const selected = accountEl.value;
+  while (accountEl.options.length > 1) {
+    accountEl.remove(1);
+  }
+  for (let i = 0; i < accounts.length; ++i) {
+    const option = document.createElement("option");
+    option.value = accounts[i];
+    option.textContent = accounts[i];
+    accountEl.appendChild(option);
+  }
+  if (accounts.includes(selected)) {
+    accountEl.value = selected;
+  } else if (accounts.length > 0) {
+    accountEl.value = accounts[accounts.length - 1];
+  }
+})
+
+// The phrase of a created account, shown once to be written down
+await listen('mnemonic', (event) => {
+  showAccountMessage("Write down the secret phrase of the new account, it is not shown again:\n" + event.payload);
+})
 
-  document.getElementById("connection-message").innerText = payload
+await listen('networks', (event) => {
+  networkProfiles = event.payload;
+  console.log("networks:", networkProfiles);
+  const menu = document.getElementById("network-profiles");
+  while (menu.firstChild) {
+    menu.removeChild(menu.firstChild);
+  }
+  for (let i = 0; i < networkProfiles.length; ++i) {
+    const profile = networkProfiles[i];
+    const item = document.createElement("a");
+    item.className = "dropdown-item";
+    item.href = "#";
+    item.textContent = profile.name;
+    item.title = profile.node_address;
+    item.addEventListener("click", () => selectNetwork(profile));
+    menu.appendChild(item);
+  }
 })
 
 await listen('showRooms', (event) => {
@@ -183,6 +347,7 @@ await listen('showRooms', (event) => {
   const modalBackdrop = document.getElementsByClassName("modal-backdrop")[0];
   modalBackdrop.parentNode.removeChild(modalBackdrop);
   document.body.classList.remove("modal-open");
+  listSaves();
 })
 
 await listen('addUsers', (event) => {
@@ -234,6 +399,52 @@ await listen('addSessions', (event) => {
   }
 })
 
+await listen('saves', (event) => {
+  let saves = event.payload;
+  console.log("saves:", saves);
+  const list = document.getElementById("saves");
+  while (list.firstChild) {
+    list.removeChild(list.firstChild);
+  }
+  saves.sort((a, b) => (b.saved_at ?? 0) - (a.saved_at ?? 0));
+  for (let i = 0; i < saves.length; ++i) {
+    const save = saves[i];
+    const listItem = document.createElement("li");
+    listItem.className = "list-group-item d-flex justify-content-between align-items-center";
+    listItem.classList.add("bg-secondary");
+    listItem.style = "--bs-bg-opacity: .2;"
+
+    const text = document.createElement("text");
+    let description = save.name;
+    if (save.metadata) {
+      description += " (" + save.metadata.map_name + ", day " + save.metadata.day + ")";
+    }
+    if (save.size !== null) {
+      description += " " + Math.ceil(save.size / 1024) + " KB";
+    }
+    text.textContent = description;
+    text.title = save.cid;
+    listItem.appendChild(text);
+
+    const button = document.createElement("button");
+    button.type = "button";
+    button.className = "btn btn-sm btn-danger";
+    button.textContent = "Delete";
+    // The first click only arms the button, a save removed from chain is gone for good
+    button.addEventListener("click", () => {
+      if (button.textContent === "Delete") {
+        button.textContent = "Sure?";
+      } else {
+        button.setAttribute("disabled", "");
+        deleteSave(save);
+      }
+    });
+    listItem.appendChild(button);
+
+    list.appendChild(listItem);
+  }
+})
+
 await listen('chatMessage', (event) => {
   let messages = event.payload;
   console.log("chat Message:", messages);
@@ -371,7 +582,6 @@ function setupDropdownMenu(buttonId, menuId) {
     }
   });
 }
-setupDropdownMenu("node-address", "node-addresses");
 setupDropdownMenu("room-max-players", "players-count");
 
 
@@ -0,0 +1,300 @@
+#![no_std]
+
+use gmeta::{In, InOut, Metadata};
+use gstd::{prelude::*, ActorId};
+
+pub struct ContractMetadata;
+
+impl Metadata for ContractMetadata {
+    type Init = In<Config>;
+    type Handle = InOut<Action, Result<Event, ArchiveError>>;
+    type Others = ();
+    type Reply = ();
+    type Signal = ();
+    type State = ArchiveState;
+}
+
+/// Limits of the archive, set when the program is initialized.
+#[derive(Encode, Decode, TypeInfo, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Debug)]
+pub struct Config {
+    /// How many archives every account can keep.
+    pub max_saves_per_owner: u32,
+    /// Maximum length of a filename and of an IPFS hash, in bytes.
+    pub max_name_len: u32,
+    /// Maximum length of the metadata of an archive, in bytes.
+    pub max_metadata_len: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_saves_per_owner: 64,
+            max_name_len: 128,
+            max_metadata_len: 4096,
+        }
+    }
+}
+
+#[derive(Encode, Decode, TypeInfo, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+pub struct ArchiveDescription {
+    pub filename: String,
+    /// IPFS CID of the zipped save.
+    pub hash: String,
+    /// SHA-256 of the archive as it was uploaded. The CID of a file IPFS splits into
+    /// blocks doesn't tell the hash of the file, so downloads are checked against this.
+    pub checksum: [u8; 32],
+    /// SCALE-encoded `SaveMetadata` of gear-connector-api, which describes the save
+    /// without downloading it. Empty if the saver didn't know it.
+    pub metadata: Vec<u8>,
+}
+
+#[derive(Encode, Decode, TypeInfo, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+pub struct GameArchive {
+    pub saver_id: ActorId,
+    pub archive: ArchiveDescription,
+}
+
+/// Actions apply to the archives of the sender only.
+/// An account has at most one archive with a given hash.
+/// Sending an action again after it succeeded changes nothing and succeeds too,
+/// so a message can be resent when its reply is lost.
+#[derive(Encode, Decode, TypeInfo, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+pub enum Action {
+    /// Adds the archive, or updates its name and metadata if its hash is already saved,
+    /// so sending the same save twice doesn't add it twice.
+    SaveArchive(ArchiveDescription),
+    Load {
+        hash: String,
+    },
+    /// Succeeds if the sender has no archive with `hash` as well.
+    Delete {
+        hash: String,
+    },
+    Rename {
+        hash: String,
+        filename: String,
+    },
+    /// Puts `archive` in place of the archive with `hash`.
+    Replace {
+        hash: String,
+        archive: ArchiveDescription,
+    },
+}
+
+#[derive(Encode, Decode, TypeInfo, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+pub enum Event {
+    Loaded(Option<GameArchive>),
+    SavedArchive,
+    Deleted,
+    Renamed,
+    Replaced,
+}
+
+#[derive(Encode, Decode, TypeInfo, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+pub enum ArchiveError {
+    /// The sender already keeps `max_saves_per_owner` archives.
+    QuotaExceeded { max_saves_per_owner: u32 },
+    /// A filename or hash is empty or longer than `max_name_len`.
+    InvalidName { max_name_len: u32 },
+    /// The metadata of an archive is longer than `max_metadata_len`.
+    MetadataTooLong { max_metadata_len: u32 },
+    /// The sender has no archive with `hash`.
+    NotFound { hash: String },
+}
+
+#[derive(Encode, Decode, TypeInfo, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Debug, Default)]
+pub struct ArchiveState {
+    pub config: Config,
+    /// Archives of every account, sorted by account, each oldest first.
+    pub saves: Vec<(ActorId, Vec<ArchiveDescription>)>,
+}
+
+impl ArchiveState {
+    /// Up to `limit` archives of `owner`, skipping the first `offset` of them.
+    pub fn saves_by_owner(
+        &self,
+        owner: ActorId,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<ArchiveDescription> {
+        self.saves_of(owner)
+            .map(|saves| {
+                saves
+                    .iter()
+                    .skip(offset as usize)
+                    .take(limit as usize)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn saves_count(&self, owner: ActorId) -> u32 {
+        self.saves_of(owner).map_or(0, |saves| saves.len() as u32)
+    }
+
+    fn saves_of(&self, owner: ActorId) -> Option<&Vec<ArchiveDescription>> {
+        self.saves
+            .binary_search_by_key(&owner, |(saver_id, _)| *saver_id)
+            .ok()
+            .map(|index| &self.saves[index].1)
+    }
+}
+
+/// Archives of every account and the rules of the actions on them, run by the
+/// program and by the in-memory chain of the connector tests alike.
+#[derive(Default, Clone, Debug)]
+pub struct Archive {
+    pub config: Config,
+    pub saves: BTreeMap<ActorId, Vec<ArchiveDescription>>,
+}
+
+impl Archive {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            saves: BTreeMap::new(),
+        }
+    }
+
+    pub fn handle(&mut self, saver_id: ActorId, action: Action) -> Result<Event, ArchiveError> {
+        match action {
+            Action::SaveArchive(archive) => self.save(saver_id, archive),
+            Action::Load { hash } => Ok(Event::Loaded(self.load(saver_id, &hash))),
+            Action::Delete { hash } => self.delete(saver_id, &hash),
+            Action::Rename { hash, filename } => self.rename(saver_id, &hash, filename),
+            Action::Replace { hash, archive } => self.replace(saver_id, &hash, archive),
+        }
+    }
+
+    fn save(
+        &mut self,
+        saver_id: ActorId,
+        archive: ArchiveDescription,
+    ) -> Result<Event, ArchiveError> {
+        self.check_archive(&archive)?;
+        let max_saves_per_owner = self.config.max_saves_per_owner;
+        let saves = self.saves.entry(saver_id).or_default();
+        match saves.iter_mut().find(|save| save.hash == archive.hash) {
+            Some(save) => *save = archive,
+            None if saves.len() >= max_saves_per_owner as usize => {
+                return Err(ArchiveError::QuotaExceeded {
+                    max_saves_per_owner,
+                })
+            }
+            None => saves.push(archive),
+        }
+        Ok(Event::SavedArchive)
+    }
+
+    fn load(&self, saver_id: ActorId, hash: &str) -> Option<GameArchive> {
+        self.saves
+            .get(&saver_id)?
+            .iter()
+            .find(|save| save.hash == hash)
+            .map(|archive| GameArchive {
+                saver_id,
+                archive: archive.clone(),
+            })
+    }
+
+    fn delete(&mut self, saver_id: ActorId, hash: &str) -> Result<Event, ArchiveError> {
+        // Deleting a missing archive succeeds, so a resent Delete does no harm.
+        if let Some(saves) = self.saves.get_mut(&saver_id) {
+            saves.retain(|save| save.hash != hash);
+            if saves.is_empty() {
+                self.saves.remove(&saver_id);
+            }
+        }
+        Ok(Event::Deleted)
+    }
+
+    fn rename(
+        &mut self,
+        saver_id: ActorId,
+        hash: &str,
+        filename: String,
+    ) -> Result<Event, ArchiveError> {
+        self.check_name(&filename)?;
+        let saves = self.saves_of(saver_id, hash)?;
+        let index = position(saves, hash);
+        saves[index].filename = filename;
+        Ok(Event::Renamed)
+    }
+
+    fn replace(
+        &mut self,
+        saver_id: ActorId,
+        hash: &str,
+        archive: ArchiveDescription,
+    ) -> Result<Event, ArchiveError> {
+        self.check_archive(&archive)?;
+        let saves = match self.saves_of(saver_id, hash) {
+            Ok(saves) => saves,
+            // A resent Replace finds the new archive in place of the old one.
+            Err(error) => {
+                return self
+                    .rename(saver_id, &archive.hash, archive.filename)
+                    .map(|_| Event::Replaced)
+                    .map_err(|_| error)
+            }
+        };
+        // The new archive may be saved under another name already, keep one of them.
+        if archive.hash != hash {
+            saves.retain(|save| save.hash != archive.hash);
+        }
+        let index = position(saves, hash);
+        saves[index] = archive;
+        Ok(Event::Replaced)
+    }
+
+    /// Archives of `saver_id`, if there is one with `hash` among them.
+    fn saves_of(
+        &mut self,
+        saver_id: ActorId,
+        hash: &str,
+    ) -> Result<&mut Vec<ArchiveDescription>, ArchiveError> {
+        self.saves
+            .get_mut(&saver_id)
+            .filter(|saves| saves.iter().any(|save| save.hash == hash))
+            .ok_or_else(|| ArchiveError::NotFound {
+                hash: hash.to_string(),
+            })
+    }
+
+    fn check_archive(&self, archive: &ArchiveDescription) -> Result<(), ArchiveError> {
+        self.check_name(&archive.filename)?;
+        self.check_name(&archive.hash)?;
+        let max_metadata_len = self.config.max_metadata_len;
+        if archive.metadata.len() > max_metadata_len as usize {
+            return Err(ArchiveError::MetadataTooLong { max_metadata_len });
+        }
+        Ok(())
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), ArchiveError> {
+        let max_name_len = self.config.max_name_len;
+        if name.is_empty() || name.len() > max_name_len as usize {
+            return Err(ArchiveError::InvalidName { max_name_len });
+        }
+        Ok(())
+    }
+
+    pub fn state(&self) -> ArchiveState {
+        ArchiveState {
+            config: self.config,
+            saves: self
+                .saves
+                .iter()
+                .map(|(saver_id, saves)| (*saver_id, saves.clone()))
+                .collect(),
+        }
+    }
+}
+
+fn position(saves: &[ArchiveDescription], hash: &str) -> usize {
+    saves
+        .iter()
+        .position(|save| save.hash == hash)
+        .expect("Checked by saves_of")
+}
@@ -0,0 +1,35 @@
+#![no_std]
+
+use gstd::msg;
+use homm3_archive_io::*;
+
+#[cfg(feature = "binary-vendor")]
+include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
+
+static mut ARCHIVE: Option<Archive> = None;
+
+#[no_mangle]
+extern "C" fn init() {
+    let config: Config = msg::load().expect("Unable to decode Config");
+    unsafe { ARCHIVE = Some(Archive::new(config)) };
+}
+
+#[no_mangle]
+extern "C" fn handle() {
+    let action: Action = msg::load().expect("Unable to decode Action");
+    let archive = unsafe { ARCHIVE.as_mut().expect("The program is not initialized") };
+    let reply = archive.handle(msg::source(), action);
+    msg::reply(reply, 0).expect("Unable to reply");
+}
+
+#[no_mangle]
+extern "C" fn state() {
+    let archive = unsafe { ARCHIVE.as_ref().expect("The program is not initialized") };
+    msg::reply(archive.state(), 0).expect("Unable to share the state");
+}
+
+#[no_mangle]
+extern "C" fn metahash() {
+    let metahash: [u8; 32] = include!("../.metahash");
+    msg::reply(metahash, 0).expect("Unable to share the metahash");
+}
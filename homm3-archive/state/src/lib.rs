@@ -0,0 +1,33 @@
+#![no_std]
+
+use gmeta::{metawasm, Metadata};
+use gstd::{prelude::*, ActorId};
+use homm3_archive_io::*;
+
+#[cfg(feature = "binary-vendor")]
+include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
+
+/// State functions of the archive program, they run on the node
+/// so a client receives only the archives it asks for.
+#[metawasm]
+pub mod metafns {
+    pub type State = <ContractMetadata as Metadata>::State;
+
+    /// Up to `limit` archives of `owner`, skipping the first `offset` of them.
+    pub fn saves_by_owner(
+        state: State,
+        owner: ActorId,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<ArchiveDescription> {
+        state.saves_by_owner(owner, offset, limit)
+    }
+
+    pub fn saves_count(state: State, owner: ActorId) -> u32 {
+        state.saves_count(owner)
+    }
+
+    pub fn config(state: State) -> Config {
+        state.config
+    }
+}
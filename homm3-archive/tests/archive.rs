@@ -0,0 +1,283 @@
+use gstd::{prelude::*, ActorId};
+use gtest::{Program, System};
+use homm3_archive_io::*;
+
+const PLAYER: u64 = 100;
+const OTHER_PLAYER: u64 = 101;
+
+fn init(system: &System, config: Config) -> Program {
+    system.init_logger();
+    let program = Program::current(system);
+    let result = program.send(PLAYER, config);
+    assert!(!result.main_failed());
+    program
+}
+
+fn archive(filename: &str, hash: &str) -> ArchiveDescription {
+    ArchiveDescription {
+        filename: filename.to_string(),
+        hash: hash.to_string(),
+        checksum: [0; 32],
+        metadata: Vec::new(),
+    }
+}
+
+fn send(program: &Program, from: u64, action: Action, reply: Result<Event, ArchiveError>) {
+    let result = program.send(from, action);
+    assert!(!result.main_failed());
+    assert!(result.contains(&(from, reply.encode())));
+}
+
+fn save(program: &Program, from: u64, filename: &str, hash: &str) {
+    let action = Action::SaveArchive(archive(filename, hash));
+    send(program, from, action, Ok(Event::SavedArchive));
+}
+
+fn saves(program: &Program, owner: u64) -> Vec<ArchiveDescription> {
+    let state: ArchiveState = program.read_state().expect("Can't read the state");
+    state.saves_by_owner(ActorId::from(owner), 0, u32::MAX)
+}
+
+#[test]
+fn save_and_load() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+
+    save(&program, PLAYER, "Game", "QmGame");
+
+    let load = Action::Load {
+        hash: "QmGame".into(),
+    };
+    let loaded = GameArchive {
+        saver_id: ActorId::from(PLAYER),
+        archive: archive("Game", "QmGame"),
+    };
+    send(
+        &program,
+        PLAYER,
+        load.clone(),
+        Ok(Event::Loaded(Some(loaded))),
+    );
+    assert_eq!(saves(&program, PLAYER), vec![archive("Game", "QmGame")]);
+
+    // Saves of another account aren't visible to the player.
+    send(&program, OTHER_PLAYER, load, Ok(Event::Loaded(None)));
+    assert!(saves(&program, OTHER_PLAYER).is_empty());
+}
+
+#[test]
+fn resent_save_is_not_duplicated() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+
+    save(&program, PLAYER, "Game", "QmGame");
+    save(&program, PLAYER, "Game", "QmGame");
+    save(&program, PLAYER, "Quick", "QmGame");
+
+    assert_eq!(saves(&program, PLAYER), vec![archive("Quick", "QmGame")]);
+}
+
+#[test]
+fn delete_rename_replace() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+    save(&program, PLAYER, "First", "QmFirst");
+    save(&program, PLAYER, "Second", "QmSecond");
+    save(&program, OTHER_PLAYER, "First", "QmFirst");
+
+    let rename = Action::Rename {
+        hash: "QmFirst".into(),
+        filename: "Renamed".into(),
+    };
+    send(&program, PLAYER, rename, Ok(Event::Renamed));
+
+    let replace = Action::Replace {
+        hash: "QmSecond".into(),
+        archive: archive("Second", "QmThird"),
+    };
+    send(&program, PLAYER, replace, Ok(Event::Replaced));
+    assert_eq!(
+        saves(&program, PLAYER),
+        vec![archive("Renamed", "QmFirst"), archive("Second", "QmThird")]
+    );
+
+    let delete = Action::Delete {
+        hash: "QmFirst".into(),
+    };
+    send(&program, PLAYER, delete, Ok(Event::Deleted));
+    assert_eq!(saves(&program, PLAYER), vec![archive("Second", "QmThird")]);
+    // The same archive of another account stays.
+    assert_eq!(
+        saves(&program, OTHER_PLAYER),
+        vec![archive("First", "QmFirst")]
+    );
+}
+
+#[test]
+fn replace_with_saved_archive_keeps_one() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+    save(&program, PLAYER, "First", "QmFirst");
+    save(&program, PLAYER, "Second", "QmSecond");
+
+    let replace = Action::Replace {
+        hash: "QmFirst".into(),
+        archive: archive("First", "QmSecond"),
+    };
+    send(&program, PLAYER, replace, Ok(Event::Replaced));
+    assert_eq!(saves(&program, PLAYER), vec![archive("First", "QmSecond")]);
+}
+
+#[test]
+fn unknown_hash_is_not_found() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+    save(&program, PLAYER, "Game", "QmGame");
+
+    let not_found = Err(ArchiveError::NotFound {
+        hash: "QmGame".into(),
+    });
+    let rename = Action::Rename {
+        hash: "QmGame".into(),
+        filename: "Renamed".into(),
+    };
+    send(&program, OTHER_PLAYER, rename, not_found.clone());
+    let replace = Action::Replace {
+        hash: "QmGame".into(),
+        archive: archive("Game", "QmNew"),
+    };
+    send(&program, OTHER_PLAYER, replace, not_found);
+    // Nothing to delete isn't an error, but the archive of the player stays.
+    let delete = Action::Delete {
+        hash: "QmGame".into(),
+    };
+    send(&program, OTHER_PLAYER, delete, Ok(Event::Deleted));
+    assert_eq!(saves(&program, PLAYER), vec![archive("Game", "QmGame")]);
+}
+
+#[test]
+fn resent_actions_succeed() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+    save(&program, PLAYER, "Game", "QmOld");
+
+    let replace = Action::Replace {
+        hash: "QmOld".into(),
+        archive: archive("Game", "QmNew"),
+    };
+    send(&program, PLAYER, replace.clone(), Ok(Event::Replaced));
+    send(&program, PLAYER, replace, Ok(Event::Replaced));
+    assert_eq!(saves(&program, PLAYER), vec![archive("Game", "QmNew")]);
+
+    let delete = Action::Delete {
+        hash: "QmNew".into(),
+    };
+    send(&program, PLAYER, delete.clone(), Ok(Event::Deleted));
+    send(&program, PLAYER, delete, Ok(Event::Deleted));
+    assert!(saves(&program, PLAYER).is_empty());
+}
+
+#[test]
+fn quotas() {
+    let system = System::new();
+    let config = Config {
+        max_saves_per_owner: 2,
+        max_name_len: 8,
+        max_metadata_len: 4,
+    };
+    let program = init(&system, config);
+
+    save(&program, PLAYER, "First", "Qm1");
+    save(&program, PLAYER, "Second", "Qm2");
+    let quota_exceeded = Err(ArchiveError::QuotaExceeded {
+        max_saves_per_owner: 2,
+    });
+    let third = Action::SaveArchive(archive("Third", "Qm3"));
+    send(&program, PLAYER, third, quota_exceeded);
+    // The quota is per account.
+    save(&program, OTHER_PLAYER, "Third", "Qm3");
+
+    let invalid_name = Err(ArchiveError::InvalidName { max_name_len: 8 });
+    let too_long = Action::SaveArchive(archive("TooLongName", "Qm4"));
+    send(&program, OTHER_PLAYER, too_long, invalid_name.clone());
+    let empty = Action::SaveArchive(archive("", "Qm4"));
+    send(&program, OTHER_PLAYER, empty, invalid_name);
+    let mut too_much_metadata = archive("Fourth", "Qm4");
+    too_much_metadata.metadata = vec![0; 5];
+    let metadata_too_long = Err(ArchiveError::MetadataTooLong {
+        max_metadata_len: 4,
+    });
+    let action = Action::SaveArchive(too_much_metadata);
+    send(&program, OTHER_PLAYER, action, metadata_too_long);
+    assert_eq!(saves(&program, OTHER_PLAYER), vec![archive("Third", "Qm3")]);
+}
+
+#[test]
+fn metadata_is_kept() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+    let mut game = archive("Game", "QmGame");
+    game.metadata = b"encoded metadata".to_vec();
+    let action = Action::SaveArchive(game.clone());
+    send(&program, PLAYER, action, Ok(Event::SavedArchive));
+
+    let rename = Action::Rename {
+        hash: "QmGame".into(),
+        filename: "Renamed".into(),
+    };
+    send(&program, PLAYER, rename, Ok(Event::Renamed));
+    game.filename = "Renamed".into();
+    assert_eq!(saves(&program, PLAYER), vec![game.clone()]);
+
+    // Saving the same archive again updates its metadata
+    game.metadata = b"newer metadata".to_vec();
+    let action = Action::SaveArchive(game.clone());
+    send(&program, PLAYER, action, Ok(Event::SavedArchive));
+    assert_eq!(saves(&program, PLAYER), vec![game]);
+}
+
+#[test]
+fn saves_by_owner_pages() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+    for i in 0..5 {
+        save(&program, PLAYER, &format!("Game{i}"), &format!("Qm{i}"));
+    }
+
+    let state: ArchiveState = program.read_state().expect("Can't read the state");
+    let player = ActorId::from(PLAYER);
+    let page = state.saves_by_owner(player, 1, 2);
+    assert_eq!(page, vec![archive("Game1", "Qm1"), archive("Game2", "Qm2")]);
+    assert_eq!(
+        state.saves_by_owner(player, 4, 2),
+        vec![archive("Game4", "Qm4")]
+    );
+    assert!(state.saves_by_owner(player, 5, 2).is_empty());
+}
+
+#[test]
+fn state_functions() {
+    let system = System::new();
+    let program = init(&system, Config::default());
+    for i in 0..3 {
+        save(&program, PLAYER, &format!("Game{i}"), &format!("Qm{i}"));
+    }
+    save(&program, OTHER_PLAYER, "Other", "QmOther");
+
+    let wasm = homm3_archive_state::WASM_BINARY.to_vec();
+    let player = ActorId::from(PLAYER);
+    let page: Vec<ArchiveDescription> = program
+        .read_state_using_wasm("saves_by_owner", wasm.clone(), Some((player, 1u32, 5u32)))
+        .expect("Can't read saves_by_owner");
+    assert_eq!(page, vec![archive("Game1", "Qm1"), archive("Game2", "Qm2")]);
+
+    let count: u32 = program
+        .read_state_using_wasm("saves_count", wasm.clone(), Some(player))
+        .expect("Can't read saves_count");
+    assert_eq!(count, 3);
+
+    let config: Config = program
+        .read_state_using_wasm("config", wasm, None::<()>)
+        .expect("Can't read config");
+    assert_eq!(config, Config::default());
+}
@@ -2494,11 +2494,12 @@ struct DLL_LINKAGE CastAdvSpell : public CPackForServer
 struct DLL_LINKAGE SaveGame : public CPackForServer
 {
 	SaveGame() = default;
-	SaveGame(std::string Fname)
-		: fname(std::move(Fname))
+	SaveGame(std::string Fname, std::string ReplaceOnChain = "")
+		: fname(std::move(Fname)), replaceOnChain(std::move(ReplaceOnChain))
 	{
 	}
 	std::string fname;
+	std::string replaceOnChain; //IPFS CID of the save on chain this one replaces, empty to add it
 
 	void applyGs(CGameState * gs) {};
 
@@ -2508,6 +2509,7 @@ struct DLL_LINKAGE SaveGame : public CPackForServer
 	{
 		h & static_cast<CPackForServer &>(*this);
 		h & fname;
+		h & replaceOnChain;
 	}
 };
 
@@ -0,0 +1,395 @@
+use crate::error::BridgeError;
+use crate::ffi::{RConflictPolicy, RErrorKind, RLoadStatus};
+use gear_connector_api::manifest::{Manifest, ManifestEntry, MANIFEST_NAME};
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path};
+use std::time::UNIX_EPOCH;
+use zip::read::ZipFile;
+use zip::write::{FileOptions, ZipWriter};
+use zip::ZipArchive;
+
+/// Largest manifest read from an archive.
+const MAX_MANIFEST_LEN: u64 = 1024 * 1024;
+/// Extensions of the files every save consists of: the client and the server part.
+const SAVE_EXTENSIONS: [&str; 2] = ["vcgm1", "vsgm1"];
+
+/// Zips the files of a save together with their manifest, in memory.
+/// Fails if any of the files is missing rather than shipping a partial save.
+pub fn pack_save(paths: &[&str], vcmi_version: &str) -> Result<(Vec<u8>, Manifest), BridgeError> {
+    for extension in SAVE_EXTENSIONS {
+        let listed = paths.iter().any(|path| {
+            Path::new(path)
+                .extension()
+                .map_or(false, |e| e == extension)
+        });
+        if !listed {
+            return Err(BridgeError::new(
+                RErrorKind::Io,
+                format!("the save has no .{} file", extension),
+            ));
+        }
+    }
+    let mut files = Vec::with_capacity(paths.len());
+    let mut saved_at = 0;
+    for path in paths {
+        let name = Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| BridgeError::new(RErrorKind::Io, format!("{path} isn't a save file")))?;
+        let data = std::fs::read(path).map_err(|e| {
+            BridgeError::new(RErrorKind::Io, format!("save file {path} is missing: {e}"))
+        })?;
+        let modified = std::fs::metadata(path)?.modified()?;
+        let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        saved_at = saved_at.max(modified.as_secs());
+        files.push((name.to_string(), data));
+    }
+    let manifest = Manifest {
+        vcmi_version: vcmi_version.to_string(),
+        saved_at,
+        files: files
+            .iter()
+            .map(|(name, data)| ManifestEntry::new(name.as_str(), data))
+            .collect(),
+    };
+
+    // Fixed timestamps keep the archive of unchanged saves byte-identical,
+    // so an interrupted upload of it is resumed by gear-connector.
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default());
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(MANIFEST_NAME, options)?;
+    zip.write_all(&manifest.to_json())?;
+    for (name, data) in &files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(data)?;
+    }
+    Ok((zip.finish()?.into_inner(), manifest))
+}
+
+/// Extracts a save archive made by `pack_save` into `target_dir`.
+/// Returns how the save is extracted and the stem of its files in `target_dir`.
+/// Archives without a manifest are rejected, their files can't be verified.
+pub fn unpack_save(
+    archive: &[u8],
+    target_dir: &Path,
+    policy: RConflictPolicy,
+) -> Result<(RLoadStatus, String), BridgeError> {
+    let mut zip = ZipArchive::new(Cursor::new(archive))?;
+    let manifest = match zip.by_name(MANIFEST_NAME) {
+        Ok(mut file) => Manifest::from_json(&read_entry(&mut file, MAX_MANIFEST_LEN)?)?,
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Err(corrupt(format!("the archive has no {}", MANIFEST_NAME)))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut files = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let name = plain_name(&file)?;
+        if name == MANIFEST_NAME {
+            continue;
+        }
+        // Sizes in the zip headers are whatever the uploader wrote, so the
+        // inflated data itself is capped.
+        let limit = manifest
+            .files
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| corrupt(format!("{} isn't in {}", name, MANIFEST_NAME)))?
+            .len;
+        let data = read_entry(&mut file, limit)?;
+        files.push((name, data));
+    }
+
+    if manifest.files.len() != files.len() {
+        return Err(corrupt(format!("files don't match {}", MANIFEST_NAME)));
+    }
+    for (name, data) in &files {
+        manifest.verify(name, data)?;
+    }
+
+    let stem = save_stem(&files)?;
+    let exists = |stem: &str| {
+        files
+            .iter()
+            .any(|(name, _)| target_dir.join(rename(name, stem)).exists())
+    };
+    let (status, saved_as) = if !exists(&stem) {
+        (RLoadStatus::Loaded, stem.clone())
+    } else {
+        match policy {
+            RConflictPolicy::Overwrite => (RLoadStatus::Overwritten, stem.clone()),
+            RConflictPolicy::Rename => {
+                let saved_as = (1..)
+                    .map(|suffix| format!("{}_{}", stem, suffix))
+                    .find(|saved_as| !exists(saved_as))
+                    .expect("Some suffix is free");
+                (RLoadStatus::Renamed, saved_as)
+            }
+            // Skip, also unknown policies leave the local save alone.
+            _ => return Ok((RLoadStatus::Skipped, stem)),
+        }
+    };
+
+    std::fs::create_dir_all(target_dir)?;
+    for (name, data) in &files {
+        std::fs::write(target_dir.join(rename(name, &saved_as)), data)?;
+    }
+    Ok((status, saved_as))
+}
+
+/// Name of `file` if it is a plain file in the root of the archive.
+/// Anything else could escape the target directory, so the archive is rejected.
+fn plain_name(file: &ZipFile) -> Result<String, BridgeError> {
+    let name = file.name().to_string();
+    let plain = !file.is_dir()
+        && file.enclosed_name().map_or(false, |path| {
+            let mut components = path.components();
+            matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none()
+        });
+    if !plain {
+        return Err(corrupt(format!("unsafe path {:?}", name)));
+    }
+    Ok(name)
+}
+
+/// Inflates `file`, failing if it is longer than `limit`.
+fn read_entry(file: &mut ZipFile, limit: u64) -> Result<Vec<u8>, BridgeError> {
+    let mut data = Vec::new();
+    file.by_ref().take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(corrupt(format!(
+            "{} is longer than {} bytes",
+            file.name(),
+            limit
+        )));
+    }
+    Ok(data)
+}
+
+/// Stem shared by all files of the save, e.g. `Game` of `Game.vcgm1` and `Game.vsgm1`.
+fn save_stem(files: &[(String, Vec<u8>)]) -> Result<String, BridgeError> {
+    let mut stems = files.iter().map(|(name, _)| stem(name));
+    let first = stems
+        .next()
+        .ok_or_else(|| corrupt("no save files".to_string()))?;
+    if stems.any(|stem| stem != first) {
+        return Err(corrupt("files of different saves".to_string()));
+    }
+    Ok(first.to_string())
+}
+
+fn stem(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+/// `name` with its stem replaced by `new_stem`.
+fn rename(name: &str, new_stem: &str) -> String {
+    format!("{}{}", new_stem, &name[stem(name).len()..])
+}
+
+fn corrupt(message: String) -> BridgeError {
+    BridgeError::new(RErrorKind::CorruptArchive, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    /// Empty directory of its own for every test.
+    fn test_dir() -> PathBuf {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "rust_vcmi-archive-{}-{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn manifest(files: &[(&str, &[u8])]) -> Vec<u8> {
+        Manifest {
+            vcmi_version: "1.2.0".to_string(),
+            saved_at: 1,
+            files: files
+                .iter()
+                .map(|(name, data)| ManifestEntry::new(*name, data))
+                .collect(),
+        }
+        .to_json()
+    }
+
+    /// Archive of a save with a manifest listing its files.
+    fn save_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let manifest = manifest(files);
+        let mut entries = vec![(MANIFEST_NAME, manifest.as_slice())];
+        entries.extend_from_slice(files);
+        zip(&entries)
+    }
+
+    fn unpack(archive: &[u8], dir: &Path) -> Result<(RLoadStatus, String), BridgeError> {
+        unpack_save(archive, dir, RConflictPolicy::Skip)
+    }
+
+    fn assert_corrupt(result: Result<(RLoadStatus, String), BridgeError>, reason: &str) {
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("corrupt_archive: "), "{}", error);
+        assert!(error.contains(reason), "{}", error);
+    }
+
+    const GAME: [(&str, &[u8]); 2] = [("Game.vcgm1", b"client"), ("Game.vsgm1", b"server")];
+
+    #[test]
+    fn unsafe_paths_are_rejected() {
+        let dir = test_dir();
+        let target = dir.join("Saves");
+        for name in ["../x.vcgm1", "/tmp/x.vcgm1", "a/b.vcgm1", "a/../../x.vcgm1"] {
+            let files: [(&str, &[u8]); 1] = [(name, b"client")];
+            assert_corrupt(unpack(&save_archive(&files), &target), "unsafe path");
+        }
+        assert!(!target.exists());
+        assert!(!dir.join("x.vcgm1").exists());
+    }
+
+    #[test]
+    fn conflict_policies() {
+        let dir = test_dir();
+        let archive = save_archive(&GAME);
+        let read = |name: &str| std::fs::read(dir.join(name)).unwrap();
+
+        let loaded = unpack_save(&archive, &dir, RConflictPolicy::Skip).unwrap();
+        assert_eq!(loaded, (RLoadStatus::Loaded, "Game".to_string()));
+        assert_eq!(read("Game.vcgm1"), b"client");
+        assert_eq!(read("Game.vsgm1"), b"server");
+
+        std::fs::write(dir.join("Game.vsgm1"), b"local").unwrap();
+        let skipped = unpack_save(&archive, &dir, RConflictPolicy::Skip).unwrap();
+        assert_eq!(skipped, (RLoadStatus::Skipped, "Game".to_string()));
+        assert_eq!(read("Game.vsgm1"), b"local");
+
+        let renamed = unpack_save(&archive, &dir, RConflictPolicy::Rename).unwrap();
+        assert_eq!(renamed, (RLoadStatus::Renamed, "Game_1".to_string()));
+        assert_eq!(read("Game_1.vcgm1"), b"client");
+        assert_eq!(read("Game_1.vsgm1"), b"server");
+        assert_eq!(read("Game.vsgm1"), b"local");
+        let renamed = unpack_save(&archive, &dir, RConflictPolicy::Rename).unwrap();
+        assert_eq!(renamed, (RLoadStatus::Renamed, "Game_2".to_string()));
+
+        let overwritten = unpack_save(&archive, &dir, RConflictPolicy::Overwrite).unwrap();
+        assert_eq!(overwritten, (RLoadStatus::Overwritten, "Game".to_string()));
+        assert_eq!(read("Game.vsgm1"), b"server");
+    }
+
+    #[test]
+    fn files_must_match_the_manifest() {
+        let dir = test_dir();
+        let manifest = manifest(&GAME);
+
+        let other_size = zip(&[
+            (MANIFEST_NAME, &manifest),
+            ("Game.vcgm1", b"client"),
+            ("Game.vsgm1", b"server, longer"),
+        ]);
+        assert_corrupt(unpack(&other_size, &dir), "longer than 6 bytes");
+
+        let other_content = zip(&[
+            (MANIFEST_NAME, &manifest),
+            ("Game.vcgm1", b"client"),
+            ("Game.vsgm1", b"SERVER"),
+        ]);
+        assert_corrupt(unpack(&other_content, &dir), "doesn't match");
+
+        let missing_file = zip(&[(MANIFEST_NAME, &manifest), ("Game.vcgm1", b"client")]);
+        assert_corrupt(unpack(&missing_file, &dir), "files don't match");
+
+        let unlisted_file = zip(&[
+            (MANIFEST_NAME, &manifest),
+            ("Game.vcgm1", b"client"),
+            ("Game.vsgm1", b"server"),
+            ("Game.txt", b"extra"),
+        ]);
+        assert_corrupt(unpack(&unlisted_file, &dir), "isn't in");
+        assert!(!dir.join("Game.vcgm1").exists());
+    }
+
+    #[test]
+    fn archive_without_manifest_is_rejected() {
+        let dir = test_dir();
+        assert_corrupt(unpack(&zip(&GAME), &dir), "no manifest.json");
+        assert!(!dir.join("Game.vcgm1").exists());
+    }
+
+    #[test]
+    fn save_without_a_component_is_rejected() {
+        let dir = test_dir();
+        let vcgm = dir.join("Game.vcgm1");
+        std::fs::write(&vcgm, b"client").unwrap();
+        let vcgm = vcgm.to_str().unwrap();
+        let vsgm = dir.join("Game.vsgm1");
+        let vsgm = vsgm.to_str().unwrap();
+
+        let error = pack_save(&[vcgm, vsgm], "1.2.0").unwrap_err().to_string();
+        assert!(error.starts_with("io: "), "{}", error);
+        assert!(error.contains("Game.vsgm1 is missing"), "{}", error);
+        let error = pack_save(&[vcgm], "1.2.0").unwrap_err().to_string();
+        assert!(error.contains("no .vsgm1 file"), "{}", error);
+    }
+
+    #[test]
+    fn pack_and_unpack() {
+        let dir = test_dir();
+        let paths: Vec<String> = GAME
+            .iter()
+            .map(|(name, data)| {
+                let path = dir.join(name);
+                std::fs::write(&path, data).unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+
+        let (archive, manifest) = pack_save(&paths, "1.2.0").unwrap();
+        assert_eq!(manifest.vcmi_version, "1.2.0");
+        let modified = std::fs::metadata(paths[1]).unwrap().modified().unwrap();
+        let modified = modified.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!(manifest.saved_at >= modified);
+        assert_eq!(
+            manifest.files,
+            vec![
+                ManifestEntry::new("Game.vcgm1", b"client"),
+                ManifestEntry::new("Game.vsgm1", b"server"),
+            ]
+        );
+        assert_eq!(manifest.files[1].len, 6);
+        assert_eq!(
+            manifest.files[1].sha256,
+            "b3eacd33433b31b5252351032c9b3e7a2e7aa7738d5decdf0dd6c62680853c06"
+        );
+        // The manifest in the archive is the returned one
+        let mut zip = ZipArchive::new(Cursor::new(archive.as_slice())).unwrap();
+        let stored = read_entry(&mut zip.by_name(MANIFEST_NAME).unwrap(), MAX_MANIFEST_LEN);
+        assert_eq!(Manifest::from_json(&stored.unwrap()).unwrap(), manifest);
+
+        let target = dir.join("Saves");
+        let loaded = unpack(&archive, &target).unwrap();
+        assert_eq!(loaded, (RLoadStatus::Loaded, "Game".to_string()));
+        for (name, data) in GAME {
+            assert_eq!(std::fs::read(target.join(name)).unwrap(), data);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::connection::{Connection, CONNECTIONS};
+use crate::error::BridgeError;
+use crate::ffi::{RBattleInfo, RBattleSide, RErrorKind};
+use crate::try_init_connection;
+use crossbeam_channel::{Receiver, TryRecvError};
+use gear_connector_api::handshake::Capability;
+use gear_connector_api::{BattleInfo, RequestId, VcmiCommand, VcmiReply};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Overrides how long VCMI waits for the chain before resolving the battle itself.
+const BATTLE_TIMEOUT_ENV: &str = "VCMI_GEAR_BATTLE_TIMEOUT_MS";
+const DEFAULT_BATTLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct PendingBattle {
+    connection: Weak<Connection>,
+    request_id: RequestId,
+    reply_receiver: Receiver<VcmiReply>,
+    deadline: Instant,
+}
+
+/// Ticket 0 is never issued, it means the battle isn't submitted.
+static NEXT_TICKET: AtomicU64 = AtomicU64::new(1);
+static BATTLES: Lazy<Mutex<HashMap<u64, PendingBattle>>> = Lazy::new(Default::default);
+
+/// Sends the battle to gear-connector and returns the ticket to poll it with,
+/// or 0 if gear-connector doesn't simulate battles.
+pub fn submit_battle_onchain(battle_info: &RBattleInfo) -> Result<u64, BridgeError> {
+    let connection = try_init_connection!();
+    if !connection.supports(Capability::BattleSimulation) {
+        return Ok(0);
+    }
+    let command = VcmiCommand::SimulateBattle(battle_info.clone().into());
+    let (request_id, reply_receiver) = connection.submit(command);
+
+    let ticket = NEXT_TICKET.fetch_add(1, Relaxed);
+    let battle = PendingBattle {
+        connection: Arc::downgrade(&connection),
+        request_id,
+        reply_receiver,
+        deadline: Instant::now() + battle_timeout(),
+    };
+    BATTLES
+        .lock()
+        .expect("Battles lock poisoned")
+        .insert(ticket, battle);
+    Ok(ticket)
+}
+
+/// Returns `true` and fills `battle_info` with the simulated battle once it is done.
+/// The ticket is forgotten unless the battle is still pending.
+pub fn poll_battle(ticket: u64, battle_info: &mut RBattleInfo) -> Result<bool, BridgeError> {
+    let mut battles = BATTLES.lock().expect("Battles lock poisoned");
+    let battle = match battles.get(&ticket) {
+        Some(battle) => battle,
+        None => {
+            return Err(BridgeError::new(
+                RErrorKind::Internal,
+                format!("unknown battle {}", ticket),
+            ))
+        }
+    };
+    let reply = match battle.reply_receiver.try_recv() {
+        Ok(reply) => reply,
+        Err(TryRecvError::Empty) if Instant::now() < battle.deadline => return Ok(false),
+        Err(TryRecvError::Empty) => {
+            if let Some(connection) = battle.connection.upgrade() {
+                connection.cancel(battle.request_id);
+            }
+            battles.remove(&ticket);
+            return Err(BridgeError::new(
+                RErrorKind::Timeout,
+                format!("battle {} isn't simulated on chain in time", ticket),
+            ));
+        }
+        Err(TryRecvError::Disconnected) => {
+            battles.remove(&ticket);
+            return Err(BridgeError::new(
+                RErrorKind::ConnectorUnreachable,
+                "link to gear-connector is down",
+            ));
+        }
+    };
+    battles.remove(&ticket);
+
+    match reply.into_result()? {
+        VcmiReply::BattleInfo(received) => {
+            apply(battle_info, &received);
+            Ok(true)
+        }
+        reply => Err(BridgeError::new(
+            RErrorKind::Internal,
+            format!("unexpected reply to SimulateBattle: {:?}", reply),
+        )),
+    }
+}
+
+fn apply(rbattle_info: &mut RBattleInfo, received: &BattleInfo) {
+    let s1: RBattleSide = (&received.sides[0]).into();
+    let s2: RBattleSide = (&received.sides[1]).into();
+
+    rbattle_info.round = received.round;
+    rbattle_info.active_stack = received.active_stack;
+    rbattle_info.terrain_type = received.terrain_type.clone().into();
+    rbattle_info.stacks = received.stacks.iter().map(|stack| stack.into()).collect();
+    rbattle_info.sides = [s1, s2];
+}
+
+fn battle_timeout() -> Duration {
+    match std::env::var(BATTLE_TIMEOUT_ENV) {
+        Ok(timeout) => match timeout.parse() {
+            Ok(timeout) => Duration::from_millis(timeout),
+            Err(e) => {
+                println!("{}: {}", BATTLE_TIMEOUT_ENV, e);
+                DEFAULT_BATTLE_TIMEOUT
+            }
+        },
+        Err(_) => DEFAULT_BATTLE_TIMEOUT,
+    }
+}
@@ -0,0 +1,369 @@
+use crate::dispatcher::Dispatcher;
+use crate::error::BridgeError;
+use crate::ffi::RErrorKind;
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use futures::{SinkExt, StreamExt};
+use gear_connector_api::codec::WireFormat;
+use gear_connector_api::endpoint::resolve_address;
+use gear_connector_api::handshake::{client_handshake, Capability, Hello};
+use gear_connector_api::utils::split_to_reply_read_command_write;
+use gear_connector_api::{CommandEnvelope, ConnectorError, RequestId, VcmiCommand, VcmiReply};
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream as TokioTcpStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+const BUILD_ID: &str = concat!("rust_vcmi-", env!("CARGO_PKG_VERSION"));
+const CAPABILITIES: &[Capability] = &[
+    Capability::BattleSimulation,
+    Capability::ArchiveSave,
+    Capability::GameStateSave,
+];
+/// Forces the wire format, e.g. `json` to read the traffic while debugging.
+const WIRE_FORMAT_ENV: &str = "VCMI_GEAR_WIRE_FORMAT";
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Connection to gear-connector shared by the VCMI client and server threads.
+pub static CONNECTIONS: ConnectionManager = ConnectionManager::new();
+
+/// Owns the current connection and replaces it when the link goes down.
+/// Reconnects happen on demand, no more often than the backoff allows.
+/// The lock is never held while connecting, so other threads fail fast meanwhile.
+pub struct ConnectionManager {
+    state: Mutex<ManagerState>,
+}
+
+struct ManagerState {
+    connection: Option<Arc<Connection>>,
+    backoff: Duration,
+    next_attempt: Option<Instant>,
+    /// Some thread is opening a connection.
+    connecting: bool,
+    /// Bumped by `shutdown`, so a connection opened meanwhile is dropped.
+    generation: u64,
+}
+
+impl ConnectionManager {
+    const fn new() -> Self {
+        Self {
+            state: Mutex::new(ManagerState {
+                connection: None,
+                backoff: INITIAL_BACKOFF,
+                next_attempt: None,
+                connecting: false,
+                generation: 0,
+            }),
+        }
+    }
+
+    /// Returns the live connection, reconnecting if the previous one is down.
+    pub fn connection(&self) -> Result<Arc<Connection>, ConnectorError> {
+        let generation = {
+            let mut state = self.lock();
+            if let Some(connection) = &state.connection {
+                if connection.is_alive() {
+                    return Ok(connection.clone());
+                }
+                state.connection = None;
+            }
+            if state.connecting {
+                return Err(ConnectorError::NotConnected(
+                    "connecting to gear-connector".to_string(),
+                ));
+            }
+            if let Some(next_attempt) = state.next_attempt {
+                let now = Instant::now();
+                if now < next_attempt {
+                    return Err(ConnectorError::NotConnected(format!(
+                        "gear-connector is unreachable, next attempt in {} ms",
+                        (next_attempt - now).as_millis()
+                    )));
+                }
+            }
+            state.connecting = true;
+            state.generation
+        };
+
+        let opened = Connection::open();
+
+        let mut state = self.lock();
+        state.connecting = false;
+        if state.generation != generation {
+            return Err(ConnectorError::NotConnected(
+                "connection is shut down".to_string(),
+            ));
+        }
+        match opened {
+            Ok(connection) => {
+                state.connection = Some(connection.clone());
+                state.backoff = INITIAL_BACKOFF;
+                state.next_attempt = None;
+                Ok(connection)
+            }
+            Err(e) => {
+                state.next_attempt = Some(Instant::now() + state.backoff);
+                state.backoff = (state.backoff * 2).min(MAX_BACKOFF);
+                Err(ConnectorError::NotConnected(format!(
+                    "can't connect to gear-connector: {}",
+                    e
+                )))
+            }
+        }
+    }
+
+    /// Closes the connection, pending requests fail with `NotConnected`.
+    pub fn shutdown(&self) {
+        let connection = {
+            let mut state = self.lock();
+            state.backoff = INITIAL_BACKOFF;
+            state.next_attempt = None;
+            state.generation += 1;
+            state.connection.take()
+        };
+        if let Some(connection) = connection {
+            connection.link.down("connection is shut down");
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ManagerState> {
+        self.state.lock().expect("ConnectionManager lock poisoned")
+    }
+}
+
+/// State shared by a connection and its tasks.
+struct Link {
+    alive: AtomicBool,
+    dispatcher: Dispatcher,
+}
+
+impl Link {
+    fn is_alive(&self) -> bool {
+        self.alive.load(SeqCst)
+    }
+
+    fn down(&self, reason: &str) {
+        if self.alive.swap(false, SeqCst) {
+            println!("Link to gear-connector is down: {}", reason);
+            let error =
+                ConnectorError::NotConnected(format!("link to gear-connector is down: {}", reason));
+            self.dispatcher.fail_all(&error);
+        }
+    }
+}
+
+pub struct Connection {
+    // Dropping the runtime stops the reader and writer tasks.
+    _runtime: tokio::runtime::Runtime,
+    capabilities: Vec<Capability>,
+    command_sender: UnboundedSender<CommandEnvelope>,
+    link: Arc<Link>,
+    // Dropping the sender stops the health check thread.
+    _stop_health_check: Sender<()>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.link.down("connection is closed");
+    }
+}
+
+impl Connection {
+    fn open() -> std::io::Result<Arc<Connection>> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let hello = Hello::new(BUILD_ID, CAPABILITIES).with_wire_formats(&wire_formats());
+        let (address, source) = resolve_address()?;
+        println!("Connect to gear-connector on {} ({})", address, source);
+        let (tokio_stream, negotiated) = runtime.block_on(async move {
+            let mut stream = TokioTcpStream::connect(address).await?;
+            let negotiated = client_handshake(&mut stream, hello).await?;
+            Ok::<_, std::io::Error>((stream, negotiated))
+        })?;
+        println!(
+            "Connected to gear-connector, capabilities: {:?}, wire format: {}",
+            negotiated.capabilities, negotiated.wire_format
+        );
+
+        let link = Arc::new(Link {
+            alive: AtomicBool::new(true),
+            dispatcher: Dispatcher::default(),
+        });
+        let (mut reply_read_stream, mut command_write_stream) =
+            split_to_reply_read_command_write(tokio_stream, negotiated.wire_format);
+        let (command_sender, mut command_receiver) = unbounded_channel::<CommandEnvelope>();
+
+        let writer_link = link.clone();
+        runtime.spawn(async move {
+            while let Some(command) = command_receiver.recv().await {
+                if let Err(e) = command_write_stream.send(command).await {
+                    writer_link.down(&format!("can't send command: {}", e));
+                    break;
+                }
+            }
+        });
+
+        let reader_link = link.clone();
+        runtime.spawn(async move {
+            loop {
+                match reply_read_stream.next().await {
+                    Some(Ok(reply)) => reader_link.dispatcher.dispatch(reply),
+                    Some(Err(e)) => {
+                        reader_link.down(&format!("can't read reply: {}", e));
+                        break;
+                    }
+                    None => {
+                        reader_link.down("gear-connector closed the connection");
+                        break;
+                    }
+                }
+            }
+        });
+
+        let (stop_health_check, health_check_stopped) = bounded(0);
+        let connection = Arc::new(Connection {
+            _runtime: runtime,
+            capabilities: negotiated.capabilities,
+            command_sender,
+            link,
+            _stop_health_check: stop_health_check,
+        });
+        let weak = Arc::downgrade(&connection);
+        std::thread::spawn(move || health_check(weak, health_check_stopped));
+        Ok(connection)
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.link.is_alive()
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        let supported = self.capabilities.contains(&capability);
+        if !supported {
+            println!("gear-connector doesn't support {capability}, skip");
+        }
+        supported
+    }
+
+    /// Fails if gear-connector didn't agree to `capability` in the handshake,
+    /// so the player isn't told that something went on chain when nothing was sent.
+    pub fn require(&self, capability: Capability) -> Result<(), BridgeError> {
+        if self.capabilities.contains(&capability) {
+            return Ok(());
+        }
+        Err(BridgeError::new(
+            RErrorKind::IncompatibleConnector,
+            format!("gear-connector doesn't support {capability}"),
+        ))
+    }
+
+    /// Sends `command` without waiting for the reply.
+    pub fn send(&self, command: VcmiCommand) -> Result<(), ConnectorError> {
+        let request_id = self.link.dispatcher.next_request_id();
+        self.enqueue(CommandEnvelope {
+            request_id,
+            command,
+        })
+    }
+
+    /// Sends `command` and returns the ID of the request and the channel which
+    /// receives the reply to it. While the link is down the reply is `NotConnected`.
+    pub fn submit(&self, command: VcmiCommand) -> (RequestId, Receiver<VcmiReply>) {
+        let (request_id, reply_receiver) = self.link.dispatcher.register();
+        let envelope = CommandEnvelope {
+            request_id,
+            command,
+        };
+        if let Err(e) = self.enqueue(envelope) {
+            self.link.dispatcher.fail_all(&e);
+        } else if !self.link.is_alive() {
+            // The link went down between `register` and `fail_all` of `Link::down`.
+            self.link.dispatcher.fail_all(&ConnectorError::NotConnected(
+                "link to gear-connector is down".to_string(),
+            ));
+        }
+        (request_id, reply_receiver)
+    }
+
+    /// Sends `command` and returns the channel which receives the reply to it.
+    pub fn request(&self, command: VcmiCommand) -> Receiver<VcmiReply> {
+        self.submit(command).1
+    }
+
+    /// Sends `command` and waits for the reply, `VcmiReply::Error` becomes `Err`.
+    pub fn call(&self, command: VcmiCommand) -> Result<VcmiReply, ConnectorError> {
+        self.request(command)
+            .recv()
+            .map_err(|e| ConnectorError::NotConnected(format!("gear-connector is gone: {e}")))?
+            .into_result()
+    }
+
+    /// Forgets a request submitted with `submit` whose reply isn't needed anymore.
+    pub fn cancel(&self, request_id: RequestId) {
+        self.link.dispatcher.cancel(request_id);
+    }
+
+    /// Like `call`, but gives up after `timeout`.
+    pub fn call_timeout(
+        &self,
+        command: VcmiCommand,
+        timeout: Duration,
+    ) -> Result<VcmiReply, ConnectorError> {
+        let (request_id, reply_receiver) = self.submit(command);
+        match reply_receiver.recv_timeout(timeout) {
+            Ok(reply) => reply.into_result(),
+            Err(e) => {
+                self.cancel(request_id);
+                Err(ConnectorError::NotConnected(format!(
+                    "no reply from gear-connector: {}",
+                    e
+                )))
+            }
+        }
+    }
+
+    fn enqueue(&self, envelope: CommandEnvelope) -> Result<(), ConnectorError> {
+        if !self.link.is_alive() {
+            return Err(ConnectorError::NotConnected(
+                "link to gear-connector is down".to_string(),
+            ));
+        }
+        self.command_sender.send(envelope).map_err(|_| {
+            self.link.down("writer task stopped");
+            ConnectorError::NotConnected("link to gear-connector is down".to_string())
+        })
+    }
+}
+
+/// Pings gear-connector until the connection is dropped or the link goes down.
+fn health_check(connection: Weak<Connection>, stopped: Receiver<()>) {
+    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(HEALTH_CHECK_INTERVAL) {
+        let connection = match connection.upgrade() {
+            Some(connection) if connection.is_alive() => connection,
+            _ => break,
+        };
+        match connection.call_timeout(VcmiCommand::Ping, HEALTH_CHECK_TIMEOUT) {
+            Ok(VcmiReply::Pong) => {}
+            Ok(reply) => connection
+                .link
+                .down(&format!("unexpected reply to Ping: {:?}", reply)),
+            Err(e) => connection.link.down(&format!("health check failed: {}", e)),
+        }
+    }
+}
+
+fn wire_formats() -> Vec<WireFormat> {
+    match std::env::var(WIRE_FORMAT_ENV) {
+        Ok(format) => match format.parse() {
+            Ok(format) => vec![format],
+            Err(e) => {
+                println!("{}: {}", WIRE_FORMAT_ENV, e);
+                WireFormat::ALL.to_vec()
+            }
+        },
+        Err(_) => WireFormat::ALL.to_vec(),
+    }
+}
@@ -0,0 +1,66 @@
+use crossbeam_channel::{bounded, Receiver, Sender};
+use gear_connector_api::{ConnectorError, ReplyEnvelope, RequestId, VcmiReply};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Mutex,
+    },
+};
+
+/// Routes replies from gear-connector to the thread which sent the matching command,
+/// so the VCMI server thread and the client thread can wait for replies at the same time.
+#[derive(Default)]
+pub struct Dispatcher {
+    next_request_id: AtomicU64,
+    pending: Mutex<HashMap<RequestId, Sender<VcmiReply>>>,
+}
+
+impl Dispatcher {
+    pub fn next_request_id(&self) -> RequestId {
+        self.next_request_id.fetch_add(1, Relaxed)
+    }
+
+    /// Allocates a request ID and a channel which receives the reply to it.
+    pub fn register(&self) -> (RequestId, Receiver<VcmiReply>) {
+        let request_id = self.next_request_id();
+        let (reply_sender, reply_receiver) = bounded(1);
+        self.pending
+            .lock()
+            .expect("Dispatcher lock poisoned")
+            .insert(request_id, reply_sender);
+        (request_id, reply_receiver)
+    }
+
+    /// Forgets a request whose reply nobody waits for anymore.
+    pub fn cancel(&self, request_id: RequestId) {
+        self.pending
+            .lock()
+            .expect("Dispatcher lock poisoned")
+            .remove(&request_id);
+    }
+
+    /// Answers every pending request with `error`.
+    pub fn fail_all(&self, error: &ConnectorError) {
+        let pending = std::mem::take(&mut *self.pending.lock().expect("Dispatcher lock poisoned"));
+        for (_, waiter) in pending {
+            let _ = waiter.send(error.clone().into());
+        }
+    }
+
+    pub fn dispatch(&self, envelope: ReplyEnvelope) {
+        let waiter = self
+            .pending
+            .lock()
+            .expect("Dispatcher lock poisoned")
+            .remove(&envelope.request_id);
+        match waiter {
+            Some(waiter) => {
+                if waiter.send(envelope.reply).is_err() {
+                    println!("Nobody waits for reply to request {}", envelope.request_id);
+                }
+            }
+            None => println!("Drop reply to request {}", envelope.request_id),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::ffi::RErrorKind;
+use gear_connector_api::ConnectorError;
+use std::fmt;
+
+/// Error of a bridge function, thrown to C++ as `rust::Error`.
+/// `what()` is `<kind>: <message>`, `error_kind` recovers the kind from it.
+#[derive(Debug)]
+pub struct BridgeError {
+    kind: RErrorKind,
+    message: String,
+}
+
+impl BridgeError {
+    pub fn new(kind: RErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", tag(self.kind), self.message)
+    }
+}
+
+impl std::error::Error for BridgeError {}
+
+impl From<ConnectorError> for BridgeError {
+    fn from(error: ConnectorError) -> Self {
+        let kind = match error {
+            ConnectorError::NotConnected(_) => RErrorKind::ConnectorUnreachable,
+            ConnectorError::Chain(_) => RErrorKind::ChainRejected,
+            ConnectorError::Ipfs(_) => RErrorKind::Storage,
+            ConnectorError::Codec(_) => RErrorKind::CorruptArchive,
+            ConnectorError::Lobby(_) | ConnectorError::Internal(_) => RErrorKind::Internal,
+        };
+        Self::new(kind, error.to_string())
+    }
+}
+
+impl From<std::io::Error> for BridgeError {
+    fn from(error: std::io::Error) -> Self {
+        Self::new(RErrorKind::Io, error.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for BridgeError {
+    fn from(error: zip::result::ZipError) -> Self {
+        match error {
+            zip::result::ZipError::Io(error) => error.into(),
+            error => Self::new(RErrorKind::CorruptArchive, error.to_string()),
+        }
+    }
+}
+
+fn tag(kind: RErrorKind) -> &'static str {
+    match kind {
+        RErrorKind::ConnectorUnreachable => "connector_unreachable",
+        RErrorKind::ChainRejected => "chain_rejected",
+        RErrorKind::Timeout => "timeout",
+        RErrorKind::CorruptArchive => "corrupt_archive",
+        RErrorKind::Io => "io",
+        RErrorKind::Storage => "storage",
+        RErrorKind::IncompatibleConnector => "incompatible_connector",
+        _ => "internal",
+    }
+}
+
+/// Kind of the `rust::Error` whose `what()` is `what`.
+pub fn error_kind(what: &str) -> RErrorKind {
+    let what_tag = what.split(": ").next().unwrap_or_default();
+    [
+        RErrorKind::ConnectorUnreachable,
+        RErrorKind::ChainRejected,
+        RErrorKind::Timeout,
+        RErrorKind::CorruptArchive,
+        RErrorKind::Io,
+        RErrorKind::Storage,
+        RErrorKind::IncompatibleConnector,
+    ]
+    .iter()
+    .copied()
+    .find(|&kind| tag(kind) == what_tag)
+    .unwrap_or(RErrorKind::Internal)
+}
+
+/// Explains the `rust::Error` whose `what()` is `what` to the player.
+pub fn error_message(what: &str) -> String {
+    let hint = match error_kind(what) {
+        RErrorKind::ConnectorUnreachable => "gear-connector is not running or not reachable",
+        RErrorKind::ChainRejected => "the Gear network rejected the request",
+        RErrorKind::Timeout => "the Gear network didn't answer in time",
+        RErrorKind::CorruptArchive => "the saved game is damaged",
+        RErrorKind::Io => "the saved game can't be read or written",
+        RErrorKind::Storage => "IPFS can't store or return the saved game",
+        RErrorKind::IncompatibleConnector => {
+            "gear-connector is of another version, update the game and gear-connector together"
+        }
+        _ => "gear-connector failed",
+    };
+    format!("{} ({})", hint, what)
+}
@@ -1,254 +1,184 @@
 #![allow(non_camel_case_types, unreachable_patterns)]
 
-mod io;
+mod archive;
+mod battle;
+mod connection;
+mod dispatcher;
+mod error;
+mod transfer;
 mod utils;
 
-use futures::{SinkExt, StreamExt};
-use gear_connector_api::utils::split_to_reply_read_command_write;
+use battle::{poll_battle, submit_battle_onchain};
+use connection::CONNECTIONS;
+use error::{error_kind, error_message, BridgeError};
+use gear_connector_api::handshake::Capability;
 use gear_connector_api::*;
 use gstd::prelude::*;
-use once_cell::sync::OnceCell;
-use std::{
-    fs::{File, OpenOptions},
-    io::Cursor,
-    path::Path,
-};
-
-use crossbeam_channel::{bounded, Receiver, Sender};
-use std::io::{Read, Write};
-use tokio::net::TcpStream as TokioTcpStream;
-use tokio::task::JoinHandle;
-
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::Relaxed;
-use std::sync::Arc;
-use zip::{
-    write::{FileOptions, ZipWriter},
-    ZipArchive,
-};
-
-#[allow(dead_code)]
-struct Connection {
-    runtime: tokio::runtime::Runtime,
-    need_stop: Arc<AtomicBool>,
-    command_sender: Sender<VcmiCommand>,
-    reply_receiver: Receiver<VcmiReply>,
-    read_t: JoinHandle<()>,
-    write_t: JoinHandle<()>,
-}
+use std::path::Path;
 
-static mut CONNECTION: OnceCell<Connection> = OnceCell::new();
+use transfer::transfer_progress;
 
-pub fn save_files_onchain(vcgm_path: String, vsgm_path: String) -> i32 {
-    let connection = try_init_connection!(connection_init);
-    println!("Rust save_state_onchain");
+/// Saves the game on chain next to the saves already there, even one with the same name.
+pub fn save_files_onchain(
+    vcgm_path: String,
+    vsgm_path: String,
+    metadata: ffi::RSaveMetadata,
+) -> Result<(), BridgeError> {
+    upload_save(&vcgm_path, &vsgm_path, metadata, None)
+}
 
-    let path = Path::new(&vcgm_path);
-    let filename = path.file_stem().unwrap().to_str().unwrap();
-    assert_eq!(
-        filename,
-        Path::new(&vsgm_path).file_stem().unwrap().to_str().unwrap()
-    );
-    let filename = format!("{filename}");
+/// Saves the game on chain in place of the save with IPFS CID `cid`,
+/// which the player chose to replace.
+pub fn replace_save_onchain(
+    cid: String,
+    vcgm_path: String,
+    vsgm_path: String,
+    metadata: ffi::RSaveMetadata,
+) -> Result<(), BridgeError> {
+    upload_save(&vcgm_path, &vsgm_path, metadata, Some(&cid))
+}
+
+fn upload_save(
+    vcgm_path: &str,
+    vsgm_path: &str,
+    metadata: ffi::RSaveMetadata,
+    replace: Option<&str>,
+) -> Result<(), BridgeError> {
+    let connection = try_init_connection!();
+    connection.require(Capability::ArchiveSave)?;
+
+    let filename = file_stem(vcgm_path)?;
+    if filename != file_stem(vsgm_path)? {
+        return Err(BridgeError::new(
+            ffi::RErrorKind::Internal,
+            format!("{vcgm_path} and {vsgm_path} belong to different saves"),
+        ));
+    }
     println!(
         "Save current state {} {} {} on gear chain",
         filename, vcgm_path, vsgm_path,
     );
 
-    let archive = File::create(&filename).unwrap();
-    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
-    let mut zip = ZipWriter::new(archive);
-
-    let mut original_vsgm_len = 0;
-    match OpenOptions::new().read(true).write(false).open(&vsgm_path) {
-        Ok(mut vsgm_file) => {
-            let vsgm_inner_path = format!("{filename}.vsgm1");
-
-            zip.start_file(vsgm_inner_path, options).unwrap();
-            let mut buffer = Vec::new();
-            vsgm_file.read_to_end(&mut buffer).unwrap();
-            zip.write_all(&*buffer).unwrap();
-
-            let vsgm_metadata = vsgm_file.metadata().unwrap();
-            original_vsgm_len = vsgm_metadata.len();
-        }
-        Err(e) => println!("Can't open {vsgm_path}: {}", e),
+    let mut metadata: SaveMetadata = metadata.into();
+    let (buf, manifest) = archive::pack_save(&[vcgm_path, vsgm_path], &metadata.vcmi_version)?;
+    metadata.uncompressed_size = manifest.files.iter().map(|file| file.len).sum();
+    println!(
+        "Upload {filename}.zip {} (original {}) to gear-connector",
+        buf.len(),
+        metadata.uncompressed_size
+    );
+    if let Some(cid) = replace {
+        println!("Replace {filename} {cid} on chain");
     }
+    transfer::upload(&filename, &buf, &metadata, replace)?;
+    println!("Saved {filename} on chain");
+    Ok(())
+}
 
-    let mut original_vcgm_len = 0;
-    let vcgm_inner_path = format!("{filename}.vcgm1");
-    match OpenOptions::new().read(true).write(false).open(&vcgm_path) {
-        Ok(mut vcgm_file) => {
-            zip.start_file(vcgm_inner_path, options).unwrap();
-            let mut buffer = Vec::new();
-            vcgm_file.read_to_end(&mut buffer).unwrap();
-            zip.write_all(&*buffer).unwrap();
+fn file_stem(path: &str) -> Result<String, BridgeError> {
+    Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.to_string())
+        .ok_or_else(|| BridgeError::new(ffi::RErrorKind::Io, format!("{path} isn't a save file")))
+}
 
-            let vcgm_metadata = vcgm_file.metadata().unwrap();
-            original_vcgm_len = vcgm_metadata.len();
+/// Lists the saves on chain matching `query` without downloading them.
+pub fn list_saves_onchain(
+    query: ffi::RSaveQuery,
+) -> Result<Vec<ffi::RSaveDescription>, BridgeError> {
+    let connection = try_init_connection!();
+    let mut saves = match connection.call(VcmiCommand::ListSaves)? {
+        VcmiReply::Saves(saves) => saves,
+        reply => {
+            return Err(BridgeError::new(
+                ffi::RErrorKind::Internal,
+                format!("unexpected reply to ListSaves: {:?}", reply),
+            ))
         }
-        Err(e) => println!("Can't open {vcgm_path}: {}", e),
-    }
-    let len = {
-        let archive = zip.finish().unwrap();
-        archive.metadata().unwrap().len()
     };
-    let mut buf: Vec<u8> = Vec::with_capacity(len as usize);
-
-    let mut archive = File::open(&filename).unwrap();
-    archive.read_to_end(&mut buf).unwrap();
 
-    // archive.read_to_end(&mut buf).unwrap();
-    let compressed_len = buf.len();
-
-    let original_len = original_vcgm_len + original_vsgm_len;
+    let text = query.text.to_lowercase();
+    saves.retain(|save| {
+        let map_name = save.metadata.as_ref().map(|metadata| &metadata.map_name);
+        let text_matches = text.is_empty()
+            || save.name.to_lowercase().contains(&text)
+            || map_name.map_or(false, |map_name| map_name.to_lowercase().contains(&text));
+        let version_matches = query.vcmi_version.is_empty()
+            || save.metadata.as_ref().map_or(false, |metadata| {
+                metadata.vcmi_version == query.vcmi_version
+            });
+        text_matches && version_matches
+    });
+    match query.sort_by {
+        ffi::RSaveSort::Date => saves.sort_by_key(|save| save.saved_at),
+        ffi::RSaveSort::Day => saves.sort_by_key(|save| save.metadata.as_ref().map(|m| m.day)),
+        ffi::RSaveSort::Size => saves.sort_by_key(|save| save.size),
+        _ => saves.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+    if query.descending {
+        saves.reverse();
+    }
+    Ok(saves.into_iter().map(|save| save.into()).collect())
+}
 
-    let vcmi_command = VcmiCommand::SaveArchive {
-        filename: filename.clone(),
-        compressed_archive: buf,
+/// Downloads the save with IPFS CID `cid` and extracts it into `target_dir`,
+/// resolving a conflict with a local save by `policy`.
+pub fn fetch_save_from_chain(
+    cid: String,
+    target_dir: String,
+    policy: ffi::RConflictPolicy,
+) -> Result<ffi::RLoadReport, BridgeError> {
+    let connection = try_init_connection!();
+
+    println!("Fetch saved game {} into {}", cid, target_dir);
+
+    let info = match connection.call(VcmiCommand::FetchSave { cid })? {
+        VcmiReply::Fetched(info) => info,
+        reply => {
+            return Err(BridgeError::new(
+                ffi::RErrorKind::Internal,
+                format!("unexpected reply to FetchSave: {:?}", reply),
+            ))
+        }
     };
-    connection
-        .command_sender
-        .send(vcmi_command)
-        .expect("Error in another thread");
-    println!(
-        "Save Command.  Sended {filename}.tar {} (vec: {}) (original {}: {} + {} to gear-connector",
-        len, compressed_len, original_len, original_vcgm_len, original_vsgm_len,
-    );
-
-    0
+    println!("Game name: {} {} bytes", info.filename, info.len);
+    let data = transfer::download(&info)?;
+    let (status, saved_as) = archive::unpack_save(&data, Path::new(&target_dir), policy)?;
+    Ok(ffi::RLoadReport {
+        filename: info.filename,
+        status,
+        saved_as,
+    })
 }
 
-pub fn load_all_from_chain() -> i32 {
-    let connection = try_init_connection!(connection_init);
-
-    println!("Load all saved games from chain");
-
-    connection
-        .command_sender
-        .send(VcmiCommand::LoadAll)
-        .expect("Error in another thread");
-    println!("Try to receive all saved games");
-    let reply = connection.reply_receiver.recv().expect("Recv error");
-    match reply {
-        VcmiReply::AllLoaded { archives } => {
-            for saved_game in archives {
-                println!(
-                    "Game name: {} {} bytes",
-                    saved_game.filename,
-                    saved_game.data.len()
-                );
-
-                let cursor = Cursor::new(saved_game.data);
-                let mut archive = ZipArchive::new(cursor).unwrap();
-                archive.extract("~/.local/share/vcmi/Saves/").unwrap();
-            }
-        }
-        _ => unreachable!(),
-    }
-    0
+fn shutdown_connection() {
+    CONNECTIONS.shutdown();
 }
 
-fn save_game_state(day: u32, current_player: String, players: Vec<ffi::RPlayerState>) -> i32 {
-    let player_states: Vec<PlayerState> = players.into_iter().map(|state| state.into()).collect();
+fn save_game_state(
+    day: u32,
+    current_player: String,
+    players: Vec<ffi::RPlayerState>,
+) -> Result<(), BridgeError> {
+    let player_states = players
+        .into_iter()
+        .map(PlayerState::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
     println!(
         "Day: {day}, current_player: {current_player}, players: {:?}",
         player_states
     );
-    let connection = try_init_connection!(connection_init);
-
-    connection
-        .command_sender
-        .send(VcmiCommand::SaveGameState {
-            day,
-            current_player,
-            player_states,
-        })
-        .expect("Error in another thread");
-    0
-}
+    let connection = try_init_connection!();
+    connection.require(Capability::GameStateSave)?;
 
-fn simulate_battle_onchain(rbattle_info: &mut ffi::RBattleInfo) -> i32 {
-    let connection = try_init_connection!(connection_init);
-    let battle_info = rbattle_info.clone().into();
-    connection
-        .command_sender
-        .send(VcmiCommand::SimulateBattle(battle_info))
-        .expect("Error in another thread");
-    let reply = connection.reply_receiver.recv().expect("Recv error");
-    match reply {
-        VcmiReply::BattleInfo(ref received) => {
-            dbg!(received);
-            let round = received.round;
-            let active_stack = received.active_stack;
-            let terrain_type = received.terrain_type.clone().into();
-            let stacks = received.stacks.iter().map(|stack| stack.into()).collect();
-            let s1 = (&received.sides[0]).into();
-            let s2 = (&received.sides[1]).into();
-            let sides: [ffi::RBattleSide; 2] = [s1, s2];
-
-            rbattle_info.round = round;
-            rbattle_info.active_stack = active_stack;
-            rbattle_info.terrain_type = terrain_type;
-            rbattle_info.stacks = stacks;
-            rbattle_info.sides = sides;
-
-            dbg!(&rbattle_info);
-        }
-        _ => {
-            dbg!(reply);
-        }
-    }
-    0
-}
-
-fn connection_init() -> Result<Connection, std::io::Error> {
-    let (command_sender, command_receiver) = bounded(1);
-    let (reply_sender, reply_receiver) = bounded(1);
-    let need_stop = Arc::new(AtomicBool::new(false));
-    let need_stop_clone = need_stop.clone();
-
-    let runtime = tokio::runtime::Runtime::new()?;
-    let tokio_stream =
-        runtime.block_on(async move { TokioTcpStream::connect("127.0.0.1:6666").await })?;
-
-    let need_stop = need_stop_clone.clone();
-    let (mut reply_read_stream, mut command_write_stream) =
-        split_to_reply_read_command_write(tokio_stream);
-    let read_t = runtime.spawn(async move {
-        while !need_stop.load(Relaxed) {
-            let command = command_receiver.recv().unwrap();
-            command_write_stream
-                .send(command)
-                .await
-                .expect("Send VCMI command Error");
-        }
-        println!("[Read thread] Stop listen gear-proxy")
-    });
-
-    let need_stop = need_stop_clone.clone();
-    let write_t = runtime.spawn(async move {
-        while !need_stop.load(Relaxed) {
-            match reply_read_stream.next().await {
-                Some(reply) => {
-                    let reply = reply.expect("Failed to parse");
-                    reply_sender.send(reply).expect("Error in another thread");
-                }
-                None => {}
-            }
-        }
-        println!("[Write thread] Stop listen gear-proxy")
-    });
-
-    let connection = Connection {
-        runtime,
-        need_stop: need_stop_clone,
-        command_sender,
-        reply_receiver,
-        read_t,
-        write_t,
+    let command = VcmiCommand::SaveGameState {
+        day,
+        current_player,
+        player_states,
     };
-    Ok(connection)
+    connection.send(command)?;
+    Ok(())
 }
 
 #[cxx::bridge]
@@ -400,6 +330,105 @@ mod ffi {
         hero: RHero,
     }
 
+    #[derive(Debug, Clone)]
+    #[repr(u8)]
+    enum RTransferDirection {
+        Idle = 0,
+        Upload,
+        Download,
+    }
+
+    /// Progress of the save archive upload or download in flight.
+    #[derive(Debug, Clone)]
+    struct RTransferProgress {
+        direction: RTransferDirection,
+        filename: String,
+        transferred: u64,
+        total: u64,
+    }
+
+    /// What to do with a saved game from chain whose files exist locally.
+    #[derive(Debug)]
+    #[repr(u8)]
+    enum RConflictPolicy {
+        Skip = 0,
+        Overwrite,
+        /// Extract it under the stem with the first free `_<n>` suffix.
+        Rename,
+    }
+
+    #[derive(Debug)]
+    #[repr(u8)]
+    enum RLoadStatus {
+        Loaded = 0,
+        Overwritten,
+        Renamed,
+        Skipped,
+    }
+
+    /// Outcome of loading a saved game from chain.
+    #[derive(Debug)]
+    struct RLoadReport {
+        filename: String,
+        status: RLoadStatus,
+        /// Stem of the extracted files, differs from `filename` if `Renamed`.
+        saved_as: String,
+    }
+
+    /// Save on chain. Unknown `size` and `saved_at` are 0, unknown `map_name` is empty.
+    #[derive(Debug)]
+    struct RSaveDescription {
+        name: String,
+        cid: String,
+        size: u64,
+        saved_at: u64,
+        /// Whether `metadata` is known, it is empty otherwise.
+        has_metadata: bool,
+        metadata: RSaveMetadata,
+        /// Whether only the account which saved the game can load it.
+        encrypted: bool,
+    }
+
+    #[derive(Debug, Clone)]
+    struct RSavePlayer {
+        color: String,
+        is_human: bool,
+    }
+
+    /// Collected by VCMI when the game is saved, `uncompressed_size` is filled by Rust.
+    #[derive(Debug, Clone)]
+    struct RSaveMetadata {
+        map_name: String,
+        map_width: i32,
+        map_height: i32,
+        two_levels: bool,
+        day: u32,
+        players: Vec<RSavePlayer>,
+        vcmi_version: String,
+        mods: Vec<String>,
+        uncompressed_size: u64,
+    }
+
+    #[derive(Debug)]
+    #[repr(u8)]
+    enum RSaveSort {
+        Name = 0,
+        Date,
+        Day,
+        Size,
+    }
+
+    /// Which saves `list_saves_onchain` returns and in what order.
+    #[derive(Debug)]
+    struct RSaveQuery {
+        sort_by: RSaveSort,
+        descending: bool,
+        /// Case-insensitive part of the save or map name, empty matches every save.
+        text: String,
+        /// Exact VCMI version the save is made with, empty matches every save.
+        vcmi_version: String,
+    }
+
     #[derive(Debug, Clone)]
     struct RBattleInfo {
         stacks: Vec<RStack>,
@@ -409,20 +438,80 @@ mod ffi {
         terrain_type: RTerrain,
     }
 
+    /// Kind of the `rust::Error` thrown by the bridge functions.
+    #[derive(Debug)]
+    #[repr(u8)]
+    enum RErrorKind {
+        Internal = 0,
+        ConnectorUnreachable,
+        ChainRejected,
+        Timeout,
+        CorruptArchive,
+        Io,
+        Storage,
+        /// gear-connector is of a build which can't do what was asked.
+        IncompatibleConnector,
+    }
+
+    extern "Rust" {
+        fn save_files_onchain(
+            vcgm_path: String,
+            vsgm_path: String,
+            metadata: RSaveMetadata,
+        ) -> Result<()>;
+    }
+
     extern "Rust" {
-        fn save_files_onchain(vcgm_path: String, vsgm_path: String) -> i32;
+        fn replace_save_onchain(
+            cid: String,
+            vcgm_path: String,
+            vsgm_path: String,
+            metadata: RSaveMetadata,
+        ) -> Result<()>;
     }
 
     extern "Rust" {
-        fn load_all_from_chain() -> i32;
+        fn list_saves_onchain(query: RSaveQuery) -> Result<Vec<RSaveDescription>>;
     }
 
     extern "Rust" {
-        fn save_game_state(day: u32, current_player: String, players: Vec<RPlayerState>) -> i32;
+        fn fetch_save_from_chain(
+            cid: String,
+            target_dir: String,
+            policy: RConflictPolicy,
+        ) -> Result<RLoadReport>;
     }
 
     extern "Rust" {
-        fn simulate_battle_onchain(battle_info: &mut RBattleInfo) -> i32;
+        fn transfer_progress() -> RTransferProgress;
+    }
+
+    extern "Rust" {
+        fn shutdown_connection();
+    }
+
+    extern "Rust" {
+        fn save_game_state(
+            day: u32,
+            current_player: String,
+            players: Vec<RPlayerState>,
+        ) -> Result<()>;
+    }
+
+    extern "Rust" {
+        fn submit_battle_onchain(battle_info: &RBattleInfo) -> Result<u64>;
+    }
+
+    extern "Rust" {
+        fn poll_battle(ticket: u64, battle_info: &mut RBattleInfo) -> Result<bool>;
+    }
+
+    extern "Rust" {
+        fn error_kind(what: &str) -> RErrorKind;
+    }
+
+    extern "Rust" {
+        fn error_message(what: &str) -> String;
     }
 
     // TODO! Try to understand how to include C++ header file
@@ -435,21 +524,109 @@ mod ffi {
     // }
 }
 
-impl From<ffi::RPlayerState> for PlayerState {
-    fn from(value: ffi::RPlayerState) -> Self {
+impl From<SaveDescription> for ffi::RSaveDescription {
+    fn from(value: SaveDescription) -> Self {
+        let has_metadata = value.metadata.is_some();
+        let metadata = value.metadata.map(|metadata| metadata.into());
+        Self {
+            name: value.name,
+            cid: value.cid,
+            size: value.size.unwrap_or_default(),
+            saved_at: value.saved_at.unwrap_or_default(),
+            has_metadata,
+            metadata: metadata.unwrap_or_else(|| ffi::RSaveMetadata {
+                map_name: String::new(),
+                map_width: 0,
+                map_height: 0,
+                two_levels: false,
+                day: 0,
+                players: Vec::new(),
+                vcmi_version: String::new(),
+                mods: Vec::new(),
+                uncompressed_size: 0,
+            }),
+            encrypted: value.encrypted,
+        }
+    }
+}
+
+impl From<ffi::RSaveMetadata> for SaveMetadata {
+    fn from(value: ffi::RSaveMetadata) -> Self {
+        Self {
+            map_name: value.map_name,
+            map_width: value.map_width,
+            map_height: value.map_height,
+            two_levels: value.two_levels,
+            day: value.day,
+            players: value
+                .players
+                .into_iter()
+                .map(|player| SavePlayer {
+                    color: player.color,
+                    is_human: player.is_human,
+                })
+                .collect(),
+            vcmi_version: value.vcmi_version,
+            mods: value.mods,
+            uncompressed_size: value.uncompressed_size,
+            // Set by gear-connector, which encrypts the archive
+            encrypted: None,
+        }
+    }
+}
+
+impl From<SaveMetadata> for ffi::RSaveMetadata {
+    fn from(value: SaveMetadata) -> Self {
+        Self {
+            map_name: value.map_name,
+            map_width: value.map_width,
+            map_height: value.map_height,
+            two_levels: value.two_levels,
+            day: value.day,
+            players: value
+                .players
+                .into_iter()
+                .map(|player| ffi::RSavePlayer {
+                    color: player.color,
+                    is_human: player.is_human,
+                })
+                .collect(),
+            vcmi_version: value.vcmi_version,
+            mods: value.mods,
+            uncompressed_size: value.uncompressed_size,
+        }
+    }
+}
+
+impl TryFrom<ffi::RPlayerState> for PlayerState {
+    type Error = BridgeError;
+
+    fn try_from(value: ffi::RPlayerState) -> Result<Self, Self::Error> {
         let heroes = value.heroes.into_iter().map(|hero| hero.into()).collect();
         let towns = value.towns.into_iter().map(|hero| hero.into()).collect();
 
-        let v: Vec<i64> = serde_json::from_str(&value.resources).expect("Can't parse resources");
-        let mut resources: Vec<Resource> = Vec::with_capacity(v.len());
-        resources.push(Resource::Wood(v[0]));
-        resources.push(Resource::Mercury(v[1]));
-        resources.push(Resource::Ore(v[2]));
-        resources.push(Resource::Sulfur(v[3]));
-        resources.push(Resource::Crystal(v[4]));
-        resources.push(Resource::Gems(v[5]));
-        resources.push(Resource::Gold(v[6]));
-        resources.push(Resource::Mithril(v[7]));
+        let v: Vec<i64> = serde_json::from_str(&value.resources).map_err(|e| {
+            BridgeError::new(
+                ffi::RErrorKind::Internal,
+                format!("can't parse resources of {}: {e}", value.color),
+            )
+        })?;
+        let &[wood, mercury, ore, sulfur, crystal, gems, gold, mithril, ..] = v.as_slice() else {
+            return Err(BridgeError::new(
+                ffi::RErrorKind::Internal,
+                format!("{} has {} resources, expected 8", value.color, v.len()),
+            ));
+        };
+        let resources = vec![
+            Resource::Wood(wood),
+            Resource::Mercury(mercury),
+            Resource::Ore(ore),
+            Resource::Sulfur(sulfur),
+            Resource::Crystal(crystal),
+            Resource::Gems(gems),
+            Resource::Gold(gold),
+            Resource::Mithril(mithril),
+        ];
 
         let days_without_castle = if value.days_without_castle >= 0 {
             Some(value.days_without_castle as u8)
@@ -457,7 +634,7 @@ impl From<ffi::RPlayerState> for PlayerState {
             None
         };
 
-        Self {
+        Ok(Self {
             color: value.color,
             team_id: value.team_id,
             is_human: value.is_human,
@@ -465,7 +642,7 @@ impl From<ffi::RPlayerState> for PlayerState {
             heroes,
             towns,
             days_without_castle,
-        }
+        })
     }
 }
 
@@ -0,0 +1,190 @@
+use crate::connection::CONNECTIONS;
+use crate::ffi::{RTransferDirection, RTransferProgress};
+use gear_connector_api::transfer::{checksum, CHUNK_LEN};
+use gear_connector_api::{
+    ArchiveInfo, Checksum, ConnectorError, SaveMetadata, VcmiCommand, VcmiReply,
+};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How many times an interrupted transfer is resumed before giving up.
+const MAX_RESUMES: u32 = 3;
+/// Pause before a resume, multiplied by the number of the resume.
+const RESUME_DELAY: Duration = Duration::from_secs(1);
+
+/// Transfer in progress, polled by the C++ side with `transfer_progress`.
+static PROGRESS: Mutex<Option<RTransferProgress>> = Mutex::new(None);
+
+pub fn transfer_progress() -> RTransferProgress {
+    PROGRESS
+        .lock()
+        .expect("Progress lock poisoned")
+        .clone()
+        .unwrap_or(RTransferProgress {
+            direction: RTransferDirection::Idle,
+            filename: String::new(),
+            transferred: 0,
+            total: 0,
+        })
+}
+
+fn set_progress(direction: RTransferDirection, filename: &str, transferred: u64, total: u64) {
+    *PROGRESS.lock().expect("Progress lock poisoned") = Some(RTransferProgress {
+        direction,
+        filename: filename.to_string(),
+        transferred,
+        total,
+    });
+}
+
+fn clear_progress() {
+    *PROGRESS.lock().expect("Progress lock poisoned") = None;
+}
+
+/// Repeats `attempt` while it fails with a retryable error, e.g. while gear-connector
+/// reconnects. Every attempt continues from where the previous one stopped.
+fn resume<T>(
+    what: &str,
+    mut attempt: impl FnMut() -> Result<T, ConnectorError>,
+) -> Result<T, ConnectorError> {
+    let mut resumes = 0;
+    let result = loop {
+        match attempt() {
+            Err(e) if e.is_retryable() && resumes < MAX_RESUMES => {
+                resumes += 1;
+                println!(
+                    "{} interrupted: {}, resume ({}/{})",
+                    what, e, resumes, MAX_RESUMES
+                );
+                std::thread::sleep(RESUME_DELAY * resumes);
+            }
+            result => break result,
+        }
+    };
+    clear_progress();
+    result
+}
+
+/// Uploads a zipped save in chunks and saves it on chain with its metadata.
+/// With `replace` the save with that IPFS CID is overwritten instead of adding another one.
+pub fn upload(
+    filename: &str,
+    archive: &[u8],
+    metadata: &SaveMetadata,
+    replace: Option<&str>,
+) -> Result<(), ConnectorError> {
+    let checksum = checksum(archive);
+    resume(&format!("Upload of {}", filename), || {
+        try_upload(filename, archive, checksum, metadata, replace)
+    })
+}
+
+fn try_upload(
+    filename: &str,
+    archive: &[u8],
+    checksum: Checksum,
+    metadata: &SaveMetadata,
+    replace: Option<&str>,
+) -> Result<(), ConnectorError> {
+    let connection = CONNECTIONS.connection()?;
+    let len = archive.len() as u64;
+    let begin = VcmiCommand::UploadBegin {
+        filename: filename.to_string(),
+        len,
+        checksum,
+    };
+    let mut offset = upload_offset(connection.call(begin)?, len)?;
+    if offset > 0 {
+        println!("Resume upload of {} from {}/{}", filename, offset, len);
+    }
+    while offset < len {
+        set_progress(RTransferDirection::Upload, filename, offset, len);
+        let start = offset as usize;
+        let end = (start + CHUNK_LEN).min(archive.len());
+        let chunk = VcmiCommand::UploadChunk {
+            checksum,
+            offset,
+            data: archive[start..end].to_vec(),
+        };
+        offset = upload_offset(connection.call(chunk)?, len)?;
+    }
+    set_progress(RTransferDirection::Upload, filename, len, len);
+
+    let commit = match replace {
+        Some(cid) => VcmiCommand::ReplaceSave {
+            cid: cid.to_string(),
+            checksum,
+            metadata: metadata.clone(),
+        },
+        None => VcmiCommand::UploadCommit {
+            checksum,
+            metadata: metadata.clone(),
+        },
+    };
+    match connection.call(commit)? {
+        VcmiReply::Saved => Ok(()),
+        reply => Err(unexpected_reply("UploadCommit", reply)),
+    }
+}
+
+fn upload_offset(reply: VcmiReply, len: u64) -> Result<u64, ConnectorError> {
+    match reply {
+        VcmiReply::UploadOffset { offset } if offset <= len => Ok(offset),
+        reply => Err(unexpected_reply("UploadChunk", reply)),
+    }
+}
+
+/// Downloads an archive staged by `FetchSave` and verifies its checksum.
+pub fn download(archive: &ArchiveInfo) -> Result<Vec<u8>, ConnectorError> {
+    let mut data = Vec::with_capacity(archive.len as usize);
+    resume(&format!("Download of {}", archive.filename), || {
+        try_download(archive, &mut data)
+    })?;
+    if checksum(&data) != archive.checksum {
+        return Err(ConnectorError::Codec(format!(
+            "checksum mismatch of {}",
+            archive.filename
+        )));
+    }
+    Ok(data)
+}
+
+fn try_download(archive: &ArchiveInfo, data: &mut Vec<u8>) -> Result<(), ConnectorError> {
+    let connection = CONNECTIONS.connection()?;
+    while (data.len() as u64) < archive.len {
+        let offset = data.len() as u64;
+        set_progress(
+            RTransferDirection::Download,
+            &archive.filename,
+            offset,
+            archive.len,
+        );
+        let command = VcmiCommand::DownloadChunk {
+            checksum: archive.checksum,
+            offset,
+            len: CHUNK_LEN as u32,
+        };
+        match connection.call(command)? {
+            VcmiReply::DownloadedChunk {
+                offset: chunk_offset,
+                data: chunk,
+            } if chunk_offset == offset && !chunk.is_empty() => data.extend_from_slice(&chunk),
+            reply => return Err(unexpected_reply("DownloadChunk", reply)),
+        }
+    }
+    Ok(())
+}
+
+fn unexpected_reply(command: &str, reply: VcmiReply) -> ConnectorError {
+    let reply = match reply {
+        VcmiReply::DownloadedChunk { offset, data } => {
+            format!(
+                "DownloadedChunk {{ offset: {}, len: {} }}",
+                offset,
+                data.len()
+            )
+        }
+        reply => format!("{:?}", reply),
+    };
+    ConnectorError::Internal(format!("unexpected reply to {}: {}", command, reply))
+}
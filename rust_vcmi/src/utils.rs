@@ -5,12 +5,12 @@ use std::io::Read;
 
 #[macro_export]
 macro_rules! try_init_connection {
-    ($init:expr) => {
-        match unsafe { CONNECTION.get_or_try_init($init) } {
+    () => {
+        match CONNECTIONS.connection() {
             Ok(connection) => connection,
             Err(e) => {
                 println!("Can't create connection: {}", e);
-                return 0;
+                return Err($crate::error::BridgeError::from(e));
             }
         }
     };
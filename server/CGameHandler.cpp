@@ -6484,32 +6484,50 @@ void CGameHandler::runBattle()
 		rbattle.sides[i] = rside;
 	}
 
-	// Run Gear program to simulate battle
-	simulate_battle_onchain(rbattle);
-
-	// Copy battle info from Rust to C++
-	int totalCount[2] = { 0 };
-	// TODO: Check whether stack count is no more than 2
-	for (int i = 0; i < battle->stacks.size(); ++i)
+	// Run Gear program to simulate battle, polling so the server stays responsive
+	bool simulated = false;
+	try
 	{
-		if (battle->stacks[i])
+		if (auto ticket = submit_battle_onchain(rbattle))
 		{
-			// battle->stacks[i]->name = rbattle.stacks[i].name;
-			// battle->stacks[i]->level = rbattle.stacks[i].level;
-			battle->stacks[i]->health.setCount(rbattle.stacks[i].count);
-			totalCount[i] = rbattle.stacks[i].count;
+			while (!simulated && lobby->state != EServerState::SHUTDOWN)
+			{
+				boost::this_thread::sleep(boost::posix_time::milliseconds(50));
+				simulated = poll_battle(ticket, rbattle);
+			}
 		}
 	}
-	int victoriusSide = totalCount[1] > totalCount[0] ? 1 : 0;
-	setBattleResult(BattleResult::EResult::NORMAL, victoriusSide);
-	if (lobby->state != EServerState::SHUTDOWN)
+	catch (const rust::Error & e)
 	{
-		endBattle(battle->tile, battle->battleGetFightingHero(0), battle->battleGetFightingHero(1));
+		logGlobal->warn("Battle isn't simulated on chain: %s", e.what());
 	}
-	// No more to do here, the battle is over.
-	return;
 
-	// Below is the original code, which is not used anymore.
+	if (simulated)
+	{
+		// Copy battle info from Rust to C++
+		int totalCount[2] = { 0 };
+		// TODO: Check whether stack count is no more than 2
+		for (int i = 0; i < battle->stacks.size(); ++i)
+		{
+			if (battle->stacks[i])
+			{
+				// battle->stacks[i]->name = rbattle.stacks[i].name;
+				// battle->stacks[i]->level = rbattle.stacks[i].level;
+				battle->stacks[i]->health.setCount(rbattle.stacks[i].count);
+				totalCount[i] = rbattle.stacks[i].count;
+			}
+		}
+		int victoriusSide = totalCount[1] > totalCount[0] ? 1 : 0;
+		setBattleResult(BattleResult::EResult::NORMAL, victoriusSide);
+		if (lobby->state != EServerState::SHUTDOWN)
+		{
+			endBattle(battle->tile, battle->battleGetFightingHero(0), battle->battleGetFightingHero(1));
+		}
+		return;
+	}
+	if (lobby->state == EServerState::SHUTDOWN)
+		return;
+	logGlobal->info("Resolving the battle locally");
 
 	//TODO: pre-tactic stuff, call scripts etc.
 
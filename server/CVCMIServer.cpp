@@ -58,6 +58,7 @@
 #include <boost/uuid/uuid_generators.hpp>
 
 #include "../lib/CGameState.h"
+#include "rusty_bridge/lib.h"
 
 template<typename T> class CApplyOnServer;
 
@@ -1124,6 +1125,9 @@ int main(int argc, const char * argv[])
 #if VCMI_ANDROID_DUAL_PROCESS
 	CAndroidVMHelper envHelper;
 	envHelper.callStaticVoidMethod(CAndroidVMHelper::NATIVE_METHODS_DEFAULT_CLASS, "killServer");
+#endif
+#ifndef SINGLE_PROCESS_APP
+	shutdown_connection();
 #endif
 	logConfig.deconfigure();
 	vstd::clear_pointer(VLC);
@@ -24,6 +24,10 @@
 
 #include "../lib/filesystem/FileInfo.h"
 #include "../lib/VCMIDirs.h"
+#include "../lib/GameConstants.h"
+#include "../lib/CModHandler.h"
+#include "../lib/CPlayerState.h"
+#include "../lib/VCMI_Lib.h"
 #include "rusty_bridge/lib.h"
 
 void ApplyGhNetPackVisitor::visitSaveGame(SaveGame & pack)
@@ -36,7 +40,35 @@ void ApplyGhNetPackVisitor::visitSaveGame(SaveGame & pack)
 
 	const std::string vcgmPath = VCMIDirs::get().userDataPath().string() + '/' + stem + ".vcgm1";
 	const std::string vsgmPath = VCMIDirs::get().userDataPath().string() + '/' + stem + ".vsgm1";
-	save_files_onchain(vcgmPath, vsgmPath);
+	try
+	{
+		RSaveMetadata metadata;
+		metadata.map_name = gs.map->name;
+		metadata.map_width = gs.map->width;
+		metadata.map_height = gs.map->height;
+		metadata.two_levels = gs.map->twoLevel;
+		metadata.day = gs.day;
+		for(const auto & player : gs.players)
+		{
+			RSavePlayer rplayer;
+			rplayer.color = player.first.getStr();
+			rplayer.is_human = player.second.human;
+			metadata.players.push_back(rplayer);
+		}
+		metadata.vcmi_version = GameConstants::VCMI_VERSION;
+		for(const auto & mod : VLC->modh->getActiveMods())
+			metadata.mods.push_back(mod);
+		metadata.uncompressed_size = 0;
+		if(pack.replaceOnChain.empty())
+			save_files_onchain(vcgmPath, vsgmPath, metadata);
+		else
+			replace_save_onchain(pack.replaceOnChain, vcgmPath, vsgmPath, metadata);
+	}
+	catch(const rust::Error & e)
+	{
+		logGlobal->error("Can't save %s on chain: %s", stem, e.what());
+		gh.sendMessageTo(pack.c, "Game isn't saved on chain: " + std::string(error_message(e.what())));
+	}
 }
 
 void ApplyGhNetPackVisitor::visitEndTurn(EndTurn & pack)